@@ -0,0 +1,2 @@
+-- Drop the job_queue table if it exists
+DROP TABLE IF EXISTS public.job_queue;
@@ -0,0 +1,20 @@
+-- Create job_queue table if it doesn't exist
+CREATE TABLE IF NOT EXISTS public.job_queue (
+    job_id VARCHAR(255) NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 3,
+    locked_by VARCHAR(255),
+    locked_until TIMESTAMP WITHOUT TIME ZONE,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT job_queue_pkey PRIMARY KEY (job_id),
+    CONSTRAINT job_queue_job_id_fkey FOREIGN KEY (job_id)
+        REFERENCES public.job_requests (job_id) ON DELETE CASCADE
+);
+
+-- Claims scan for unleased or expired rows in insertion order
+CREATE INDEX IF NOT EXISTS job_queue_claim_idx
+    ON public.job_queue (locked_until, created_at);
+
+-- Set the owner of the job_queue table
+ALTER TABLE IF EXISTS public.job_queue
+    OWNER TO postgres;
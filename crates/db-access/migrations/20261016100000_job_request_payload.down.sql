@@ -0,0 +1,3 @@
+-- Drop the request column from job_requests
+ALTER TABLE IF EXISTS public.job_requests
+    DROP COLUMN IF EXISTS request;
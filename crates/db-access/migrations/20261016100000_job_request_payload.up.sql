@@ -0,0 +1,4 @@
+-- Keep the full request alongside each job so it can be echoed back and re-run.
+-- The queue reads the request of a job from here.
+ALTER TABLE IF EXISTS public.job_requests
+    ADD COLUMN IF NOT EXISTS request JSONB;
@@ -0,0 +1,27 @@
+-- Drop the lifecycle triggers and their functions
+DROP TRIGGER IF EXISTS job_requests_status_history ON public.job_requests;
+DROP TRIGGER IF EXISTS job_requests_status_transition ON public.job_requests;
+DROP FUNCTION IF EXISTS public.record_job_status_history();
+DROP FUNCTION IF EXISTS public.enforce_job_status_transition();
+
+-- Drop the lifecycle tables if they exist
+DROP TABLE IF EXISTS public.job_status_history;
+DROP TABLE IF EXISTS public.job_status_transitions;
+
+-- Map lifecycle states back onto the original three
+UPDATE public.job_requests
+SET status = CASE
+    WHEN status IN ('CallbackConfirmed') THEN 'Completed'
+    WHEN status IN ('Cancelled') THEN 'Failed'
+    WHEN status IN ('Queued', 'FetchingData', 'Computing', 'Computed', 'CallbackSubmitted')
+        THEN 'Pending'
+    ELSE status
+END;
+
+ALTER TABLE IF EXISTS public.job_requests
+    DROP CONSTRAINT IF EXISTS job_requests_status_check;
+
+ALTER TABLE IF EXISTS public.job_requests
+    ADD CONSTRAINT job_requests_status_check CHECK (
+        status::TEXT = ANY (ARRAY['Completed'::TEXT, 'Pending'::TEXT, 'Failed'::TEXT])
+    );
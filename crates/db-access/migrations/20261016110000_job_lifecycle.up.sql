@@ -0,0 +1,121 @@
+-- Allow the full job lifecycle in job_requests.status
+ALTER TABLE IF EXISTS public.job_requests
+    DROP CONSTRAINT IF EXISTS job_requests_status_check;
+
+ALTER TABLE IF EXISTS public.job_requests
+    ADD CONSTRAINT job_requests_status_check CHECK (
+        status::TEXT = ANY (ARRAY[
+            'Pending'::TEXT,
+            'Queued'::TEXT,
+            'FetchingData'::TEXT,
+            'Computing'::TEXT,
+            'Computed'::TEXT,
+            'CallbackSubmitted'::TEXT,
+            'CallbackConfirmed'::TEXT,
+            'Completed'::TEXT,
+            'Failed'::TEXT,
+            'Cancelled'::TEXT
+        ])
+    );
+
+-- Create job_status_transitions table listing every allowed status change
+CREATE TABLE IF NOT EXISTS public.job_status_transitions (
+    from_status VARCHAR(20) NOT NULL,
+    to_status VARCHAR(20) NOT NULL,
+    CONSTRAINT job_status_transitions_pkey PRIMARY KEY (from_status, to_status)
+);
+
+INSERT INTO public.job_status_transitions (from_status, to_status) VALUES
+    -- Jobs accepted before the lifecycle existed
+    ('Pending', 'Queued'),
+    ('Pending', 'FetchingData'),
+    ('Pending', 'Failed'),
+    ('Pending', 'Cancelled'),
+    ('Queued', 'FetchingData'),
+    ('Queued', 'Failed'),
+    ('Queued', 'Cancelled'),
+    ('FetchingData', 'Computing'),
+    ('FetchingData', 'Failed'),
+    ('FetchingData', 'Cancelled'),
+    -- A job reclaimed after a crash starts over from fetching
+    ('Computing', 'FetchingData'),
+    ('Computing', 'Computed'),
+    ('Computing', 'Failed'),
+    ('Computing', 'Cancelled'),
+    ('Computed', 'FetchingData'),
+    ('Computed', 'CallbackSubmitted'),
+    ('Computed', 'Failed'),
+    ('Computed', 'Cancelled'),
+    ('CallbackSubmitted', 'CallbackConfirmed'),
+    ('CallbackSubmitted', 'Completed'),
+    ('CallbackSubmitted', 'Failed'),
+    ('CallbackConfirmed', 'Completed'),
+    ('CallbackConfirmed', 'Failed'),
+    -- Retries
+    ('Failed', 'Queued'),
+    ('Cancelled', 'Queued')
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE IF EXISTS public.job_status_transitions
+    OWNER TO postgres;
+
+-- Create job_status_history table with one row per status change
+CREATE TABLE IF NOT EXISTS public.job_status_history (
+    id BIGSERIAL PRIMARY KEY,
+    job_id VARCHAR(255) NOT NULL,
+    from_status VARCHAR(20),
+    to_status VARCHAR(20) NOT NULL,
+    changed_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT CLOCK_TIMESTAMP(),
+    CONSTRAINT job_status_history_job_id_fkey FOREIGN KEY (job_id)
+        REFERENCES public.job_requests (job_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS job_status_history_job_id_idx
+    ON public.job_status_history (job_id, id);
+
+ALTER TABLE IF EXISTS public.job_status_history
+    OWNER TO postgres;
+
+-- Reject status changes that are not listed in job_status_transitions
+CREATE OR REPLACE FUNCTION public.enforce_job_status_transition()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.status IS DISTINCT FROM OLD.status AND NOT EXISTS (
+        SELECT 1
+        FROM public.job_status_transitions
+        WHERE from_status = OLD.status AND to_status = NEW.status
+    ) THEN
+        RAISE EXCEPTION 'Invalid job status transition from % to % for job %',
+            OLD.status, NEW.status, OLD.job_id
+            USING ERRCODE = 'check_violation',
+                  CONSTRAINT = 'job_requests_status_transition';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS job_requests_status_transition ON public.job_requests;
+CREATE TRIGGER job_requests_status_transition
+    BEFORE UPDATE OF status ON public.job_requests
+    FOR EACH ROW EXECUTE FUNCTION public.enforce_job_status_transition();
+
+-- Record every status a job enters, including the initial one
+CREATE OR REPLACE FUNCTION public.record_job_status_history()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.status IS DISTINCT FROM OLD.status THEN
+        INSERT INTO public.job_status_history (job_id, from_status, to_status)
+        VALUES (
+            NEW.job_id,
+            CASE WHEN TG_OP = 'UPDATE' THEN OLD.status END,
+            NEW.status
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS job_requests_status_history ON public.job_requests;
+CREATE TRIGGER job_requests_status_history
+    AFTER INSERT OR UPDATE OF status ON public.job_requests
+    FOR EACH ROW EXECUTE FUNCTION public.record_job_status_history();
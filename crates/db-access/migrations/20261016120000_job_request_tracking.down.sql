@@ -0,0 +1,8 @@
+-- Drop the updated_at trigger and its function
+DROP TRIGGER IF EXISTS job_requests_touch ON public.job_requests;
+DROP FUNCTION IF EXISTS public.touch_job_request();
+
+-- Drop the tracking columns
+ALTER TABLE IF EXISTS public.job_requests
+    DROP COLUMN IF EXISTS callback_tx_hash,
+    DROP COLUMN IF EXISTS updated_at;
@@ -0,0 +1,24 @@
+-- Track when a job last changed and the hash of its Starknet callback
+ALTER TABLE IF EXISTS public.job_requests
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP WITHOUT TIME ZONE,
+    ADD COLUMN IF NOT EXISTS callback_tx_hash VARCHAR(66);
+
+UPDATE public.job_requests SET updated_at = created_at WHERE updated_at IS NULL;
+
+ALTER TABLE IF EXISTS public.job_requests
+    ALTER COLUMN updated_at SET DEFAULT CURRENT_TIMESTAMP,
+    ALTER COLUMN updated_at SET NOT NULL;
+
+-- Keep updated_at current on every change
+CREATE OR REPLACE FUNCTION public.touch_job_request()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = CURRENT_TIMESTAMP;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS job_requests_touch ON public.job_requests;
+CREATE TRIGGER job_requests_touch
+    BEFORE UPDATE ON public.job_requests
+    FOR EACH ROW EXECUTE FUNCTION public.touch_job_request();
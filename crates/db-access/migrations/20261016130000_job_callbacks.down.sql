@@ -0,0 +1,7 @@
+-- Allow finishing a job straight after submitting its callback again
+INSERT INTO public.job_status_transitions (from_status, to_status)
+VALUES ('CallbackSubmitted', 'Completed')
+ON CONFLICT DO NOTHING;
+
+-- Drop the job_callbacks table if it exists
+DROP TABLE IF EXISTS public.job_callbacks;
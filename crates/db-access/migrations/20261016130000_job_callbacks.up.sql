@@ -0,0 +1,37 @@
+-- Create job_callbacks table with one row per callback transaction sent for a job
+CREATE TABLE IF NOT EXISTS public.job_callbacks (
+    id BIGSERIAL PRIMARY KEY,
+    job_id VARCHAR(255) NOT NULL,
+    attempt INTEGER NOT NULL,
+    tx_hash VARCHAR(66) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'Submitted',
+    revert_reason TEXT,
+    submitted_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at TIMESTAMP WITHOUT TIME ZONE,
+    CONSTRAINT job_callbacks_job_id_fkey FOREIGN KEY (job_id)
+        REFERENCES public.job_requests (job_id) ON DELETE CASCADE,
+    CONSTRAINT job_callbacks_job_id_attempt_key UNIQUE (job_id, attempt),
+    CONSTRAINT job_callbacks_tx_hash_key UNIQUE (tx_hash),
+    CONSTRAINT job_callbacks_status_check CHECK (
+        status::TEXT = ANY (ARRAY[
+            'Submitted'::TEXT,
+            'Accepted'::TEXT,
+            'Reverted'::TEXT,
+            'Rejected'::TEXT
+        ])
+    )
+);
+
+ALTER TABLE IF EXISTS public.job_callbacks
+    OWNER TO postgres;
+
+-- Backfill the transactions already recorded on jobs
+INSERT INTO public.job_callbacks (job_id, attempt, tx_hash, submitted_at)
+SELECT job_id, 1, callback_tx_hash, updated_at
+FROM public.job_requests
+WHERE callback_tx_hash IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+-- A job is only finished once its callback transaction is confirmed
+DELETE FROM public.job_status_transitions
+WHERE from_status = 'CallbackSubmitted' AND to_status = 'Completed';
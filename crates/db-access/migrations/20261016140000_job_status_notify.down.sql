@@ -0,0 +1,15 @@
+-- Record status changes without announcing them
+CREATE OR REPLACE FUNCTION public.record_job_status_history()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.status IS DISTINCT FROM OLD.status THEN
+        INSERT INTO public.job_status_history (job_id, from_status, to_status)
+        VALUES (
+            NEW.job_id,
+            CASE WHEN TG_OP = 'UPDATE' THEN OLD.status END,
+            NEW.status
+        );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,21 @@
+-- Announce every recorded status change on the job_status_events channel,
+-- with the job_status_history id as payload
+CREATE OR REPLACE FUNCTION public.record_job_status_history()
+RETURNS TRIGGER AS $$
+DECLARE
+    history_id BIGINT;
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.status IS DISTINCT FROM OLD.status THEN
+        INSERT INTO public.job_status_history (job_id, from_status, to_status)
+        VALUES (
+            NEW.job_id,
+            CASE WHEN TG_OP = 'UPDATE' THEN OLD.status END,
+            NEW.status
+        )
+        RETURNING id INTO history_id;
+
+        PERFORM pg_notify('job_status_events', history_id::TEXT);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
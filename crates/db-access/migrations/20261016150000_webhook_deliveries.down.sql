@@ -0,0 +1,2 @@
+-- Drop the webhook_deliveries table if it exists
+DROP TABLE IF EXISTS public.webhook_deliveries;
@@ -0,0 +1,24 @@
+-- Create webhook_deliveries table logging every webhook sent for a job
+CREATE TABLE IF NOT EXISTS public.webhook_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    job_id VARCHAR(255) NOT NULL,
+    url TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'Pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_status_code INTEGER,
+    last_error TEXT,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TIMESTAMP WITHOUT TIME ZONE,
+    CONSTRAINT webhook_deliveries_job_id_fkey FOREIGN KEY (job_id)
+        REFERENCES public.job_requests (job_id) ON DELETE CASCADE,
+    CONSTRAINT webhook_deliveries_status_check CHECK (
+        status::TEXT = ANY (ARRAY['Pending'::TEXT, 'Delivered'::TEXT, 'Failed'::TEXT])
+    )
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_job_id_idx
+    ON public.webhook_deliveries (job_id, id);
+
+ALTER TABLE IF EXISTS public.webhook_deliveries
+    OWNER TO postgres;
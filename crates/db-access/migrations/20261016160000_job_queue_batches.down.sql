@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS public.job_queue_batch_id_idx;
+
+ALTER TABLE public.job_queue
+    DROP COLUMN IF EXISTS batch_id;
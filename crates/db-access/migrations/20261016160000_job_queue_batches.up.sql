@@ -0,0 +1,8 @@
+-- Jobs submitted together through /pricing_data/batch share a batch_id so the
+-- worker can claim them together and fetch their block headers once
+ALTER TABLE public.job_queue
+    ADD COLUMN IF NOT EXISTS batch_id VARCHAR(36);
+
+CREATE INDEX IF NOT EXISTS job_queue_batch_id_idx
+    ON public.job_queue (batch_id)
+    WHERE batch_id IS NOT NULL;
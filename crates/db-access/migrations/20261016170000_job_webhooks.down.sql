@@ -0,0 +1,13 @@
+-- Put the first subscribed URL of each job back into its stored request
+UPDATE public.job_requests
+SET request = jsonb_set(request, '{callback_url}', to_jsonb(first.url))
+FROM (
+    SELECT DISTINCT ON (job_id) job_id, url
+    FROM public.job_webhooks
+    ORDER BY job_id, id
+) AS first
+WHERE first.job_id = job_requests.job_id
+  AND job_requests.request IS NOT NULL;
+
+-- Drop the job_webhooks table if it exists
+DROP TABLE IF EXISTS public.job_webhooks;
@@ -0,0 +1,26 @@
+-- Create job_webhooks table with one row per URL subscribed to a job's webhooks.
+-- Identical requests share a job, so every submitter's callback_url is kept.
+CREATE TABLE IF NOT EXISTS public.job_webhooks (
+    id BIGSERIAL PRIMARY KEY,
+    job_id VARCHAR(255) NOT NULL,
+    url TEXT NOT NULL,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT job_webhooks_job_id_fkey FOREIGN KEY (job_id)
+        REFERENCES public.job_requests (job_id) ON DELETE CASCADE,
+    CONSTRAINT job_webhooks_job_id_url_key UNIQUE (job_id, url)
+);
+
+ALTER TABLE IF EXISTS public.job_webhooks
+    OWNER TO postgres;
+
+-- Move the callback_url stored with each request into the table, so stored
+-- requests no longer carry it
+INSERT INTO public.job_webhooks (job_id, url)
+SELECT job_id, request->>'callback_url'
+FROM public.job_requests
+WHERE jsonb_typeof(request->'callback_url') = 'string'
+ON CONFLICT DO NOTHING;
+
+UPDATE public.job_requests
+SET request = request - 'callback_url'
+WHERE request ? 'callback_url';
@@ -2,7 +2,7 @@ use eth_rlp_types::BlockHeader as EthBlockHeader;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(sqlx::FromRow, Debug)]
+#[derive(sqlx::FromRow, Debug, Clone)]
 pub struct BlockHeader {
     pub block_hash: Option<String>,
     pub number: i64,
@@ -44,24 +44,86 @@ pub struct ApiKey {
     pub name: Option<String>,
 }
 
-#[derive(sqlx::Type, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Lifecycle state of a job.
+///
+/// Allowed transitions live in the `job_status_transitions` table and are
+/// enforced by a trigger on `job_requests`, so every writer is held to them.
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[sqlx(type_name = "TEXT")]
 pub enum JobStatus {
+    /// Accepted before the lifecycle states existed. Not assigned anymore.
     Pending,
+    Queued,
+    FetchingData,
+    Computing,
+    /// Results are stored but not yet sent to the vault.
+    Computed,
+    CallbackSubmitted,
+    CallbackConfirmed,
     Completed,
     Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Every state a job can still move on from on its own.
+    pub const IN_PROGRESS: [Self; 7] = [
+        Self::Pending,
+        Self::Queued,
+        Self::FetchingData,
+        Self::Computing,
+        Self::Computed,
+        Self::CallbackSubmitted,
+        Self::CallbackConfirmed,
+    ];
+
+    pub fn is_in_progress(&self) -> bool {
+        Self::IN_PROGRESS.contains(self)
+    }
+
+    /// `true` for states a job can be retried from.
+    pub const fn is_retryable(&self) -> bool {
+        matches!(self, Self::Failed | Self::Cancelled)
+    }
 }
 
 impl fmt::Display for JobStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Pending => write!(f, "Pending"),
+            Self::Queued => write!(f, "Queued"),
+            Self::FetchingData => write!(f, "FetchingData"),
+            Self::Computing => write!(f, "Computing"),
+            Self::Computed => write!(f, "Computed"),
+            Self::CallbackSubmitted => write!(f, "CallbackSubmitted"),
+            Self::CallbackConfirmed => write!(f, "CallbackConfirmed"),
             Self::Completed => write!(f, "Completed"),
             Self::Failed => write!(f, "Failed"),
+            Self::Cancelled => write!(f, "Cancelled"),
         }
     }
 }
 
+/// One entry of `job_status_history`. `from_status` is `None` for the status a
+/// job was created with.
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobStatusChange {
+    pub from_status: Option<JobStatus>,
+    pub to_status: JobStatus,
+    pub changed_at: chrono::NaiveDateTime,
+}
+
+/// A `job_status_history` entry together with the job it belongs to, as
+/// announced on the `job_status_events` channel.
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq)]
+pub struct JobStatusEvent {
+    pub id: i64,
+    pub job_id: String,
+    pub from_status: Option<JobStatus>,
+    pub to_status: JobStatus,
+    pub changed_at: chrono::NaiveDateTime,
+}
+
 // impl FromStr for JobStatus {
 //     type Err = ();
 
@@ -80,7 +142,100 @@ pub struct JobRequest {
     pub job_id: String,
     pub status: JobStatus,
     pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
     pub result: Option<serde_json::Value>,
+    /// The request the job was created from. `None` for jobs created before
+    /// requests were persisted.
+    pub request: Option<serde_json::Value>,
+    /// Hash of the latest Starknet transaction delivering the result to the
+    /// vault. Every attempt is kept in `job_callbacks`.
+    pub callback_tx_hash: Option<String>,
+}
+
+/// Outcome of a callback transaction as tracked in `job_callbacks`.
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[sqlx(type_name = "TEXT")]
+pub enum CallbackTxStatus {
+    Submitted,
+    Accepted,
+    Reverted,
+    Rejected,
+}
+
+impl fmt::Display for CallbackTxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Submitted => write!(f, "Submitted"),
+            Self::Accepted => write!(f, "Accepted"),
+            Self::Reverted => write!(f, "Reverted"),
+            Self::Rejected => write!(f, "Rejected"),
+        }
+    }
+}
+
+/// One callback transaction sent for a job. A job gets a new row for every
+/// resubmission; `attempt` counts from 1.
+#[derive(sqlx::FromRow, Debug)]
+pub struct JobCallback {
+    pub job_id: String,
+    pub attempt: i32,
+    pub tx_hash: String,
+    pub status: CallbackTxStatus,
+    pub revert_reason: Option<String>,
+    pub submitted_at: chrono::NaiveDateTime,
+    pub resolved_at: Option<chrono::NaiveDateTime>,
+}
+
+/// State of a webhook delivery as tracked in `webhook_deliveries`.
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[sqlx(type_name = "TEXT")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl fmt::Display for WebhookDeliveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pending => write!(f, "Pending"),
+            Self::Delivered => write!(f, "Delivered"),
+            Self::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+/// One webhook notification for a job. Retries of the same notification
+/// update the row; a replay creates a new one.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub job_id: String,
+    pub url: String,
+    pub payload: serde_json::Value,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub delivered_at: Option<chrono::NaiveDateTime>,
+}
+
+/// A job waiting in (or leased from) the durable `job_queue`.
+///
+/// `request` is read from the job row so the job can be rebuilt by any
+/// worker, including one started after a crash.
+#[derive(sqlx::FromRow, Debug)]
+pub struct QueuedJob {
+    pub job_id: String,
+    pub request: Option<serde_json::Value>,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub locked_by: Option<String>,
+    pub locked_until: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+    /// Set on jobs submitted together through `/pricing_data/batch`.
+    pub batch_id: Option<String>,
 }
 
 #[derive(Debug, sqlx::FromRow)]
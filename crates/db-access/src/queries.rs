@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use crate::models::{temp_to_block_header, JobRequest, JobStatus};
+use crate::models::{
+    temp_to_block_header, CallbackTxStatus, JobCallback, JobRequest, JobStatus, JobStatusChange,
+    JobStatusEvent, QueuedJob, WebhookDelivery, WebhookDeliveryStatus,
+};
 use crate::models::{
     BlockHeader as DbBlockHeader, BlockHeaderSubset, TempBlockHeader, Transaction,
 };
@@ -241,6 +244,370 @@ pub async fn create_job_request(
     Ok(())
 }
 
+/// Registers a new job with its request and pushes it onto the durable queue in
+/// one transaction, so a job row never exists without the data needed to run it.
+///
+/// `callback_url` is subscribed to the job's webhooks in the same transaction,
+/// before any worker can pick the job up.
+pub async fn create_queued_job_request(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    status: JobStatus,
+    request: &serde_json::Value,
+    callback_url: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.db_connection().pool.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO job_requests (job_id, status, request) VALUES ($1, $2, $3)",
+        job_id,
+        status.to_string(),
+        request
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if let Some(url) = callback_url {
+        sqlx::query!(
+            "INSERT INTO job_webhooks (job_id, url) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            job_id,
+            url
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query!("INSERT INTO job_queue (job_id) VALUES ($1)", job_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+/// Registers several new jobs with their requests and queues them as one batch,
+/// all in one transaction, together with the `(job_id, url)` webhook
+/// subscriptions for them.
+pub async fn create_queued_job_batch(
+    db: Arc<OffchainProcessorDbConnection>,
+    batch_id: &str,
+    status: JobStatus,
+    jobs: &[(String, serde_json::Value)],
+    webhooks: &[(String, String)],
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.db_connection().pool.begin().await?;
+
+    for (job_id, request) in jobs {
+        sqlx::query!(
+            "INSERT INTO job_requests (job_id, status, request) VALUES ($1, $2, $3)",
+            job_id,
+            status.to_string(),
+            request
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO job_queue (job_id, batch_id) VALUES ($1, $2)",
+            job_id,
+            batch_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for (job_id, url) in webhooks {
+        sqlx::query!(
+            "INSERT INTO job_webhooks (job_id, url) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            job_id,
+            url
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Resets an existing job to `status` and (re)queues it with a fresh attempt budget.
+///
+/// When `request` is `None` the job is re-run from the request stored with it.
+pub async fn requeue_job_request(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    status: JobStatus,
+    request: Option<&serde_json::Value>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.db_connection().pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE job_requests
+        SET status = $1,
+            result = NULL,
+            request = COALESCE($2, request),
+            callback_tx_hash = NULL
+        WHERE job_id = $3
+        "#,
+        status.to_string(),
+        request,
+        job_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO job_queue (job_id)
+        VALUES ($1)
+        ON CONFLICT (job_id) DO UPDATE
+        SET attempts = 0,
+            locked_by = NULL,
+            locked_until = NULL,
+            batch_id = NULL,
+            created_at = CURRENT_TIMESTAMP
+        "#,
+        job_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+/// Leases the oldest claimable job to `worker_id` for `lease_secs` seconds.
+///
+/// A job is claimable when it has never been leased or its lease has expired
+/// (e.g. the previous worker crashed) and it still has attempts left.
+/// `SKIP LOCKED` lets several workers poll the queue concurrently.
+pub async fn claim_next_job(
+    db: Arc<OffchainProcessorDbConnection>,
+    worker_id: &str,
+    lease_secs: f64,
+) -> Result<Option<QueuedJob>, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+        WITH claimed AS (
+            UPDATE job_queue
+            SET locked_by = $1,
+                locked_until = CURRENT_TIMESTAMP + make_interval(secs => $2),
+                attempts = attempts + 1
+            WHERE job_id = (
+                SELECT job_id
+                FROM job_queue
+                WHERE (locked_until IS NULL OR locked_until < CURRENT_TIMESTAMP)
+                  AND attempts < max_attempts
+                ORDER BY created_at ASC
+                FOR UPDATE SKIP LOCKED
+                LIMIT 1
+            )
+            RETURNING job_id, attempts, max_attempts, locked_by, locked_until, created_at,
+                      batch_id
+        )
+        SELECT
+            claimed.job_id AS "job_id!",
+            job_requests.request,
+            claimed.attempts AS "attempts!",
+            claimed.max_attempts AS "max_attempts!",
+            claimed.locked_by,
+            claimed.locked_until,
+            claimed.created_at AS "created_at!",
+            claimed.batch_id
+        FROM claimed
+        JOIN job_requests ON job_requests.job_id = claimed.job_id
+        "#,
+        worker_id,
+        lease_secs
+    )
+    .fetch_optional(&db.db_connection().pool)
+    .await
+}
+
+/// Leases up to `limit` other claimable jobs of `batch_id` to `worker_id`,
+/// oldest first, so a batch is processed by the worker that claimed its first
+/// job as far as it has room for them.
+pub async fn claim_batch_jobs(
+    db: Arc<OffchainProcessorDbConnection>,
+    batch_id: &str,
+    worker_id: &str,
+    lease_secs: f64,
+    limit: i64,
+) -> Result<Vec<QueuedJob>, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+        WITH claimed AS (
+            UPDATE job_queue
+            SET locked_by = $2,
+                locked_until = CURRENT_TIMESTAMP + make_interval(secs => $3),
+                attempts = attempts + 1
+            WHERE job_id IN (
+                SELECT job_id
+                FROM job_queue
+                WHERE batch_id = $1
+                  AND (locked_until IS NULL OR locked_until < CURRENT_TIMESTAMP)
+                  AND attempts < max_attempts
+                ORDER BY created_at ASC, job_id ASC
+                FOR UPDATE SKIP LOCKED
+                LIMIT $4
+            )
+            RETURNING job_id, attempts, max_attempts, locked_by, locked_until, created_at,
+                      batch_id
+        )
+        SELECT
+            claimed.job_id AS "job_id!",
+            job_requests.request,
+            claimed.attempts AS "attempts!",
+            claimed.max_attempts AS "max_attempts!",
+            claimed.locked_by,
+            claimed.locked_until,
+            claimed.created_at AS "created_at!",
+            claimed.batch_id
+        FROM claimed
+        JOIN job_requests ON job_requests.job_id = claimed.job_id
+        ORDER BY claimed.created_at ASC, claimed.job_id ASC
+        "#,
+        batch_id,
+        worker_id,
+        lease_secs,
+        limit
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// Pushes the lease of a job held by `worker_id` forward. Returns `false` if
+/// the lease was lost to another worker.
+pub async fn extend_job_lease(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    worker_id: &str,
+    lease_secs: f64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE job_queue
+        SET locked_until = CURRENT_TIMESTAMP + make_interval(secs => $3)
+        WHERE job_id = $1 AND locked_by = $2
+        "#,
+        job_id,
+        worker_id,
+        lease_secs
+    )
+    .execute(&db.db_connection().pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Removes a job held by `worker_id` from the queue once processing has
+/// reached a final outcome. Returns `false` if the lease was lost meanwhile,
+/// in which case the queue entry belongs to another worker and is kept.
+pub async fn complete_queued_job(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    worker_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM job_queue WHERE job_id = $1 AND locked_by = $2",
+        job_id,
+        worker_id
+    )
+    .execute(&db.db_connection().pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Marks jobs that ran out of attempts as `Failed` and drops them from the queue.
+///
+/// Returns the ids of the jobs that were failed.
+pub async fn fail_exhausted_jobs(
+    db: Arc<OffchainProcessorDbConnection>,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        WITH exhausted AS (
+            DELETE FROM job_queue
+            WHERE attempts >= max_attempts
+              AND (locked_until IS NULL OR locked_until < CURRENT_TIMESTAMP)
+            RETURNING job_id, attempts
+        )
+        UPDATE job_requests
+        SET status = $1,
+            result = jsonb_build_object(
+                'error',
+                'Job abandoned after ' || exhausted.attempts || ' attempts without finishing'
+            )
+        FROM exhausted
+        WHERE job_requests.job_id = exhausted.job_id
+          AND job_requests.status = ANY($2)
+        RETURNING job_requests.job_id
+        "#,
+        JobStatus::Failed.to_string(),
+        &status_names(&JobStatus::IN_PROGRESS)
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// Fails jobs that were still being prepared but have no queue entry, e.g. jobs
+/// accepted before the queue existed whose in-process task died with the
+/// server. Clients can then resubmit them instead of getting a conflict forever.
+pub async fn fail_orphaned_jobs(
+    db: Arc<OffchainProcessorDbConnection>,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE job_requests
+        SET status = $1,
+            result = jsonb_build_object(
+                'error', 'Job was interrupted before completion. Please resubmit.'
+            )
+        WHERE status = ANY($2)
+          AND NOT EXISTS (
+              SELECT 1 FROM job_queue WHERE job_queue.job_id = job_requests.job_id
+          )
+        RETURNING job_id
+        "#,
+        JobStatus::Failed.to_string(),
+        &status_names(&[
+            JobStatus::Pending,
+            JobStatus::Queued,
+            JobStatus::FetchingData,
+            JobStatus::Computing,
+            JobStatus::Computed,
+        ])
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// Cancels a job and drops it from the queue.
+///
+/// Fails with an invalid transition error (see [`is_invalid_status_transition`])
+/// once the job is past the point where it can be cancelled.
+pub async fn cancel_job_request(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.db_connection().pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE job_requests SET status = $1 WHERE job_id = $2",
+        JobStatus::Cancelled.to_string(),
+        job_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM job_queue WHERE job_id = $1", job_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
 pub async fn get_job_request(
     db: Arc<OffchainProcessorDbConnection>,
     job_id: &str,
@@ -252,7 +619,10 @@ pub async fn get_job_request(
             job_id, 
             status as "status: JobStatus", 
             created_at, 
-            result
+            updated_at,
+            result,
+            request,
+            callback_tx_hash
         FROM job_requests 
         WHERE job_id = $1
         "#,
@@ -284,6 +654,380 @@ pub async fn update_job_status(
     Ok(())
 }
 
+/// Moves a job to `status`, leaving its result untouched.
+pub async fn set_job_status(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    status: JobStatus,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE job_requests SET status = $1 WHERE job_id = $2",
+        status.to_string(),
+        job_id
+    )
+    .execute(&db.db_connection().pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a newly sent callback transaction as the job's next attempt and
+/// moves the job to `CallbackSubmitted`. Returns the attempt number.
+pub async fn record_callback_submission(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    tx_hash: &str,
+) -> Result<i32, sqlx::Error> {
+    let mut tx = db.db_connection().pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE job_requests SET status = $1, callback_tx_hash = $2 WHERE job_id = $3",
+        JobStatus::CallbackSubmitted.to_string(),
+        tx_hash,
+        job_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let attempt = sqlx::query_scalar!(
+        r#"
+        INSERT INTO job_callbacks (job_id, attempt, tx_hash)
+        SELECT $1, COALESCE(MAX(attempt), 0) + 1, $2
+        FROM job_callbacks
+        WHERE job_id = $1
+        RETURNING attempt
+        "#,
+        job_id,
+        tx_hash
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(attempt)
+}
+
+/// Stores the final outcome of a callback transaction.
+pub async fn resolve_callback(
+    db: Arc<OffchainProcessorDbConnection>,
+    tx_hash: &str,
+    status: CallbackTxStatus,
+    revert_reason: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE job_callbacks
+        SET status = $1, revert_reason = $2, resolved_at = CURRENT_TIMESTAMP
+        WHERE tx_hash = $3
+        "#,
+        status.to_string(),
+        revert_reason,
+        tx_hash
+    )
+    .execute(&db.db_connection().pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Stores the acceptance of a callback transaction and finishes the job in one
+/// transaction, so a job is never left `CallbackConfirmed` by a crash.
+pub async fn confirm_callback(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    tx_hash: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.db_connection().pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE job_callbacks
+        SET status = $1, resolved_at = CURRENT_TIMESTAMP
+        WHERE tx_hash = $2
+        "#,
+        CallbackTxStatus::Accepted.to_string(),
+        tx_hash
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for status in [JobStatus::CallbackConfirmed, JobStatus::Completed] {
+        sqlx::query!(
+            "UPDATE job_requests SET status = $1 WHERE job_id = $2",
+            status.to_string(),
+            job_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Every callback transaction sent for the job, oldest first.
+pub async fn get_job_callbacks(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+) -> Result<Vec<JobCallback>, sqlx::Error> {
+    sqlx::query_as!(
+        JobCallback,
+        r#"
+        SELECT
+            job_id,
+            attempt,
+            tx_hash,
+            status as "status: CallbackTxStatus",
+            revert_reason,
+            submitted_at,
+            resolved_at
+        FROM job_callbacks
+        WHERE job_id = $1
+        ORDER BY attempt ASC
+        "#,
+        job_id
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// Every status the job has been in, oldest first.
+pub async fn get_job_status_history(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+) -> Result<Vec<JobStatusChange>, sqlx::Error> {
+    sqlx::query_as!(
+        JobStatusChange,
+        r#"
+        SELECT
+            from_status as "from_status: JobStatus",
+            to_status as "to_status: JobStatus",
+            changed_at
+        FROM job_status_history
+        WHERE job_id = $1
+        ORDER BY id ASC
+        "#,
+        job_id
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// Subscribes `url` to the webhooks of a job. Returns `false` if it already
+/// was, in which case nothing changes.
+pub async fn add_job_webhook(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    url: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "INSERT INTO job_webhooks (job_id, url) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        job_id,
+        url
+    )
+    .execute(&db.db_connection().pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Every URL subscribed to the webhooks of a job, oldest first.
+pub async fn get_job_webhooks(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT url FROM job_webhooks WHERE job_id = $1 ORDER BY id ASC",
+        job_id
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// Logs a new webhook notification for a job, not yet attempted.
+pub async fn create_webhook_delivery(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    url: &str,
+    payload: &serde_json::Value,
+) -> Result<WebhookDelivery, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        INSERT INTO webhook_deliveries (job_id, url, payload)
+        VALUES ($1, $2, $3)
+        RETURNING
+            id,
+            job_id,
+            url,
+            payload,
+            status as "status: WebhookDeliveryStatus",
+            attempts,
+            last_status_code,
+            last_error,
+            created_at,
+            delivered_at
+        "#,
+        job_id,
+        url,
+        payload
+    )
+    .fetch_one(&db.db_connection().pool)
+    .await
+}
+
+/// Records the outcome of one attempt to send a webhook.
+pub async fn record_webhook_attempt(
+    db: Arc<OffchainProcessorDbConnection>,
+    id: i64,
+    status: WebhookDeliveryStatus,
+    status_code: Option<i32>,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = $1,
+            attempts = attempts + 1,
+            last_status_code = $2,
+            last_error = $3,
+            delivered_at = CASE WHEN $1 = 'Delivered' THEN CURRENT_TIMESTAMP END
+        WHERE id = $4
+        "#,
+        status.to_string(),
+        status_code,
+        error,
+        id
+    )
+    .execute(&db.db_connection().pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Every webhook notification sent for the job, oldest first.
+pub async fn get_webhook_deliveries(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        SELECT
+            id,
+            job_id,
+            url,
+            payload,
+            status as "status: WebhookDeliveryStatus",
+            attempts,
+            last_status_code,
+            last_error,
+            created_at,
+            delivered_at
+        FROM webhook_deliveries
+        WHERE job_id = $1
+        ORDER BY id ASC
+        "#,
+        job_id
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// Webhook notifications neither delivered nor given up on yet, oldest first.
+pub async fn get_pending_webhook_deliveries(
+    db: Arc<OffchainProcessorDbConnection>,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        SELECT
+            id,
+            job_id,
+            url,
+            payload,
+            status as "status: WebhookDeliveryStatus",
+            attempts,
+            last_status_code,
+            last_error,
+            created_at,
+            delivered_at
+        FROM webhook_deliveries
+        WHERE status = $1
+        ORDER BY id ASC
+        "#,
+        WebhookDeliveryStatus::Pending.to_string()
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// Postgres channel on which the id of every new `job_status_history` row is
+/// announced.
+pub const JOB_STATUS_EVENTS_CHANNEL: &str = "job_status_events";
+
+/// The status change with the given `job_status_history` id.
+pub async fn get_job_status_event(
+    db: Arc<OffchainProcessorDbConnection>,
+    id: i64,
+) -> Result<Option<JobStatusEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        JobStatusEvent,
+        r#"
+        SELECT
+            id,
+            job_id,
+            from_status as "from_status: JobStatus",
+            to_status as "to_status: JobStatus",
+            changed_at
+        FROM job_status_history
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(&db.db_connection().pool)
+    .await
+}
+
+/// Status changes of a job recorded after the `after_id` history entry,
+/// oldest first.
+pub async fn get_job_status_events(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    after_id: i64,
+) -> Result<Vec<JobStatusEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        JobStatusEvent,
+        r#"
+        SELECT
+            id,
+            job_id,
+            from_status as "from_status: JobStatus",
+            to_status as "to_status: JobStatus",
+            changed_at
+        FROM job_status_history
+        WHERE job_id = $1 AND id > $2
+        ORDER BY id ASC
+        "#,
+        job_id,
+        after_id
+    )
+    .fetch_all(&db.db_connection().pool)
+    .await
+}
+
+/// `true` if `error` is the database rejecting a status change that is not
+/// listed in `job_status_transitions`.
+pub fn is_invalid_status_transition(error: &sqlx::Error) -> bool {
+    matches!(
+        error,
+        sqlx::Error::Database(e) if e.constraint() == Some("job_requests_status_transition")
+    )
+}
+
+fn status_names(statuses: &[JobStatus]) -> Vec<String> {
+    statuses.iter().map(ToString::to_string).collect()
+}
+
 pub async fn get_block_hashes_by_block_range(
     db: Arc<IndexerDbConnection>,
     start_block: i64,
@@ -0,0 +1,49 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ndarray::Array1;
+use server::pricing_data::{rng::pricing_rng, simulation::PathModel};
+
+// A 30 day round: 720 hourly periods, with parameters close to a mainnet fit
+fn model() -> PathModel {
+    let sigma: f64 = 0.5;
+    let dt = 1.0 / 24.0;
+
+    PathModel {
+        mrj_params: vec![-3.928e-02, 0.98, 4.617e-02, 1e-3, 4e-2, 0.05],
+        mrj_start: -1.85,
+        season: Array1::zeros(720),
+        trend: 24.0,
+        trend_drift: 0.5f64.mul_add(-sigma.powi(2), 0.05 / 12.0),
+        dt,
+        shock_std: sigma * dt.sqrt(),
+    }
+}
+
+fn bench_simulation(c: &mut Criterion) {
+    let model = model();
+    let mut group = c.benchmark_group("reserve_price_paths");
+    group.sample_size(10);
+
+    for num_paths in [1_000, 15_000] {
+        group.bench_with_input(
+            BenchmarkId::new("materialized", num_paths),
+            &num_paths,
+            |b, &num_paths| {
+                b.iter(|| {
+                    model
+                        .simulate_materialized(num_paths, false, &mut pricing_rng(42))
+                        .unwrap()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("streamed", num_paths),
+            &num_paths,
+            |b, &num_paths| b.iter(|| model.simulate(num_paths, false, 42).unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_simulation);
+criterion_main!(benches);
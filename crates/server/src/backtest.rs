@@ -0,0 +1,508 @@
+use std::io::Write;
+
+use eyre::{eyre, Result};
+use serde::Serialize;
+use starknet_crypto::Felt;
+use tokio_util::sync::CancellationToken;
+
+use crate::handlers::get_pricing_data::pricing_headers;
+use crate::header_ranges::HeaderRanges;
+use crate::pricing_data::{
+    config::{BaseFeeInput, PricingConfig},
+    data_quality::DataQualityReport,
+    model::PricingModel,
+    twap::calculate_twap,
+};
+use crate::types::PitchLakeJobRequestParams;
+
+/// How a backtest lays out and prices its rounds.
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    /// Seconds from a round's start to its settlement.
+    pub vault_length: i64,
+    pub alpha: u128,
+    pub k: i128,
+    /// Start of the first round.
+    pub start: i64,
+    /// No round settles after this timestamp.
+    pub end: i64,
+    /// Seconds of history before a round start each value is computed from.
+    pub twap_lookback: i64,
+    pub cap_level_lookback: i64,
+    pub reserve_price_lookback: i64,
+    /// Base seed, offset by each round's start.
+    pub seed: u64,
+    pub program_id: Felt,
+}
+
+impl BacktestConfig {
+    /// Start of every round that settles by `end`.
+    pub fn round_starts(&self) -> Vec<i64> {
+        if self.vault_length <= 0 {
+            return Vec::new();
+        }
+        (0..)
+            .map(|round| self.start + round * self.vault_length)
+            .take_while(|start| start + self.vault_length <= self.end)
+            .collect()
+    }
+
+    /// Oldest and newest header timestamps the rounds read.
+    pub fn header_range(&self) -> (i64, i64) {
+        (self.start - self.max_lookback(), self.end)
+    }
+
+    pub fn max_lookback(&self) -> i64 {
+        self.twap_lookback
+            .max(self.cap_level_lookback)
+            .max(self.reserve_price_lookback)
+    }
+
+    fn round_seed(&self, start: i64) -> u64 {
+        self.seed.wrapping_add(start as u64)
+    }
+
+    // The request the vault would have made at `start`
+    fn params(&self, start: i64) -> PitchLakeJobRequestParams {
+        PitchLakeJobRequestParams {
+            twap: (start - self.twap_lookback, start),
+            cap_level: (start - self.cap_level_lookback, start),
+            reserve_price: (start - self.reserve_price_lookback, start),
+            alpha: self.alpha,
+            k: self.k,
+            seed: Some(self.round_seed(start)),
+            pricing_config: None,
+            program_id: Some(self.program_id),
+        }
+    }
+}
+
+/// One round priced at its start and settled at its end.
+///
+/// The strike is `(1 + k)` times the TWAP sent to the vault, as the vault
+/// sets it, and the option pays out on the TWAP over the round. The payoff
+/// is discounted like the reserve price, so the two are directly comparable.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BacktestRound {
+    pub round_start: i64,
+    pub round_end: i64,
+    pub twap: f64,
+    pub strike: f64,
+    pub cap_level: f64,
+    pub capped_price: f64,
+    pub reserve_price: f64,
+    pub reserve_price_std_error: f64,
+    pub settlement_twap: f64,
+    pub payoff: f64,
+    /// Reserve price less payoff: positive when the option was overpriced.
+    pub pricing_error: f64,
+    /// Simulated chance the option pays out at all.
+    pub predicted_exercise_probability: f64,
+    /// Simulated chance the payout is capped.
+    pub predicted_cap_hit_probability: f64,
+    pub exercised: bool,
+    pub cap_hit: bool,
+    /// Whether the payoff fell within the simulated 5th to 95th percentiles.
+    pub within_payoff_band: bool,
+    /// Running P&L of a vault selling one option per round at the reserve price.
+    pub cumulative_pnl: f64,
+}
+
+/// A round that could not be priced or settled, and why.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkippedRound {
+    pub round_start: i64,
+    pub error: String,
+}
+
+/// Aggregates over the priced rounds.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct BacktestSummary {
+    pub rounds: usize,
+    pub skipped: usize,
+    pub mean_pricing_error: f64,
+    pub mean_absolute_pricing_error: f64,
+    pub root_mean_squared_pricing_error: f64,
+    pub exercise_rate: f64,
+    pub predicted_exercise_rate: f64,
+    pub cap_hit_rate: f64,
+    pub predicted_cap_hit_rate: f64,
+    pub payoff_band_hit_rate: f64,
+    /// P&L of a vault selling one option per round at the reserve price.
+    pub total_pnl: f64,
+    pub worst_round_pnl: f64,
+    /// Largest fall of the cumulative P&L from a previous high.
+    pub max_drawdown: f64,
+}
+
+impl BacktestSummary {
+    pub fn new(rounds: &[BacktestRound], skipped: usize) -> Self {
+        if rounds.is_empty() {
+            return Self {
+                skipped,
+                ..Default::default()
+            };
+        }
+
+        let n = rounds.len() as f64;
+        let mean = |value: fn(&BacktestRound) -> f64| rounds.iter().map(value).sum::<f64>() / n;
+        let rate = |hit: fn(&BacktestRound) -> bool| {
+            rounds.iter().filter(|round| hit(round)).count() as f64 / n
+        };
+
+        let mut peak = 0.0f64;
+        let mut max_drawdown = 0.0f64;
+        for round in rounds {
+            peak = peak.max(round.cumulative_pnl);
+            max_drawdown = max_drawdown.max(peak - round.cumulative_pnl);
+        }
+
+        Self {
+            rounds: rounds.len(),
+            skipped,
+            mean_pricing_error: mean(|round| round.pricing_error),
+            mean_absolute_pricing_error: mean(|round| round.pricing_error.abs()),
+            root_mean_squared_pricing_error: mean(|round| round.pricing_error.powi(2)).sqrt(),
+            exercise_rate: rate(|round| round.exercised),
+            predicted_exercise_rate: mean(|round| round.predicted_exercise_probability),
+            cap_hit_rate: rate(|round| round.cap_hit),
+            predicted_cap_hit_rate: mean(|round| round.predicted_cap_hit_probability),
+            payoff_band_hit_rate: rate(|round| round.within_payoff_band),
+            total_pnl: rounds.last().map_or(0.0, |round| round.cumulative_pnl),
+            worst_round_pnl: rounds
+                .iter()
+                .map(|round| round.pricing_error)
+                .fold(f64::INFINITY, f64::min),
+            max_drawdown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BacktestReport {
+    pub summary: BacktestSummary,
+    pub rounds: Vec<BacktestRound>,
+    pub skipped: Vec<SkippedRound>,
+}
+
+impl BacktestReport {
+    pub fn write_json(&self, writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// One line per priced round. Skipped rounds and the summary are left out.
+    pub fn write_csv(&self, mut writer: impl Write) -> Result<()> {
+        writeln!(
+            writer,
+            "round_start,round_end,twap,strike,cap_level,capped_price,reserve_price,\
+             reserve_price_std_error,settlement_twap,payoff,pricing_error,\
+             predicted_exercise_probability,predicted_cap_hit_probability,exercised,cap_hit,\
+             within_payoff_band,cumulative_pnl"
+        )?;
+        for round in &self.rounds {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                round.round_start,
+                round.round_end,
+                round.twap,
+                round.strike,
+                round.cap_level,
+                round.capped_price,
+                round.reserve_price,
+                round.reserve_price_std_error,
+                round.settlement_twap,
+                round.payoff,
+                round.pricing_error,
+                round.predicted_exercise_probability,
+                round.predicted_cap_hit_probability,
+                round.exercised,
+                round.cap_hit,
+                round.within_payoff_band,
+                round.cumulative_pnl,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Prices every round of `config` from `headers` with `model` and settles
+/// it on the TWAP of the round. Rounds that fail are reported as skipped.
+pub async fn run_backtest(
+    headers: &HeaderRanges,
+    config: &BacktestConfig,
+    pricing_config: &PricingConfig,
+    model: &dyn PricingModel,
+) -> BacktestReport {
+    let mut rounds: Vec<BacktestRound> = Vec::new();
+    let mut skipped = Vec::new();
+
+    for round_start in config.round_starts() {
+        let cumulative_pnl = rounds.last().map_or(0.0, |round| round.cumulative_pnl);
+        match backtest_round(
+            headers,
+            config,
+            pricing_config,
+            model,
+            round_start,
+            cumulative_pnl,
+        )
+        .await
+        {
+            Ok(round) => {
+                tracing::info!(
+                    "Round {}: reserve price = {}, payoff = {}, pricing error = {}",
+                    round_start,
+                    round.reserve_price,
+                    round.payoff,
+                    round.pricing_error
+                );
+                rounds.push(round);
+            }
+            Err(e) => {
+                tracing::warn!("Skipping round {}: {:?}", round_start, e);
+                skipped.push(SkippedRound {
+                    round_start,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    BacktestReport {
+        summary: BacktestSummary::new(&rounds, skipped.len()),
+        rounds,
+        skipped,
+    }
+}
+
+async fn backtest_round(
+    headers: &HeaderRanges,
+    config: &BacktestConfig,
+    pricing_config: &PricingConfig,
+    model: &dyn PricingModel,
+    round_start: i64,
+    cumulative_pnl: f64,
+) -> Result<BacktestRound> {
+    let round_end = round_start + config.vault_length;
+    let params = config.params(round_start);
+    let not_covered = || eyre!("Headers do not cover the round");
+
+    let round_headers = pricing_headers(headers, &params).ok_or_else(not_covered)?;
+    // Rounds whose headers violate the data quality thresholds are skipped
+    // when `data_quality_action` is `fail`
+    DataQualityReport::new(&round_headers, &params).check(pricing_config)?;
+    let settlement_headers = headers
+        .get((round_start, round_end))
+        .ok_or_else(not_covered)?;
+
+    let seed = config.round_seed(round_start);
+    let output = model
+        .price(
+            round_headers,
+            &params,
+            seed,
+            pricing_config,
+            &CancellationToken::new(),
+        )
+        .await?;
+    // Vaults settle on the base fees as the blocks report them
+    let raw_config = PricingConfig {
+        base_fee_input: BaseFeeInput::Raw,
+        ..pricing_config.clone()
+    };
+    let settlement_twap = calculate_twap(settlement_headers, &raw_config).await?;
+
+    let strike = (1.0 + config.k as f64 / 10_000.0) * output.twap;
+    let capped_price = (1.0 + output.cap_level) * strike;
+    let discount = f64::exp(-pricing_config.risk_free_rate);
+    let payoff = discount * (settlement_twap.min(capped_price) - strike).max(0.0);
+
+    let estimate = output.reserve_price;
+    let diagnostics = estimate.diagnostics;
+    let pricing_error = estimate.reserve_price - payoff;
+
+    Ok(BacktestRound {
+        round_start,
+        round_end,
+        twap: output.twap,
+        strike,
+        cap_level: output.cap_level,
+        capped_price,
+        reserve_price: estimate.reserve_price,
+        reserve_price_std_error: diagnostics.std_error,
+        settlement_twap,
+        payoff,
+        pricing_error,
+        predicted_exercise_probability: 1.0 - diagnostics.zero_payoff_fraction,
+        predicted_cap_hit_probability: diagnostics.cap_hit_fraction,
+        exercised: settlement_twap > strike,
+        cap_hit: settlement_twap >= capped_price,
+        within_payoff_band: diagnostics.payoff_quantiles.p05 <= payoff
+            && payoff <= diagnostics.payoff_quantiles.p95,
+        cumulative_pnl: cumulative_pnl + pricing_error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing_data::model::{PricingFuture, PricingHeaders, PricingOutput};
+    use crate::pricing_data::reserve_price::{
+        PayoffQuantiles, ReservePriceDiagnostics, ReservePriceEstimate,
+    };
+    use db_access::models::BlockHeader;
+
+    const HOUR: i64 = 3600;
+
+    // Prices every round at a TWAP of 100, a 50% cap and a reserve price of 5
+    struct FixedModel;
+
+    impl PricingModel for FixedModel {
+        fn price<'a>(
+            &'a self,
+            _headers: PricingHeaders,
+            _params: &'a PitchLakeJobRequestParams,
+            _seed: u64,
+            _config: &'a PricingConfig,
+            _cancel: &'a CancellationToken,
+        ) -> PricingFuture<'a> {
+            Box::pin(async {
+                Ok(PricingOutput {
+                    twap: 100.0,
+                    cap_level: 0.5,
+                    volatility: None,
+                    reserve_price: ReservePriceEstimate {
+                        reserve_price: 5.0,
+                        diagnostics: ReservePriceDiagnostics {
+                            num_paths: 1,
+                            std_error: 0.1,
+                            confidence_interval_95: (4.8, 5.2),
+                            payoff_quantiles: PayoffQuantiles {
+                                p05: 0.0,
+                                p25: 0.0,
+                                p50: 2.0,
+                                p75: 8.0,
+                                p95: 20.0,
+                            },
+                            cap_hit_fraction: 0.1,
+                            zero_payoff_fraction: 0.4,
+                            control_variate_coefficient: None,
+                            calibration: None,
+                        },
+                        sensitivities: None,
+                        analytic: None,
+                    },
+                })
+            })
+        }
+    }
+
+    fn header(number: i64, timestamp: i64, base_fee: u64) -> BlockHeader {
+        BlockHeader {
+            block_hash: None,
+            number,
+            gas_limit: None,
+            gas_used: None,
+            base_fee_per_gas: Some(format!("{:#x}", base_fee)),
+            nonce: None,
+            transaction_root: None,
+            receipts_root: None,
+            state_root: None,
+            timestamp: Some(timestamp.to_string()),
+        }
+    }
+
+    fn config(start: i64, end: i64) -> BacktestConfig {
+        BacktestConfig {
+            vault_length: 10 * HOUR,
+            alpha: 5000,
+            k: 0,
+            start,
+            end,
+            twap_lookback: 10 * HOUR,
+            cap_level_lookback: 10 * HOUR,
+            reserve_price_lookback: 10 * HOUR,
+            seed: 0,
+            program_id: Felt::ONE,
+        }
+    }
+
+    #[test]
+    fn test_round_starts_stop_at_the_last_settled_round() {
+        let config = config(10 * HOUR, 45 * HOUR);
+
+        assert_eq!(config.round_starts(), vec![10 * HOUR, 20 * HOUR, 30 * HOUR]);
+        assert_eq!(config.header_range(), (0, 45 * HOUR));
+    }
+
+    #[tokio::test]
+    async fn test_rounds_are_settled_on_the_round_twap() {
+        // Base fee 100 before the first round, 110 during it and 200 during the second
+        let headers: Vec<_> = (0..=30)
+            .map(|hour| {
+                let base_fee = match hour {
+                    0..=9 => 100,
+                    10..=19 => 110,
+                    _ => 200,
+                };
+                header(hour, hour * HOUR, base_fee)
+            })
+            .collect();
+        let headers = HeaderRanges::from_headers(headers);
+        // The third round settles after the last header
+        let config = config(10 * HOUR, 40 * HOUR);
+
+        let report = run_backtest(&headers, &config, &PricingConfig::default(), &FixedModel).await;
+
+        let discount = f64::exp(-PricingConfig::default().risk_free_rate);
+        assert_eq!(report.rounds.len(), 2);
+        assert_eq!(report.skipped.len(), 1);
+
+        // Both ends of a round are included, so the hour 20 header counts too
+        let first = &report.rounds[0];
+        assert!((first.settlement_twap - (10.0 * 110.0 + 200.0) / 11.0).abs() < 1e-9);
+        assert!(first.exercised && !first.cap_hit && first.within_payoff_band);
+        assert!((first.payoff - discount * (first.settlement_twap - 100.0)).abs() < 1e-9);
+
+        let second = &report.rounds[1];
+        assert!(second.cap_hit);
+        assert!((second.payoff - discount * 50.0).abs() < 1e-9);
+        assert!(!second.within_payoff_band);
+
+        let summary = &report.summary;
+        assert_eq!((summary.rounds, summary.skipped), (2, 1));
+        assert!((summary.predicted_exercise_rate - 0.6).abs() < 1e-12);
+        assert!((summary.exercise_rate - 1.0).abs() < 1e-12);
+        assert!((summary.cap_hit_rate - 0.5).abs() < 1e-12);
+        assert!(
+            (summary.total_pnl - report.rounds.iter().map(|r| r.pricing_error).sum::<f64>()).abs()
+                < 1e-9
+        );
+        assert!(summary.max_drawdown > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_rounds_without_headers_are_skipped() {
+        let headers = HeaderRanges::from_headers(vec![header(1, 0, 100), header(2, HOUR, 100)]);
+
+        let report = run_backtest(
+            &headers,
+            &config(10 * HOUR, 20 * HOUR),
+            &PricingConfig::default(),
+            &FixedModel,
+        )
+        .await;
+
+        assert!(report.rounds.is_empty());
+        assert_eq!(
+            report.skipped,
+            vec![SkippedRound {
+                round_start: 10 * HOUR,
+                error: "Headers do not cover the round".to_string(),
+            }]
+        );
+        assert_eq!(report.summary.skipped, 1);
+    }
+}
@@ -0,0 +1,408 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use db_access::{
+    models::CallbackTxStatus,
+    queries::{confirm_callback, record_callback_submission, resolve_callback},
+    OffchainProcessorDbConnection,
+};
+use eyre::{eyre, Result};
+use starknet_crypto::Felt;
+use starknet_handler::{FossilStarknetAccount, JobRequest, PitchLakeResult, TxStatus};
+use tokio::time::{sleep, timeout, Instant};
+
+use crate::env_config::{env_secs, env_value_where};
+
+/// How callback transactions are confirmed.
+#[derive(Debug, Clone)]
+pub struct CallbackConfig {
+    /// Delay between two receipt lookups.
+    pub poll_interval: Duration,
+    /// How long a submitted transaction may stay pending before the job fails.
+    pub confirmation_timeout: Duration,
+    /// How long a submitted transaction may stay unknown to the node before
+    /// it counts as dropped and is resubmitted.
+    pub not_found_grace: Duration,
+    /// How many transactions may be sent for one job when the sequencer
+    /// rejects them.
+    pub max_submissions: usize,
+}
+
+impl Default for CallbackConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(5),
+            confirmation_timeout: Duration::from_secs(600),
+            not_found_grace: Duration::from_secs(60),
+            max_submissions: 3,
+        }
+    }
+}
+
+impl CallbackConfig {
+    /// Reads `CALLBACK_POLL_INTERVAL_SECS`, `CALLBACK_CONFIRMATION_TIMEOUT_SECS`,
+    /// `CALLBACK_NOT_FOUND_GRACE_SECS` and `CALLBACK_MAX_SUBMISSIONS`, falling
+    /// back to the defaults when unset and warning about unparsable values or
+    /// zero submissions.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            poll_interval: env_secs("CALLBACK_POLL_INTERVAL_SECS", default.poll_interval),
+            confirmation_timeout: env_secs(
+                "CALLBACK_CONFIRMATION_TIMEOUT_SECS",
+                default.confirmation_timeout,
+            ),
+            not_found_grace: env_secs("CALLBACK_NOT_FOUND_GRACE_SECS", default.not_found_grace),
+            max_submissions: env_value_where(
+                "CALLBACK_MAX_SUBMISSIONS",
+                default.max_submissions,
+                |&n| n > 0,
+            ),
+        }
+    }
+}
+
+/// Sends the job result to the vault and waits until the transaction is
+/// accepted on L2, then finishes the job.
+///
+/// `pending_tx` resumes waiting on a transaction sent before a restart instead
+/// of sending a new one. Rejected transactions, and ones the node still
+/// doesn't know after `not_found_grace`, are resubmitted until
+/// `max_submissions` were sent by this run, so a retried job gets a fresh
+/// budget; a revert fails the job with its reason.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn deliver_callback(
+    db: Arc<OffchainProcessorDbConnection>,
+    account: &FossilStarknetAccount,
+    job_id: &str,
+    client_address: Felt,
+    job_request: &JobRequest,
+    result: &PitchLakeResult,
+    pending_tx: Option<Felt>,
+    config: &CallbackConfig,
+    context: &str,
+) -> Result<()> {
+    let mut pending_tx = pending_tx;
+    let mut submissions = usize::from(pending_tx.is_some());
+
+    loop {
+        let tx_hash = match pending_tx.take() {
+            Some(tx_hash) => {
+                tracing::info!("Resuming confirmation of {:#x}. {}", tx_hash, context);
+                tx_hash
+            }
+            None => {
+                let tx_hash = account
+                    .callback_to_contract(client_address, job_request, result)
+                    .await
+                    .map_err(|e| eyre!("Starknet callback failed. Error: {:?}", e))?;
+                submissions += 1;
+                let attempt =
+                    record_callback_submission(db.clone(), job_id, &format!("{:#x}", tx_hash))
+                        .await?;
+                tracing::info!(
+                    "Starknet callback submitted (attempt {}): {:#x}. {}",
+                    attempt,
+                    tx_hash,
+                    context
+                );
+                tx_hash
+            }
+        };
+        let tx_hash_hex = format!("{:#x}", tx_hash);
+
+        let status = wait_for_tx(
+            || account.tx_status(tx_hash),
+            config.poll_interval,
+            config.not_found_grace,
+            config.confirmation_timeout,
+        )
+        .await
+        .map_err(|e| eyre!("Callback transaction {}: {}", tx_hash_hex, e))?;
+
+        match status {
+            FinalTxStatus::Accepted => {
+                confirm_callback(db, job_id, &tx_hash_hex).await?;
+                tracing::info!("Callback transaction {} accepted. {}", tx_hash_hex, context);
+                return Ok(());
+            }
+            FinalTxStatus::Reverted { reason } => {
+                resolve_callback(
+                    db.clone(),
+                    &tx_hash_hex,
+                    CallbackTxStatus::Reverted,
+                    Some(&reason),
+                )
+                .await?;
+                // A revert is deterministic, sending the same call again won't help
+                return Err(eyre!(
+                    "Callback transaction {} reverted: {}",
+                    tx_hash_hex,
+                    reason
+                ));
+            }
+            FinalTxStatus::Rejected | FinalTxStatus::Dropped => {
+                // A dropped transaction never made it into a block either
+                resolve_callback(db.clone(), &tx_hash_hex, CallbackTxStatus::Rejected, None)
+                    .await?;
+                if submissions >= config.max_submissions {
+                    return Err(eyre!(
+                        "Callback transaction rejected or dropped {} times, last {}",
+                        submissions,
+                        tx_hash_hex
+                    ));
+                }
+                let outcome = if status == FinalTxStatus::Dropped {
+                    "still unknown to the node"
+                } else {
+                    "rejected"
+                };
+                tracing::warn!(
+                    "Callback transaction {} {}, resubmitting. {}",
+                    tx_hash_hex,
+                    outcome,
+                    context
+                );
+            }
+        }
+    }
+}
+
+/// Status of a callback transaction that is no longer pending.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FinalTxStatus {
+    Accepted,
+    Reverted {
+        reason: String,
+    },
+    Rejected,
+    /// Still unknown to the node once the grace period ran out.
+    Dropped,
+}
+
+impl FinalTxStatus {
+    // `None` while the transaction is still pending or not found yet
+    fn from_status(status: TxStatus) -> Option<Self> {
+        match status {
+            TxStatus::Pending | TxStatus::NotFound => None,
+            TxStatus::Accepted => Some(Self::Accepted),
+            TxStatus::Reverted { reason } => Some(Self::Reverted { reason }),
+            TxStatus::Rejected => Some(Self::Rejected),
+        }
+    }
+}
+
+// Polls `status` until the transaction leaves `Pending`. A transaction the
+// node doesn't know once `not_found_grace` has passed is dropped. Lookup
+// errors are treated as transient and only end the wait when time runs out.
+async fn wait_for_tx<F, Fut>(
+    mut status: F,
+    poll_interval: Duration,
+    not_found_grace: Duration,
+    confirmation_timeout: Duration,
+) -> Result<FinalTxStatus>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<TxStatus>>,
+{
+    let started = Instant::now();
+    let wait = async {
+        loop {
+            match status().await {
+                Ok(TxStatus::NotFound) if started.elapsed() >= not_found_grace => {
+                    return FinalTxStatus::Dropped
+                }
+                Ok(status) => {
+                    if let Some(status) = FinalTxStatus::from_status(status) {
+                        return status;
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to look up transaction status: {:?}", e),
+            }
+            sleep(poll_interval).await;
+        }
+    };
+
+    timeout(confirmation_timeout, wait).await.map_err(|_| {
+        eyre!(
+            "not confirmed within {}s",
+            confirmation_timeout.as_secs_f64()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::fixtures::TestContext;
+    use db_access::models::JobStatus;
+    use db_access::queries::{
+        get_job_callbacks, get_job_request, get_job_status_history, is_invalid_status_transition,
+        set_job_status,
+    };
+    use std::collections::VecDeque;
+
+    #[tokio::test]
+    async fn test_wait_for_tx_returns_first_final_status() {
+        let mut statuses = VecDeque::from([
+            Ok(TxStatus::Pending),
+            Err(eyre!("node unavailable")),
+            Ok(TxStatus::Reverted {
+                reason: "Vault: invalid round".to_string(),
+            }),
+        ]);
+
+        let status = wait_for_tx(
+            || std::future::ready(statuses.pop_front().unwrap()),
+            Duration::from_millis(1),
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            status,
+            FinalTxStatus::Reverted {
+                reason: "Vault: invalid round".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_tx_times_out() {
+        let result = wait_for_tx(
+            || std::future::ready(Ok(TxStatus::Pending)),
+            Duration::from_millis(1),
+            Duration::from_millis(1),
+            Duration::from_millis(20),
+        )
+        .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_tx_waits_for_unknown_tx_within_grace() {
+        let mut statuses = VecDeque::from([
+            Ok(TxStatus::NotFound),
+            Ok(TxStatus::NotFound),
+            Ok(TxStatus::Accepted),
+        ]);
+
+        let status = wait_for_tx(
+            || std::future::ready(statuses.pop_front().unwrap()),
+            Duration::from_millis(1),
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, FinalTxStatus::Accepted);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_tx_drops_tx_unknown_after_grace() {
+        let status = wait_for_tx(
+            || std::future::ready(Ok(TxStatus::NotFound)),
+            Duration::from_millis(1),
+            Duration::from_millis(20),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, FinalTxStatus::Dropped);
+    }
+
+    #[tokio::test]
+    async fn test_callback_submissions_are_recorded() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+        ctx.create_job("callback_job", JobStatus::Computed).await;
+
+        let first = record_callback_submission(db.clone(), "callback_job", "0x1")
+            .await
+            .unwrap();
+        resolve_callback(db.clone(), "0x1", CallbackTxStatus::Rejected, None)
+            .await
+            .unwrap();
+        let second = record_callback_submission(db.clone(), "callback_job", "0x2")
+            .await
+            .unwrap();
+        resolve_callback(
+            db.clone(),
+            "0x2",
+            CallbackTxStatus::Reverted,
+            Some("Vault: invalid round"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!((first, second), (1, 2));
+        let callbacks = get_job_callbacks(db.clone(), "callback_job").await.unwrap();
+        assert_eq!(callbacks[0].status, CallbackTxStatus::Rejected);
+        assert_eq!(callbacks[1].status, CallbackTxStatus::Reverted);
+        assert_eq!(
+            callbacks[1].revert_reason.as_deref(),
+            Some("Vault: invalid round")
+        );
+
+        let job = get_job_request(db.clone(), "callback_job")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.status, JobStatus::CallbackSubmitted);
+        assert_eq!(job.callback_tx_hash.as_deref(), Some("0x2"));
+    }
+
+    #[tokio::test]
+    async fn test_job_cannot_finish_before_callback_is_confirmed() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+        ctx.create_job("callback_job", JobStatus::Computed).await;
+        record_callback_submission(db.clone(), "callback_job", "0x1")
+            .await
+            .unwrap();
+
+        let error = set_job_status(db.clone(), "callback_job", JobStatus::Completed)
+            .await
+            .unwrap_err();
+        assert!(is_invalid_status_transition(&error));
+
+        set_job_status(db.clone(), "callback_job", JobStatus::CallbackConfirmed)
+            .await
+            .unwrap();
+        set_job_status(db.clone(), "callback_job", JobStatus::Completed)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_callback_finishes_the_job() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+        ctx.create_job("callback_job", JobStatus::Computed).await;
+        record_callback_submission(db.clone(), "callback_job", "0x1")
+            .await
+            .unwrap();
+
+        confirm_callback(db.clone(), "callback_job", "0x1")
+            .await
+            .unwrap();
+
+        let job = get_job_request(db.clone(), "callback_job")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Completed);
+        let callbacks = get_job_callbacks(db.clone(), "callback_job").await.unwrap();
+        assert_eq!(callbacks[0].status, CallbackTxStatus::Accepted);
+        let history = get_job_status_history(db.clone(), "callback_job")
+            .await
+            .unwrap();
+        let statuses: Vec<_> = history.iter().map(|change| change.to_status).collect();
+        assert!(statuses.ends_with(&[JobStatus::CallbackConfirmed, JobStatus::Completed]));
+    }
+}
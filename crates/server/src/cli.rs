@@ -0,0 +1,117 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use eyre::{eyre, Result};
+
+/// Arguments of the offline binaries: `--name value` options and bare
+/// `--name` switches, each known up front.
+#[derive(Debug)]
+pub struct CliArgs {
+    values: HashMap<String, String>,
+    switches: HashSet<String>,
+    usage: &'static str,
+}
+
+impl CliArgs {
+    /// Fails on unknown names and options without a value, with `usage`
+    /// appended to the error.
+    pub fn parse(
+        args: impl IntoIterator<Item = String>,
+        options: &[&str],
+        switches: &[&str],
+        usage: &'static str,
+    ) -> Result<Self> {
+        let mut parsed = Self {
+            values: HashMap::new(),
+            switches: HashSet::new(),
+            usage,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if switches.contains(&name) => {
+                    parsed.switches.insert(name.to_string());
+                }
+                Some(name) if options.contains(&name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| eyre!("Missing value for {}. {}", arg, usage))?;
+                    parsed.values.insert(name.to_string(), value);
+                }
+                _ => return Err(eyre!("Unexpected argument {}. {}", arg, usage)),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn optional<T: FromStr>(&self, name: &str) -> Result<Option<T>>
+    where
+        T::Err: Display,
+    {
+        self.get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| eyre!("Invalid --{} {}: {}", name, value, e))
+            })
+            .transpose()
+    }
+
+    pub fn required<T: FromStr>(&self, name: &str) -> Result<T>
+    where
+        T::Err: Display,
+    {
+        self.optional(name)?
+            .ok_or_else(|| eyre!("Missing --{}. {}", name, self.usage))
+    }
+
+    pub fn switch(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs> {
+        CliArgs::parse(
+            args.iter().map(|arg| arg.to_string()),
+            &["alpha", "headers"],
+            &["series"],
+            "Usage: test",
+        )
+    }
+
+    #[test]
+    fn test_options_and_switches() {
+        let args = parse(&["--headers", "headers.csv", "--series", "--alpha", "5000"]).unwrap();
+
+        assert_eq!(args.get("headers"), Some("headers.csv"));
+        assert_eq!(args.required::<u128>("alpha").unwrap(), 5000);
+        assert!(args.switch("series"));
+        assert_eq!(args.optional::<i128>("k").unwrap(), None);
+        assert_eq!(
+            args.required::<i128>("k").unwrap_err().to_string(),
+            "Missing --k. Usage: test"
+        );
+    }
+
+    #[test]
+    fn test_unknown_and_incomplete_arguments_are_rejected() {
+        assert!(parse(&["--k", "0"]).is_err());
+        assert!(parse(&["--alpha"]).is_err());
+        assert!(parse(&["headers.csv"]).is_err());
+        assert!(parse(&["--alpha", "half"])
+            .unwrap()
+            .required::<u128>("alpha")
+            .is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Reads `name`, falling back to `default` when it is unset or empty and
+/// warning before falling back when it is unparsable.
+pub fn env_value<T: FromStr + Display>(name: &str, default: T) -> T
+where
+    T::Err: Display,
+{
+    env_value_where(name, default, |_| true)
+}
+
+/// Like [`env_value`], but values that `valid` rejects are warned about and
+/// replaced by `default` too.
+pub fn env_value_where<T: FromStr + Display>(
+    name: &str,
+    default: T,
+    valid: impl Fn(&T) -> bool,
+) -> T
+where
+    T::Err: Display,
+{
+    parse_value(name, env::var(name).ok(), default, valid)
+}
+
+/// Reads a number of seconds from `name`, as [`env_value`] does.
+pub fn env_secs(name: &str, default: Duration) -> Duration {
+    Duration::from_secs(env_value(name, default.as_secs()))
+}
+
+/// Reads a number of milliseconds from `name`, as [`env_value`] does.
+pub fn env_millis(name: &str, default: Duration) -> Duration {
+    let default = u64::try_from(default.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(env_value(name, default))
+}
+
+fn parse_value<T: FromStr + Display>(
+    name: &str,
+    raw: Option<String>,
+    default: T,
+    valid: impl Fn(&T) -> bool,
+) -> T
+where
+    T::Err: Display,
+{
+    let Some(raw) = raw.filter(|raw| !raw.trim().is_empty()) else {
+        return default;
+    };
+    match raw.trim().parse::<T>() {
+        Ok(value) if valid(&value) => value,
+        Ok(value) => {
+            tracing::warn!("Ignoring {}={}, using {} instead", name, value, default);
+            default
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Ignoring unparsable {}={:?} ({}), using {} instead",
+                name,
+                raw,
+                e,
+                default
+            );
+            default
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bad_values_fall_back_to_the_default() {
+        let positive = |n: &usize| *n > 0;
+
+        assert_eq!(parse_value("N", Some(" 8 ".into()), 4, positive), 8);
+        assert_eq!(parse_value("N", None, 4, positive), 4);
+        assert_eq!(parse_value("N", Some(String::new()), 4, positive), 4);
+        assert_eq!(parse_value("N", Some("eight".into()), 4, positive), 4);
+        assert_eq!(parse_value("N", Some("-1".into()), 4, positive), 4);
+        assert_eq!(parse_value("N", Some("0".into()), 4, positive), 4);
+    }
+}
@@ -0,0 +1,275 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::env_config::{env_secs, env_value_where};
+use crate::handlers::get_pricing_data::{
+    calculate_pricing_data, fetch_headers, mock_pricing_result, resolve_pricing_config,
+    validate_pricing_config, validate_program_id, validate_time_ranges,
+};
+use crate::pricing_data::config::PricingConfigFile;
+use crate::types::{
+    ComputePricingRequest, ComputePricingResponse, ComputePricingResponseEnum, ErrorResponse,
+    PitchLakeJobRequestParams, PricingResult,
+};
+use crate::AppState;
+use axum::{extract::State, http::StatusCode, Json};
+use db_access::IndexerDbConnection;
+use eyre::{eyre, Result};
+use tokio::{runtime::Handle, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+// Longest a compute-only request may run unless `COMPUTE_TIMEOUT_SECS` says otherwise
+const DEFAULT_COMPUTE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Compute-only requests run at once unless `COMPUTE_CONCURRENCY` says otherwise
+const DEFAULT_COMPUTE_CONCURRENCY: usize = 2;
+
+// Calculate the TWAP, cap level and reserve price for the given ranges and
+// return them right away. Nothing is stored and no callback is sent, so the
+// values can be previewed or checked against a past round.
+pub async fn compute_pricing_data(
+    State(state): State<AppState>,
+    Json(request): Json<ComputePricingRequest>,
+) -> (StatusCode, Json<ComputePricingResponseEnum>) {
+    let params = request.params;
+    let context =
+        format!(
+        "twap-range=({},{}), cap_level-range=({},{}), reserve_price-range=({},{}), alpha={}, k={}",
+        params.twap.0, params.twap.1,
+        params.cap_level.0, params.cap_level.1,
+        params.reserve_price.0, params.reserve_price.1,
+        params.alpha,
+        params.k,
+    );
+    tracing::info!("Received compute-only pricing request. {}", context);
+
+    if let Err((status, response)) = validate_time_ranges(&params)
+        .and_then(|_| validate_program_id(&params))
+        .and_then(|_| validate_pricing_config(&state.pricing_config, &params))
+    {
+        tracing::warn!("Invalid request: {:?}. {}", response, context);
+        return error_response(status, response.message.unwrap_or_default());
+    }
+
+    // Each computation keeps a blocking thread and the rayon pool busy, so
+    // requests beyond the limit are turned away instead of piling up
+    let Ok(permit) = state.compute_slots.clone().try_acquire_owned() else {
+        tracing::warn!("Too many computations in progress. {}", context);
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Too many computations in progress. Please try again later.".to_string(),
+        );
+    };
+
+    let timeout = compute_timeout();
+    let started = Instant::now();
+    let indexer_db = state.indexer_db.clone();
+    let mock_pricing_data = state.mock_pricing_data;
+    let pricing_config = state.pricing_config.clone();
+    let handle = Handle::current();
+    // Stops the simulation on timeout, or when the client goes away and this
+    // handler is dropped
+    let cancel = CancellationToken::new();
+    let _cancel_on_drop = cancel.clone().drop_guard();
+
+    // Runs off the async runtime like job processing. The slot is only freed
+    // once the thread has stopped.
+    let computation = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        handle.block_on(compute_pricing(
+            indexer_db,
+            &params,
+            &pricing_config,
+            mock_pricing_data,
+            &cancel,
+        ))
+    });
+
+    match tokio::time::timeout(timeout, computation).await {
+        Ok(Ok(Ok(result))) => {
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+            tracing::info!(
+                "Computed pricing data in {}ms: {:?}. {}",
+                elapsed_ms,
+                result,
+                context
+            );
+            (
+                StatusCode::OK,
+                Json(ComputePricingResponseEnum::Success(
+                    ComputePricingResponse { result, elapsed_ms },
+                )),
+            )
+        }
+        Ok(Ok(Err(e))) => {
+            tracing::error!("Failed to compute pricing data: {:?}. {}", e, context);
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to compute pricing data: {}", e),
+            )
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Pricing computation aborted: {:?}. {}", e, context);
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "An internal error occurred. Please try again later.".to_string(),
+            )
+        }
+        Err(_) => {
+            tracing::warn!(
+                "Pricing computation timed out after {:?}. {}",
+                timeout,
+                context
+            );
+            error_response(
+                StatusCode::GATEWAY_TIMEOUT,
+                format!(
+                    "Computation did not finish within {}s.",
+                    timeout.as_secs_f64()
+                ),
+            )
+        }
+    }
+}
+
+async fn compute_pricing(
+    indexer_db: Arc<IndexerDbConnection>,
+    params: &PitchLakeJobRequestParams,
+    pricing_config: &PricingConfigFile,
+    mock_pricing_data: bool,
+    cancel: &CancellationToken,
+) -> Result<PricingResult> {
+    // Without an explicit seed a fresh one is drawn and returned, so the
+    // values can still be reproduced
+    let seed = params.seed.unwrap_or_else(rand::random);
+    let config = resolve_pricing_config(pricing_config, params)?;
+
+    if mock_pricing_data {
+        tracing::info!("Using mock pricing data");
+        return Ok(mock_pricing_result(seed, config));
+    }
+
+    let headers = fetch_headers(indexer_db, params)
+        .await
+        .map_err(|e| eyre!("Error fetching headers: {:?}", e))?;
+    calculate_pricing_data(params, headers, seed, config, cancel).await
+}
+
+// Reads `COMPUTE_TIMEOUT_SECS`, falling back to the default when unset or unparsable
+fn compute_timeout() -> Duration {
+    env_secs("COMPUTE_TIMEOUT_SECS", DEFAULT_COMPUTE_TIMEOUT)
+}
+
+// Reads `COMPUTE_CONCURRENCY`, falling back to the default when unset, unparsable or zero
+pub(crate) fn compute_concurrency() -> usize {
+    env_value_where("COMPUTE_CONCURRENCY", DEFAULT_COMPUTE_CONCURRENCY, |&n| {
+        n > 0
+    })
+}
+
+fn error_response(
+    status: StatusCode,
+    error: String,
+) -> (StatusCode, Json<ComputePricingResponseEnum>) {
+    (
+        status,
+        Json(ComputePricingResponseEnum::Error(ErrorResponse { error })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::fixtures::TestContext;
+    use crate::handlers::get_pricing_data::MOCK_PRICING_DATA;
+    use crate::pricing_data::config::PricingConfig;
+
+    fn request(twap: (i64, i64)) -> ComputePricingRequest {
+        ComputePricingRequest {
+            params: PitchLakeJobRequestParams {
+                twap,
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: Some(7),
+                pricing_config: None,
+                program_id: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compute_pricing_data_invalid_params() {
+        let ctx = TestContext::new().await;
+
+        let (status, Json(response)) = ctx.compute_pricing_data(request((100, 0))).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        match response {
+            ComputePricingResponseEnum::Error(e) => {
+                assert_eq!(e.error, "Invalid time range for TWAP calculation.")
+            }
+            ComputePricingResponseEnum::Success(_) => panic!("Unexpected response status"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compute_pricing_data_does_not_create_a_job() {
+        let mut ctx = TestContext::new().await;
+        ctx.app_state.mock_pricing_data = true;
+
+        let (status, Json(response)) = ctx.compute_pricing_data(request((0, 100))).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let response = match response {
+            ComputePricingResponseEnum::Success(response) => response,
+            ComputePricingResponseEnum::Error(e) => panic!("Unexpected error: {}", e.error),
+        };
+        let (twap, cap_level, reserve_price) = MOCK_PRICING_DATA;
+        assert_eq!(
+            response.result,
+            PricingResult {
+                twap,
+                cap_level,
+                reserve_price,
+                volatility: None,
+                reserve_price_diagnostics: None,
+                reserve_price_sensitivities: None,
+                analytic_cross_check: None,
+                data_quality: None,
+                outliers: None,
+                seed: Some(7),
+                config: Some(PricingConfig::default()),
+            }
+        );
+
+        let jobs: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM job_requests")
+            .fetch_one(&ctx.offchain_processor_db.db_connection().pool)
+            .await
+            .unwrap();
+        assert_eq!(jobs, 0);
+    }
+
+    #[tokio::test]
+    async fn test_compute_pricing_data_is_refused_without_a_free_slot() {
+        let mut ctx = TestContext::new().await;
+        ctx.app_state.mock_pricing_data = true;
+        let _busy = ctx
+            .app_state
+            .compute_slots
+            .clone()
+            .try_acquire_owned()
+            .unwrap();
+
+        let (status, Json(response)) = ctx.compute_pricing_data(request((0, 100))).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        match response {
+            ComputePricingResponseEnum::Error(e) => assert_eq!(
+                e.error,
+                "Too many computations in progress. Please try again later."
+            ),
+            ComputePricingResponseEnum::Success(_) => panic!("Unexpected response status"),
+        }
+    }
+}
@@ -1,12 +1,19 @@
 use std::sync::Arc;
 
 use crate::{
+    job_events::JobEvents,
+    pricing_data::config::PricingConfigFile,
     types::{
+        BatchJobResponse, ComputePricingRequest, ComputePricingResponseEnum,
         GetJobStatusResponseEnum, GetLatestBlockResponseEnum, JobResponse, PitchLakeJobRequest,
     },
     AppState,
 };
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    Json,
+};
 use db_access::{
     models::JobStatus, queries::create_job_request, DbConnection, IndexerDbConnection,
     OffchainProcessorDbConnection,
@@ -14,9 +21,15 @@ use db_access::{
 use lazy_static::lazy_static;
 use sqlx::postgres::PgPoolOptions;
 use testcontainers::{clients::Cli, images::postgres::Postgres as PostgresImage, Container};
+use tokio::sync::{Notify, Semaphore};
 
 use super::{
-    get_pricing_data::get_pricing_data, job_status::get_job_status,
+    compute_pricing::compute_pricing_data,
+    get_pricing_data::{
+        cancel_job, get_batch_pricing_data, get_pricing_data, replay_webhook, retry_job,
+    },
+    job_events::get_job_status_events,
+    job_status::get_job_status,
     latest_block::get_latest_block_number,
 };
 
@@ -44,21 +57,6 @@ impl TestContext {
             .await
             .expect("Failed to create database pool");
 
-        // Create the `job_requests` table with the dynamic JSONB result column.
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS job_requests (
-                job_id TEXT PRIMARY KEY,
-                status TEXT NOT NULL CHECK (status IN ('Completed', 'Pending', 'Failed')),
-                result JSONB, -- Stores dynamic JSON responses
-                created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
-        )
-        .execute(&pool)
-        .await
-        .expect("Failed to create job_requests table");
-
         // Create the blockheaders table
         sqlx::query(
             r#"
@@ -82,9 +80,24 @@ impl TestContext {
                 .await
                 .unwrap(),
         );
+
+        // The offchain processor schema, including its triggers, comes from the
+        // real migrations so tests cannot drift from production.
+        offchain_processor_db
+            .migrate()
+            .await
+            .expect("Failed to run offchain processor migrations");
+        let job_events = JobEvents::default();
+        job_events.spawn_listener(offchain_processor_db.clone());
+
         let app_state = AppState {
             indexer_db: indexer_db.clone(),
             offchain_processor_db: offchain_processor_db.clone(),
+            job_notifier: Arc::new(Notify::new()),
+            job_events,
+            compute_slots: Arc::new(Semaphore::new(1)),
+            mock_pricing_data: false,
+            pricing_config: Arc::new(PricingConfigFile::default()),
         };
 
         Self {
@@ -113,6 +126,31 @@ impl TestContext {
         .await
     }
 
+    /// Opens the SSE stream of a job and collects it until it closes.
+    pub async fn get_job_status_events(
+        &self,
+        job_id: &str,
+        last_event_id: Option<i64>,
+    ) -> (StatusCode, String) {
+        let mut headers = HeaderMap::new();
+        if let Some(id) = last_event_id {
+            headers.insert("last-event-id", id.to_string().parse().unwrap());
+        }
+
+        let response = get_job_status_events(
+            State(self.app_state.clone()),
+            axum::extract::Path(job_id.to_string()),
+            headers,
+        )
+        .await;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("Failed to read event stream");
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
     /// Sends a pricing data request and returns the status and response.
     pub async fn get_pricing_data(
         &self,
@@ -121,6 +159,46 @@ impl TestContext {
         get_pricing_data(State(self.app_state.clone()), Json(payload)).await
     }
 
+    pub async fn compute_pricing_data(
+        &self,
+        request: ComputePricingRequest,
+    ) -> (StatusCode, Json<ComputePricingResponseEnum>) {
+        compute_pricing_data(State(self.app_state.clone()), Json(request)).await
+    }
+
+    pub async fn get_batch_pricing_data(
+        &self,
+        payloads: Vec<PitchLakeJobRequest>,
+    ) -> (StatusCode, Json<BatchJobResponse>) {
+        get_batch_pricing_data(State(self.app_state.clone()), Json(payloads)).await
+    }
+
+    /// Retries a failed job from its stored request.
+    pub async fn retry_job(&self, job_id: &str) -> (StatusCode, Json<JobResponse>) {
+        retry_job(
+            State(self.app_state.clone()),
+            axum::extract::Path(job_id.to_string()),
+        )
+        .await
+    }
+
+    /// Cancels a job.
+    pub async fn cancel_job(&self, job_id: &str) -> (StatusCode, Json<JobResponse>) {
+        cancel_job(
+            State(self.app_state.clone()),
+            axum::extract::Path(job_id.to_string()),
+        )
+        .await
+    }
+
+    pub async fn replay_webhook(&self, job_id: &str) -> (StatusCode, Json<JobResponse>) {
+        replay_webhook(
+            State(self.app_state.clone()),
+            axum::extract::Path(job_id.to_string()),
+        )
+        .await
+    }
+
     pub async fn create_job_with_result(
         &self,
         job_id: &str,
@@ -1,32 +1,48 @@
-use db_access::{IndexerDbConnection, OffchainProcessorDbConnection};
-use dotenv::dotenv;
-use std::env;
+use db_access::IndexerDbConnection;
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::types::{JobResponse, PitchLakeJobRequest};
+use crate::callback::{deliver_callback, CallbackConfig};
+use crate::header_ranges::HeaderRanges;
+use crate::job_id::{generate_job_id, generate_job_id_with_version, JobIdVersion};
+use crate::job_queue::LeaseLost;
+use crate::types::{
+    BatchJobItem, BatchJobResponse, JobResponse, PitchLakeJobRequest, PricingResult,
+};
+use crate::webhooks::{
+    create_job_webhooks, is_webhook_url, spawn_job_webhooks, spawn_subscriber_webhook,
+    spawn_webhook_delivery, WebhookConfig,
+};
 use crate::AppState;
 use crate::{
     pricing_data::{
-        cap_level::calculate_cap_level, reserve_price::calculate_reserve_price,
-        twap::calculate_twap,
+        config::{PricingConfig, PricingConfigFile},
+        data_quality::DataQualityReport,
+        model::{pitch_lake_v1, PricingCancelled, PricingHeaders, PricingModelRegistry},
+        outliers::OutlierReport,
+        rng::seed_from_job_id,
     },
     types::PitchLakeJobRequestParams,
 };
 use axum::{
-    extract::{Json, State},
+    extract::{Json, Path, State},
     http::StatusCode,
 };
 use db_access::{
     models::JobStatus,
     queries::{
-        create_job_request, get_block_headers_by_time_range, get_job_request, update_job_status,
+        add_job_webhook, cancel_job_request, create_queued_job_batch, create_queued_job_request,
+        get_block_headers_by_time_range, get_job_request, is_invalid_status_transition,
+        requeue_job_request, set_job_status, update_job_status,
     },
 };
 use eyre::{eyre, Result};
 use starknet::core::types::U256;
-use starknet_crypto::{poseidon_hash_single, Felt};
-use starknet_handler::{FossilStarknetAccount, JobRequest, PitchLakeResult, PITCH_LAKE_V1};
-use tokio::{join, runtime::Handle, time::Instant};
+use starknet_crypto::Felt;
+use starknet_handler::{FossilStarknetAccount, JobRequest, PitchLakeResult};
+use tokio::join;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
 // Main handler function
 pub async fn get_pricing_data(
@@ -47,45 +63,212 @@ pub async fn get_pricing_data(
 
     tracing::info!("Received pricing data request. {}", context);
 
-    if let Err((status, response)) = validate_request(&payload) {
+    if let Err((status, response)) = validate_request(&state, &payload) {
         tracing::warn!("Invalid request: {:?}. {}", response, context);
         return (status, Json(response));
     }
 
-    let starknet_account = FossilStarknetAccount::default();
-    let job_id = generate_job_id(&payload.identifiers, &payload.params);
-
-    tracing::info!("Generated job_id: {}. {}", job_id, context);
-
-    match get_job_request(state.offchain_processor_db.clone(), &job_id).await {
-        Ok(Some(job_request)) => {
+    match resolve_job_id(&state, &payload).await {
+        Ok((job_id, Some(status))) => {
             tracing::info!(
-                "Found existing job with status: {}. {}",
-                job_request.status,
+                "Found existing job {} with status: {}. {}",
+                job_id,
+                status,
                 context
             );
-            handle_existing_job(
-                &state,
-                job_request.status,
-                job_id,
-                payload,
-                starknet_account,
-            )
-            .await
+            handle_existing_job(&state, status, job_id, payload).await
         }
-        Ok(None) => {
+        Ok((job_id, None)) => {
+            tracing::info!("Generated job_id: {}. {}", job_id, context);
             tracing::info!("Creating new job request. {}", context);
-            handle_new_job_request(&state, job_id, payload, starknet_account).await
+            handle_new_job_request(&state, job_id, payload).await
         }
         Err(e) => {
             tracing::error!("Database error: {}. {}", e, context);
-            internal_server_error(e, job_id)
+            internal_server_error(e, request_job_id(&state, &payload))
+        }
+    }
+}
+
+// Most requests accepted by `/pricing_data/batch` at once
+const MAX_BATCH_SIZE: usize = 100;
+
+// Register pricing jobs for several vaults at once. Identical requests map to
+// the same job, and new jobs are queued as one batch so the worker reads the
+// block headers they share only once.
+pub async fn get_batch_pricing_data(
+    State(state): State<AppState>,
+    Json(payloads): Json<Vec<PitchLakeJobRequest>>,
+) -> (StatusCode, Json<BatchJobResponse>) {
+    tracing::info!(
+        "Received batch pricing data request with {} requests.",
+        payloads.len()
+    );
+
+    if payloads.is_empty() || payloads.len() > MAX_BATCH_SIZE {
+        let message = format!(
+            "A batch must contain between 1 and {} requests.",
+            MAX_BATCH_SIZE
+        );
+        tracing::warn!("Invalid batch request: {}", message);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(BatchJobResponse {
+                message: Some(message),
+                jobs: Vec::new(),
+            }),
+        );
+    }
+
+    let mut responses: Vec<Option<(StatusCode, JobResponse)>> =
+        payloads.iter().map(|_| None).collect();
+    let mut first_index: HashMap<String, usize> = HashMap::new();
+    let mut duplicates = Vec::new();
+    let mut new_jobs = Vec::new();
+    let mut new_webhooks = Vec::new();
+
+    for (index, payload) in payloads.into_iter().enumerate() {
+        if let Err(response) = validate_request(&state, &payload) {
+            responses[index] = Some(response);
+            continue;
+        }
+
+        let (job_id, status) = match resolve_job_id(&state, &payload).await {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let (code, Json(response)) =
+                    internal_server_error(e, request_job_id(&state, &payload));
+                responses[index] = Some((code, response));
+                continue;
+            }
+        };
+
+        if let Some(&first) = first_index.get(&job_id) {
+            // Every submitter of the job gets its webhook
+            if let Some(url) = payload.callback_url {
+                match status {
+                    Some(status) => {
+                        if let Err(e) = subscribe_webhook(&state, &job_id, status, url).await {
+                            let (code, Json(response)) = internal_server_error(e, job_id);
+                            responses[index] = Some((code, response));
+                            continue;
+                        }
+                    }
+                    None => new_webhooks.push((job_id, url)),
+                }
+            }
+            duplicates.push((index, first));
+            continue;
+        }
+        first_index.insert(job_id.clone(), index);
+
+        match status {
+            Some(status) => {
+                let (code, Json(mut response)) =
+                    handle_existing_job(&state, status, job_id, payload).await;
+                if code.is_success() && response.status.is_none() {
+                    response.status = Some(if status.is_retryable() {
+                        JobStatus::Queued
+                    } else {
+                        status
+                    });
+                }
+                responses[index] = Some((code, response));
+            }
+            None => match stored_request(&payload) {
+                Ok(request) => {
+                    if let Some(url) = payload.callback_url {
+                        new_webhooks.push((job_id.clone(), url));
+                    }
+                    new_jobs.push((index, job_id, request));
+                }
+                Err(e) => {
+                    let (code, Json(response)) = internal_server_error(e, job_id);
+                    responses[index] = Some((code, response));
+                }
+            },
+        }
+    }
+
+    if !new_jobs.is_empty() {
+        let batch_id = Uuid::new_v4().to_string();
+        let jobs: Vec<_> = new_jobs
+            .iter()
+            .map(|(_, job_id, request)| (job_id.clone(), request.clone()))
+            .collect();
+
+        match create_queued_job_batch(
+            state.offchain_processor_db.clone(),
+            &batch_id,
+            JobStatus::Queued,
+            &jobs,
+            &new_webhooks,
+        )
+        .await
+        {
+            Ok(()) => {
+                tracing::info!("Queued {} new job(s) as batch {}.", jobs.len(), batch_id);
+                state.job_notifier.notify_one();
+                for (index, job_id, _) in new_jobs {
+                    responses[index] = Some((
+                        StatusCode::CREATED,
+                        JobResponse::new(
+                            job_id,
+                            Some(
+                                "New job request registered and processing initiated.".to_string(),
+                            ),
+                            Some(JobStatus::Queued),
+                        ),
+                    ));
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to queue batch {}: {:?}", batch_id, e);
+                for (index, job_id, _) in new_jobs {
+                    let (code, Json(response)) = internal_server_error(&e, job_id);
+                    responses[index] = Some((code, response));
+                }
+            }
         }
     }
+
+    for (index, first) in duplicates {
+        let (code, original) = responses[first]
+            .as_ref()
+            .expect("the first occurrence is answered before its duplicates");
+        let duplicate = JobResponse::new(
+            original.job_id.clone(),
+            Some(format!("Same job as request {} of this batch.", first)),
+            original.status,
+        );
+        responses[index] = Some((*code, duplicate));
+    }
+
+    let jobs = responses
+        .into_iter()
+        .map(|response| {
+            let (code, job) = response.expect("every request is answered");
+            BatchJobItem {
+                code: code.as_u16(),
+                job,
+            }
+        })
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(BatchJobResponse {
+            message: None,
+            jobs,
+        }),
+    )
 }
 
 // Helper to validate the request
-fn validate_request(payload: &PitchLakeJobRequest) -> Result<(), (StatusCode, JobResponse)> {
+fn validate_request(
+    state: &AppState,
+    payload: &PitchLakeJobRequest,
+) -> Result<(), (StatusCode, JobResponse)> {
     if payload.identifiers.is_empty() {
         return Err((
             StatusCode::BAD_REQUEST,
@@ -96,27 +279,77 @@ fn validate_request(payload: &PitchLakeJobRequest) -> Result<(), (StatusCode, Jo
             ),
         ));
     }
-    validate_time_ranges(&payload.params)
+    if let Some(callback_url) = &payload.callback_url {
+        if !is_webhook_url(callback_url, &WebhookConfig::from_env().allowed_hosts) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                JobResponse::new(
+                    String::new(),
+                    Some("Invalid callback_url.".to_string()),
+                    None,
+                ),
+            ));
+        }
+    }
+    validate_time_ranges(&payload.params)?;
+    validate_program_id(&payload.params)?;
+    validate_pricing_config(&state.pricing_config, &payload.params)
+}
+
+// The request as stored with its job. Its `callback_url` is kept with the
+// job's other webhook subscribers instead, as it does not identify the job.
+fn stored_request(payload: &PitchLakeJobRequest) -> serde_json::Result<serde_json::Value> {
+    serde_json::to_value(PitchLakeJobRequest {
+        callback_url: None,
+        ..payload.clone()
+    })
+}
+
+// Subscribes `url` to the webhooks of an existing job. A job that already
+// completed notifies a new subscriber right away.
+async fn subscribe_webhook(
+    state: &AppState,
+    job_id: &str,
+    status: JobStatus,
+    url: String,
+) -> Result<(), sqlx::Error> {
+    let subscribed = add_job_webhook(state.offchain_processor_db.clone(), job_id, &url).await?;
+    if subscribed && status == JobStatus::Completed {
+        spawn_subscriber_webhook(state.offchain_processor_db.clone(), job_id.to_string(), url);
+    }
+    Ok(())
+}
+
+// The ID a new job for `payload` gets. Overrides of a value its program
+// already runs with don't change it.
+fn request_job_id(state: &AppState, payload: &PitchLakeJobRequest) -> String {
+    let defaults = state
+        .pricing_config
+        .program_defaults(program_id(&payload.params));
+    generate_job_id(payload, &defaults)
 }
 
-// Helper to generate a job ID
-fn generate_job_id(identifiers: &[String], params: &PitchLakeJobRequestParams) -> String {
-    let mut input = identifiers.join("");
-
-    // Concatenate all time ranges as part of the job ID generation
-    input.push_str(&format!(
-        "{}{}{}{}{}{}{}{}",
-        params.twap.0,
-        params.twap.1,
-        params.cap_level.0,
-        params.cap_level.1,
-        params.reserve_price.0,
-        params.reserve_price.1,
-        params.alpha,
-        params.k,
-    ));
-
-    poseidon_hash_single(Felt::from_bytes_be_slice(input.as_bytes())).to_string()
+// Helper to find the job a request maps to. Jobs created before the current
+// ID derivation are still found under their legacy ID.
+async fn resolve_job_id(
+    state: &AppState,
+    payload: &PitchLakeJobRequest,
+) -> Result<(String, Option<JobStatus>), sqlx::Error> {
+    let defaults = state
+        .pricing_config
+        .program_defaults(program_id(&payload.params));
+    let job_id = generate_job_id(payload, &defaults);
+    if let Some(job) = get_job_request(state.offchain_processor_db.clone(), &job_id).await? {
+        return Ok((job_id, Some(job.status)));
+    }
+
+    let legacy_job_id = generate_job_id_with_version(payload, JobIdVersion::V1, &defaults);
+    if let Some(job) = get_job_request(state.offchain_processor_db.clone(), &legacy_job_id).await? {
+        tracing::info!("Resolved request to legacy job_id: {}", legacy_job_id);
+        return Ok((legacy_job_id, Some(job.status)));
+    }
+
+    Ok((job_id, None))
 }
 
 // Handle existing jobs based on status
@@ -125,20 +358,33 @@ async fn handle_existing_job(
     status: JobStatus,
     job_id: String,
     payload: PitchLakeJobRequest,
-    starknet_account: FossilStarknetAccount,
 ) -> (StatusCode, Json<JobResponse>) {
+    if let Some(url) = payload.callback_url.clone() {
+        if let Err(e) = subscribe_webhook(state, &job_id, status, url).await {
+            return internal_server_error(e, job_id);
+        }
+    }
+
     match status {
-        JobStatus::Pending => job_response(
-            StatusCode::CONFLICT,
-            job_id,
-            "Job is already pending. Use the status endpoint to monitor progress.",
-        ),
         JobStatus::Completed => job_response(
             StatusCode::OK,
             job_id,
             "Job has already been completed. No further processing required.",
         ),
-        JobStatus::Failed => reprocess_failed_job(state, job_id, payload, starknet_account).await,
+        JobStatus::Failed | JobStatus::Cancelled => {
+            reprocess_failed_job(state, status, job_id, payload).await
+        }
+        JobStatus::Pending
+        | JobStatus::Queued
+        | JobStatus::FetchingData
+        | JobStatus::Computing
+        | JobStatus::Computed
+        | JobStatus::CallbackSubmitted
+        | JobStatus::CallbackConfirmed => job_response(
+            StatusCode::CONFLICT,
+            job_id,
+            "Job is already pending. Use the status endpoint to monitor progress.",
+        ),
     }
 }
 
@@ -147,86 +393,178 @@ async fn handle_new_job_request(
     state: &AppState,
     job_id: String,
     payload: PitchLakeJobRequest,
-    starknet_account: FossilStarknetAccount,
 ) -> (StatusCode, Json<JobResponse>) {
-    match create_job_request(
+    let request = match stored_request(&payload) {
+        Ok(request) => request,
+        Err(e) => return internal_server_error(e, job_id),
+    };
+
+    match create_queued_job_request(
         state.offchain_processor_db.clone(),
         &job_id,
-        JobStatus::Pending,
+        JobStatus::Queued,
+        &request,
+        payload.callback_url.as_deref(),
     )
     .await
     {
         Ok(_) => {
-            tracing::info!("New job request registered and processing initiated.");
-            let offchain_processor_db_clone = state.offchain_processor_db.clone();
-            let indexer_db_clone = state.indexer_db.clone();
-            let job_id_clone = job_id.clone();
-            let handle = Handle::current();
-
-            tokio::task::spawn_blocking(move || {
-                handle.block_on(process_job(
-                    offchain_processor_db_clone,
-                    indexer_db_clone,
-                    job_id_clone,
-                    payload,
-                    starknet_account,
-                ));
-            });
+            tracing::info!("New job request registered and queued for processing.");
+            state.job_notifier.notify_one();
 
             (
                 StatusCode::CREATED,
-                Json(JobResponse {
-                    job_id: job_id.clone(),
-                    message: Some(
-                        "New job request registered and processing initiated.".to_string(),
-                    ),
-                    status: Some(JobStatus::Pending),
-                }),
+                Json(JobResponse::new(
+                    job_id,
+                    Some("New job request registered and processing initiated.".to_string()),
+                    Some(JobStatus::Queued),
+                )),
             )
         }
         Err(e) => internal_server_error(e, job_id),
     }
 }
 
-// Helper to handle failed job reprocessing
+// Helper to handle failed or cancelled job reprocessing
 async fn reprocess_failed_job(
     state: &AppState,
+    status: JobStatus,
     job_id: String,
     payload: PitchLakeJobRequest,
-    starknet_account: FossilStarknetAccount,
 ) -> (StatusCode, Json<JobResponse>) {
-    if let Err(e) = update_job_status(
+    let request = match stored_request(&payload) {
+        Ok(request) => request,
+        Err(e) => return internal_server_error(e, job_id),
+    };
+
+    if let Err(e) = requeue_job_request(
+        state.offchain_processor_db.clone(),
+        &job_id,
+        JobStatus::Queued,
+        Some(&request),
+    )
+    .await
+    {
+        return internal_server_error(e, job_id);
+    }
+    state.job_notifier.notify_one();
+
+    let message = if status == JobStatus::Cancelled {
+        "Previous job request was cancelled. Reprocessing initiated."
+    } else {
+        "Previous job request failed. Reprocessing initiated."
+    };
+    job_response(StatusCode::OK, job_id, message)
+}
+
+// Re-run a failed job from the request stored with it, without the client resubmitting
+pub async fn retry_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+) -> (StatusCode, Json<JobResponse>) {
+    tracing::info!("Received retry request for job_id: {}", job_id);
+
+    let job = match get_job_request(state.offchain_processor_db.clone(), &job_id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return job_response(StatusCode::NOT_FOUND, job_id, "Job not found."),
+        Err(e) => return internal_server_error(e, job_id),
+    };
+
+    if !job.status.is_retryable() {
+        return job_response(
+            StatusCode::CONFLICT,
+            job_id,
+            "Only failed or cancelled jobs can be retried.",
+        );
+    }
+    if job.request.is_none() {
+        return job_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            job_id,
+            "No request stored for this job. Please resubmit it.",
+        );
+    }
+
+    if let Err(e) = requeue_job_request(
         state.offchain_processor_db.clone(),
         &job_id,
-        JobStatus::Pending,
+        JobStatus::Queued,
         None,
     )
     .await
     {
         return internal_server_error(e, job_id);
     }
-    let offchain_processor_db_clone = state.offchain_processor_db.clone();
-    let indexer_db_clone = state.indexer_db.clone();
-    let job_id_clone = job_id.clone();
-    let handle = Handle::current();
-
-    tokio::task::spawn_blocking(move || {
-        handle.block_on(process_job(
-            offchain_processor_db_clone,
-            indexer_db_clone,
-            job_id_clone,
-            payload,
-            starknet_account,
-        ));
-    });
+    state.job_notifier.notify_one();
 
     job_response(
         StatusCode::OK,
         job_id,
-        "Previous job request failed. Reprocessing initiated.",
+        "Reprocessing initiated from the stored request.",
     )
 }
 
+// Cancel a job that has not reached the vault yet
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+) -> (StatusCode, Json<JobResponse>) {
+    tracing::info!("Received cancel request for job_id: {}", job_id);
+
+    match get_job_request(state.offchain_processor_db.clone(), &job_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return job_response(StatusCode::NOT_FOUND, job_id, "Job not found."),
+        Err(e) => return internal_server_error(e, job_id),
+    }
+
+    match cancel_job_request(state.offchain_processor_db.clone(), &job_id).await {
+        Ok(()) => job_response(StatusCode::OK, job_id, "Job cancelled."),
+        Err(e) if is_invalid_status_transition(&e) => job_response(
+            StatusCode::CONFLICT,
+            job_id,
+            "Job can no longer be cancelled.",
+        ),
+        Err(e) => internal_server_error(e, job_id),
+    }
+}
+
+// Send the job's webhook to its subscribers again, e.g. after a receiver was down
+pub async fn replay_webhook(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+) -> (StatusCode, Json<JobResponse>) {
+    tracing::info!("Received webhook replay request for job_id: {}", job_id);
+
+    let job = match get_job_request(state.offchain_processor_db.clone(), &job_id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return job_response(StatusCode::NOT_FOUND, job_id, "Job not found."),
+        Err(e) => return internal_server_error(e, job_id),
+    };
+
+    if job.status.is_in_progress() {
+        return job_response(
+            StatusCode::CONFLICT,
+            job_id,
+            "Job is still in progress. Its webhook is sent once it finishes.",
+        );
+    }
+
+    match create_job_webhooks(state.offchain_processor_db.clone(), &job_id).await {
+        Ok(deliveries) if deliveries.is_empty() => job_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            job_id,
+            "No callback_url registered for this job.",
+        ),
+        Ok(deliveries) => {
+            for delivery in deliveries {
+                spawn_webhook_delivery(state.offchain_processor_db.clone(), delivery);
+            }
+            job_response(StatusCode::ACCEPTED, job_id, "Webhook replay initiated.")
+        }
+        Err(e) => internal_server_error(e, job_id),
+    }
+}
+
 // Helper to generate a JSON response
 fn job_response(
     status: StatusCode,
@@ -241,7 +579,10 @@ fn job_response(
 }
 
 // Handle internal server errors
-fn internal_server_error(error: sqlx::Error, job_id: String) -> (StatusCode, Json<JobResponse>) {
+fn internal_server_error(
+    error: impl std::fmt::Display + std::fmt::Debug,
+    job_id: String,
+) -> (StatusCode, Json<JobResponse>) {
     tracing::error!("Internal server error: {:?}", error);
     (
         StatusCode::INTERNAL_SERVER_ERROR,
@@ -253,14 +594,19 @@ fn internal_server_error(error: sqlx::Error, job_id: String) -> (StatusCode, Jso
     )
 }
 
-// Process the job and trigger the Starknet callback
-async fn process_job(
-    offchain_processor_db: Arc<OffchainProcessorDbConnection>,
-    indexer_db: Arc<IndexerDbConnection>,
+// Process the job and trigger the Starknet callback. `prefetched` headers,
+// e.g. shared by a batch, spare the job its own indexer queries. Once `lease`
+// is cancelled the job stops before sending its callback.
+//
+// Returns `true` once the job reached a final status, and `false` when it was
+// interrupted or its failure could not be recorded.
+pub(crate) async fn process_job(
+    state: AppState,
     job_id: String,
     payload: PitchLakeJobRequest,
-    starknet_account: FossilStarknetAccount,
-) {
+    prefetched: Option<PricingHeaders>,
+    lease: CancellationToken,
+) -> bool {
     let context = format!(
         "job_id={}, identifiers=[{}], twap=({},{}), cap_level=({},{}), reserve_price=({},{}), alpha={}, k={}, client_address={:#064x}, vault_address={:#064x}",
         job_id,
@@ -277,220 +623,344 @@ async fn process_job(
     tracing::info!("Starting job processing. {}", context);
     tracing::debug!("Payload received: {:?}. {}", payload, context);
 
-    let job_result = match fetch_headers(indexer_db.clone(), &payload).await {
-        Ok(Some((twap, cap_level, reserve_price))) => {
-            tracing::info!(
-                "Fetched block headers. Calculated values: TWAP = {}, Cap Level = {}, Reserve Price = {}. {}",
-                twap, cap_level, reserve_price, context
-            );
-
-            let result = PitchLakeResult {
-                twap: U256::from(twap as u128),
-                cap_level: (cap_level * 10_000.0) as u128,
-                reserve_price: U256::from(reserve_price as u128),
-            };
-
+    let offchain_processor_db = state.offchain_processor_db.clone();
+    match run_job(&state, &job_id, &payload, prefetched, &lease, &context).await {
+        Ok(()) => tracing::info!("Job processing finished successfully. {}", context),
+        Err(e) if is_interrupted(&e) => {
+            // Someone else moved the job on, e.g. it was cancelled or another
+            // worker took it over
+            tracing::warn!("Job processing stopped: {}. {}", e, context);
+            return false;
+        }
+        Err(e) => {
+            let error_msg = e.to_string();
+            tracing::error!("{}. {}", error_msg, context);
             if let Err(e) = update_job_status(
                 offchain_processor_db.clone(),
                 &job_id,
-                JobStatus::Completed,
+                JobStatus::Failed,
                 Some(serde_json::json!({
-                    "twap": twap,
-                    "cap_level": cap_level,
-                    "reserve_price": reserve_price,
+                    "error": error_msg
                 })),
             )
             .await
             {
+                // Left to a retry once the lease expires
                 tracing::error!("Failed to update job status: {:?}. {}", e, context);
-                return;
+                return false;
             }
-
-            tracing::info!(
-                "Job completed. Initiating Starknet callback to contract at address: {}. {}",
-                payload.client_info.client_address,
+            tracing::error!(
+                "Job processing failed. See previous errors for details. {}",
                 context
             );
+        }
+    }
 
-            let program_id = match Felt::from_hex(PITCH_LAKE_V1) {
-                Ok(id) => id,
-                Err(e) => {
-                    let error_msg = format!("Failed to parse program ID: {:?}", e);
-                    tracing::error!("{}. {}", error_msg, context);
-                    let _ = update_job_status(
-                        offchain_processor_db.clone(),
-                        &job_id,
-                        JobStatus::Failed,
-                        Some(serde_json::json!({
-                            "error": error_msg
-                        })),
-                    )
-                    .await;
-                    return;
-                }
-            };
-
-            let job_request = JobRequest {
-                vault_address: payload.client_info.vault_address,
-                timestamp: payload.client_info.timestamp.to_string(),
-                program_id,
-                alpha: payload.params.alpha,
-                k: payload.params.k,
-            };
-
-            tracing::debug!(
-                "Starknet callback calldata: Client Address = {:?}, Vault Address = {:?}, Timestamp = {}, Program ID = {}. {}",
-                payload.client_info.client_address,
-                payload.client_info.vault_address,
-                job_request.timestamp,
-                PITCH_LAKE_V1,
-                context
-            );
+    spawn_job_webhooks(offchain_processor_db, job_id);
+    true
+}
 
-            match starknet_account
-                .callback_to_contract(payload.client_info.client_address, &job_request, &result)
-                .await
-            {
-                Ok(_) => {
-                    tracing::info!("Job processing finished successfully. {}", context);
-                    true
-                }
-                Err(e) => {
-                    let error_msg = format!("Starknet callback failed. Error: {:?}", e);
-                    tracing::error!("{}. {}", error_msg, context);
-                    let _ = update_job_status(
-                        offchain_processor_db.clone(),
-                        &job_id,
-                        JobStatus::Failed,
-                        Some(serde_json::json!({
-                            "error": error_msg
-                        })),
-                    )
-                    .await;
-                    false
-                }
-            }
+// Walks the job through its lifecycle states. Any error fails the job.
+async fn run_job(
+    state: &AppState,
+    job_id: &str,
+    payload: &PitchLakeJobRequest,
+    prefetched: Option<PricingHeaders>,
+    lease: &CancellationToken,
+    context: &str,
+) -> Result<()> {
+    let offchain_processor_db = state.offchain_processor_db.clone();
+    let job = get_job_request(offchain_processor_db.clone(), job_id)
+        .await?
+        .ok_or_else(|| eyre!("Job {} no longer exists", job_id))?;
+
+    // A job that got as far as its callback before a restart is never computed
+    // again: a sent callback is confirmed, and one that was not recorded is
+    // sent from the stored result
+    let (pricing, pending_tx) = match (job.status, job.result, job.callback_tx_hash) {
+        (JobStatus::CallbackConfirmed, _, _) => {
+            set_job_status(offchain_processor_db, job_id, JobStatus::Completed).await?;
+            return Ok(());
         }
-        Ok(None) => {
-            let error_msg = "Failed to fetch headers or calculate pricing data";
-            tracing::error!("{}. {}", error_msg, context);
-            let _ = update_job_status(
-                offchain_processor_db.clone(),
-                &job_id,
-                JobStatus::Failed,
-                Some(serde_json::json!({
-                    "error": error_msg
-                })),
-            )
-            .await;
-            false
+        (JobStatus::CallbackSubmitted, Some(result), tx_hash) => {
+            let pricing = serde_json::from_value::<PricingResult>(result)?;
+            let tx_hash = tx_hash
+                .map(|tx_hash| {
+                    Felt::from_hex(&tx_hash)
+                        .map_err(|e| eyre!("Invalid stored callback tx hash {}: {:?}", tx_hash, e))
+                })
+                .transpose()?;
+            (pricing, tx_hash)
         }
-        Err(e) => {
-            let error_msg = format!("Error fetching headers: {:?}", e);
-            tracing::error!("{}. {}", error_msg, context);
-            let _ = update_job_status(
-                offchain_processor_db.clone(),
-                &job_id,
-                JobStatus::Failed,
-                Some(serde_json::json!({
-                    "error": error_msg
-                })),
-            )
-            .await;
-            false
+        (JobStatus::CallbackSubmitted, None, _) => {
+            return Err(eyre!("No result stored with the job to send its callback"));
         }
+        _ => (
+            compute_job(state, job_id, payload, prefetched, lease, context).await?,
+            None,
+        ),
     };
 
-    if job_result {
-        tracing::info!("Job processing finished successfully. {}", context);
-    } else {
-        tracing::error!(
-            "Job processing failed. See previous errors for details. {}",
-            context
-        );
+    let result = PitchLakeResult {
+        twap: U256::from(pricing.twap as u128),
+        cap_level: (pricing.cap_level * 10_000.0) as u128,
+        reserve_price: U256::from(pricing.reserve_price as u128),
+    };
+
+    tracing::info!(
+        "Job computed. Initiating Starknet callback to contract at address: {}. {}",
+        payload.client_info.client_address,
+        context
+    );
+
+    let program_id = program_id(&payload.params);
+
+    let job_request = JobRequest {
+        vault_address: payload.client_info.vault_address,
+        timestamp: payload.client_info.timestamp.to_string(),
+        program_id,
+        alpha: payload.params.alpha,
+        k: payload.params.k,
+    };
+
+    tracing::debug!(
+        "Starknet callback calldata: Client Address = {:?}, Vault Address = {:?}, Timestamp = {}, Program ID = {:#x}. {}",
+        payload.client_info.client_address,
+        payload.client_info.vault_address,
+        job_request.timestamp,
+        program_id,
+        context
+    );
+
+    // Only the worker holding the lease may send the callback
+    if lease.is_cancelled() {
+        return Err(LeaseLost.into());
     }
+
+    let starknet_account = FossilStarknetAccount::new()
+        .map_err(|e| eyre!("Failed to create Starknet account: {:?}", e))?;
+
+    deliver_callback(
+        offchain_processor_db,
+        &starknet_account,
+        job_id,
+        payload.client_info.client_address,
+        &job_request,
+        &result,
+        pending_tx,
+        &CallbackConfig::from_env(),
+        context,
+    )
+    .await
 }
 
-// Helper to fetch block headers in parallel
-async fn fetch_headers(
-    db: Arc<IndexerDbConnection>,
+// Fetches the block headers and calculates the pricing data, leaving the job
+// `Computed`. The simulation stops early once `lease` is cancelled.
+async fn compute_job(
+    state: &AppState,
+    job_id: &str,
     payload: &PitchLakeJobRequest,
-) -> Result<Option<(f64, f64, f64)>, eyre::Error> {
-    tracing::debug!("Fetching block headers for calculations.");
+    prefetched: Option<PricingHeaders>,
+    lease: &CancellationToken,
+    context: &str,
+) -> Result<PricingResult> {
+    let offchain_processor_db = state.offchain_processor_db.clone();
+    set_job_status(
+        offchain_processor_db.clone(),
+        job_id,
+        JobStatus::FetchingData,
+    )
+    .await?;
 
-    dotenv().ok();
-    let use_mock_pricing_data = env::var("USE_MOCK_PRICING_DATA")
-        .map_err(|_| eyre!("USE_MOCK_PRICING_DATA should be provided as env vars."))?;
+    let seed = payload
+        .params
+        .seed
+        .unwrap_or_else(|| seed_from_job_id(job_id));
+    let config = resolve_pricing_config(&state.pricing_config, &payload.params)?;
 
-    if use_mock_pricing_data.to_lowercase() == "true" {
+    let pricing = if state.mock_pricing_data {
         tracing::info!("Using mock pricing data");
-        return Ok(Some((14732102267.474916, 440.0, 2597499408.638207)));
+        set_job_status(offchain_processor_db.clone(), job_id, JobStatus::Computing).await?;
+        mock_pricing_result(seed, config)
+    } else {
+        let headers = match prefetched {
+            Some(headers) => headers,
+            None => fetch_headers(state.indexer_db.clone(), &payload.params)
+                .await
+                .map_err(|e| eyre!("Error fetching headers: {:?}", e))?,
+        };
+
+        set_job_status(offchain_processor_db.clone(), job_id, JobStatus::Computing).await?;
+        calculate_pricing_data(&payload.params, headers, seed, config, lease).await?
+    };
+
+    tracing::info!(
+        "Calculated values: TWAP = {}, Cap Level = {}, Reserve Price = {} (std error {:?}), Seed = {}. {}",
+        pricing.twap,
+        pricing.cap_level,
+        pricing.reserve_price,
+        pricing.reserve_price_diagnostics.as_ref().map(|d| d.std_error),
+        seed,
+        context
+    );
+
+    update_job_status(
+        offchain_processor_db,
+        job_id,
+        JobStatus::Computed,
+        Some(serde_json::to_value(&pricing)?),
+    )
+    .await?;
+
+    Ok(pricing)
+}
+
+// `true` if the job was moved out from under the worker, e.g. by a cancellation
+// or another worker taking over its lease
+fn is_interrupted(error: &eyre::Error) -> bool {
+    error.is::<LeaseLost>()
+        || error.is::<PricingCancelled>()
+        || error
+            .downcast_ref::<sqlx::Error>()
+            .is_some_and(is_invalid_status_transition)
+}
+
+// TWAP, cap level and reserve price reported when `USE_MOCK_PRICING_DATA` is set
+pub(crate) const MOCK_PRICING_DATA: (f64, f64, f64) =
+    (14732102267.474916, 440.0, 2597499408.638207);
+
+pub(crate) fn mock_pricing_result(seed: u64, config: PricingConfig) -> PricingResult {
+    let (twap, cap_level, reserve_price) = MOCK_PRICING_DATA;
+    PricingResult {
+        twap,
+        cap_level,
+        reserve_price,
+        volatility: None,
+        reserve_price_diagnostics: None,
+        reserve_price_sensitivities: None,
+        analytic_cross_check: None,
+        data_quality: None,
+        outliers: None,
+        seed: Some(seed),
+        config: Some(config),
     }
+}
+
+// Helper to fetch block headers in parallel
+pub(crate) async fn fetch_headers(
+    db: Arc<IndexerDbConnection>,
+    params: &PitchLakeJobRequestParams,
+) -> Result<PricingHeaders> {
+    tracing::debug!("Fetching block headers for calculations.");
 
     let (twap_headers, cap_level_headers, reserve_price_headers) = join!(
         get_block_headers_by_time_range(
             db.clone(),
-            payload.params.twap.0.to_string(),
-            payload.params.twap.1.to_string()
+            params.twap.0.to_string(),
+            params.twap.1.to_string()
         ),
         get_block_headers_by_time_range(
             db.clone(),
-            payload.params.cap_level.0.to_string(),
-            payload.params.cap_level.1.to_string()
+            params.cap_level.0.to_string(),
+            params.cap_level.1.to_string()
         ),
         get_block_headers_by_time_range(
             db.clone(),
-            payload.params.reserve_price.0.to_string(),
-            payload.params.reserve_price.1.to_string()
+            params.reserve_price.0.to_string(),
+            params.reserve_price.1.to_string()
         )
     );
 
-    let alpha = payload.params.alpha;
-    let k = payload.params.k;
-
-    match (twap_headers, cap_level_headers, reserve_price_headers) {
-        (Ok(twap), Ok(cap_level), Ok(reserve)) => {
-            tracing::debug!("Block headers fetched successfully.");
+    let headers = (twap_headers?, cap_level_headers?, reserve_price_headers?);
+    tracing::debug!("Block headers fetched successfully.");
 
-            let now = Instant::now();
-            tracing::info!("Started processing...");
-
-            // Get twap value
-            let twap = calculate_twap(twap);
+    Ok(headers)
+}
 
-            // Get cap level value
-            let cap_level = calculate_cap_level(alpha, k, cap_level).await;
-
-            // Get reserve price future
-            let reserve_price = match cap_level {
-                Ok(cl) => calculate_reserve_price(reserve, cl, k),
-                Err(e) => {
-                    tracing::error!("No cap level to pass to reserve price {}.", e);
-                    return Err(e);
-                }
-            };
-
-            // Convert cap level back into pseudo-future to satisfy `join!`
-            let cap_level = async { cap_level };
+// Fetches the headers of several jobs at once, one query per group of
+// overlapping ranges. `None` when the jobs have to fetch their own.
+pub(crate) async fn prefetch_headers(
+    db: Arc<IndexerDbConnection>,
+    payloads: &[&PitchLakeJobRequest],
+) -> Option<HeaderRanges> {
+    let ranges = payloads.iter().flat_map(|payload| {
+        [
+            payload.params.twap,
+            payload.params.cap_level,
+            payload.params.reserve_price,
+        ]
+    });
+    match HeaderRanges::fetch(db, ranges).await {
+        Ok(headers) => {
+            tracing::info!(
+                "Fetched block headers for {} jobs with {} queries",
+                payloads.len(),
+                headers.len()
+            );
+            Some(headers)
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Failed to prefetch block headers, jobs fetch their own: {:?}",
+                e
+            );
+            None
+        }
+    }
+}
 
-            let results = join!(twap, cap_level, reserve_price);
+// The slice of prefetched headers a job needs
+pub(crate) fn pricing_headers(
+    headers: &HeaderRanges,
+    params: &PitchLakeJobRequestParams,
+) -> Option<PricingHeaders> {
+    Some((
+        headers.get(params.twap)?,
+        headers.get(params.cap_level)?,
+        headers.get(params.reserve_price)?,
+    ))
+}
 
-            let elapsed = now.elapsed();
-            tracing::info!("Elapsed: {:.2?}", elapsed);
+// Checks the quality of fetched headers, then prices them with the model
+// registered for the request's program ID until `cancel` fires
+pub(crate) async fn calculate_pricing_data(
+    params: &PitchLakeJobRequestParams,
+    headers: PricingHeaders,
+    seed: u64,
+    config: PricingConfig,
+    cancel: &CancellationToken,
+) -> Result<PricingResult> {
+    let data_quality = DataQualityReport::new(&headers, params);
+    data_quality.check(&config)?;
+    let outliers = OutlierReport::new(&headers, &config);
+
+    let output = PricingModelRegistry::global()
+        .model(program_id(params))?
+        .price(headers, params, seed, &config, cancel)
+        .await?;
+
+    Ok(PricingResult {
+        twap: output.twap,
+        cap_level: output.cap_level,
+        reserve_price: output.reserve_price.reserve_price,
+        volatility: output.volatility,
+        reserve_price_diagnostics: Some(output.reserve_price.diagnostics),
+        reserve_price_sensitivities: output.reserve_price.sensitivities,
+        analytic_cross_check: output.reserve_price.analytic,
+        data_quality: Some(data_quality),
+        outliers: Some(outliers),
+        seed: Some(seed),
+        config: Some(config),
+    })
+}
 
-            match results {
-                (Ok(twap), Ok(cap_level), Ok(reserve_price)) => {
-                    Ok(Some((twap, cap_level, reserve_price)))
-                }
-                _ => Ok(None),
-            }
-        }
-        _ => Ok(None),
-    }
+// The program the request is priced for, Pitch Lake v1 unless it names another
+pub(crate) fn program_id(params: &PitchLakeJobRequestParams) -> Felt {
+    params.program_id.unwrap_or_else(pitch_lake_v1)
 }
 
 // Validate the provided time ranges
-fn validate_time_ranges(
+pub(crate) fn validate_time_ranges(
     params: &PitchLakeJobRequestParams,
 ) -> Result<(), (StatusCode, JobResponse)> {
     let validations = [
@@ -514,12 +984,62 @@ fn validate_time_ranges(
     Ok(())
 }
 
+// The pricing model parameters a request runs with: built-in defaults, then
+// `PRICING_CONFIG_FILE`, then the request's own overrides
+pub(crate) fn resolve_pricing_config(
+    pricing_config: &PricingConfigFile,
+    params: &PitchLakeJobRequestParams,
+) -> Result<PricingConfig> {
+    pricing_config.resolve(program_id(params), params.pricing_config.as_ref())
+}
+
+// Reject program IDs no pricing model is registered for
+pub(crate) fn validate_program_id(
+    params: &PitchLakeJobRequestParams,
+) -> Result<(), (StatusCode, JobResponse)> {
+    let program_id = program_id(params);
+    if PricingModelRegistry::global().contains(program_id) {
+        return Ok(());
+    }
+    Err((
+        StatusCode::BAD_REQUEST,
+        JobResponse::new(
+            String::new(),
+            Some(format!("Unknown program_id: {:#x}", program_id)),
+            None,
+        ),
+    ))
+}
+
+// Reject pricing model parameters that can't be computed with before the job starts
+pub(crate) fn validate_pricing_config(
+    pricing_config: &PricingConfigFile,
+    params: &PitchLakeJobRequestParams,
+) -> Result<(), (StatusCode, JobResponse)> {
+    resolve_pricing_config(pricing_config, params)
+        .map(|_| ())
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                JobResponse::new(
+                    String::new(),
+                    Some(format!("Invalid pricing_config: {}", e)),
+                    None,
+                ),
+            )
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::handlers::fixtures::TestContext;
+    use crate::pricing_data::config::PricingConfigOverrides;
     use crate::types::{ClientInfo, PitchLakeJobRequest, PitchLakeJobRequestParams};
     use axum::http::StatusCode;
+    use db_access::queries::{
+        get_job_webhooks, get_webhook_deliveries, record_callback_submission,
+    };
     use starknet::core::types::Felt;
 
     #[tokio::test]
@@ -534,12 +1054,16 @@ mod tests {
                 reserve_price: (0, 100),
                 alpha: 2500,
                 k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
             },
             client_info: ClientInfo {
                 client_address: Felt::from_hex("0x123").unwrap(),
                 vault_address: Felt::from_hex("0x456").unwrap(),
                 timestamp: 0,
             },
+            callback_url: None,
         };
 
         let (status, Json(response)) = ctx.get_pricing_data(payload).await;
@@ -564,15 +1088,19 @@ mod tests {
                 reserve_price: (0, 100),
                 alpha: 2500,
                 k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
             },
             client_info: ClientInfo {
                 client_address: Felt::from_hex("0x123").unwrap(),
                 vault_address: Felt::from_hex("0x456").unwrap(),
                 timestamp: 0,
             },
+            callback_url: None,
         };
 
-        let job_id = generate_job_id(&payload.identifiers, &payload.params);
+        let job_id = generate_job_id(&payload, &PricingConfig::default());
         ctx.create_job(&job_id, JobStatus::Pending).await;
 
         let (status, Json(response)) = ctx.get_pricing_data(payload).await;
@@ -597,15 +1125,19 @@ mod tests {
                 reserve_price: (0, 100),
                 alpha: 2500,
                 k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
             },
             client_info: ClientInfo {
                 client_address: Felt::from_hex("0x123").unwrap(),
                 vault_address: Felt::from_hex("0x456").unwrap(),
                 timestamp: 0,
             },
+            callback_url: None,
         };
 
-        let job_id = generate_job_id(&payload.identifiers, &payload.params);
+        let job_id = generate_job_id(&payload, &PricingConfig::default());
         ctx.create_job(&job_id, JobStatus::Completed).await;
 
         let (status, Json(response)) = ctx.get_pricing_data(payload).await;
@@ -618,6 +1150,24 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_get_pricing_data_ignores_overrides_of_default_values() {
+        let ctx = TestContext::new().await;
+        let mut payload = vault_request("0x456");
+        let job_id = generate_job_id(&payload, &PricingConfig::default());
+        ctx.create_job(&job_id, JobStatus::Completed).await;
+
+        payload.params.pricing_config = Some(PricingConfigOverrides {
+            num_paths: Some(PricingConfig::default().num_paths),
+            antithetic: Some(PricingConfig::default().antithetic),
+            ..Default::default()
+        });
+        let (status, Json(response)) = ctx.get_pricing_data(payload).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response.job_id, job_id);
+    }
+
     #[tokio::test]
     async fn test_get_pricing_data_failed_job() {
         let ctx = TestContext::new().await;
@@ -630,15 +1180,19 @@ mod tests {
                 reserve_price: (0, 100),
                 alpha: 2500,
                 k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
             },
             client_info: ClientInfo {
                 client_address: Felt::from_hex("0x123").unwrap(),
                 vault_address: Felt::from_hex("0x456").unwrap(),
                 timestamp: 0,
             },
+            callback_url: None,
         };
 
-        let job_id = generate_job_id(&payload.identifiers, &payload.params);
+        let job_id = generate_job_id(&payload, &PricingConfig::default());
         ctx.create_job(&job_id, JobStatus::Failed).await;
 
         let (status, Json(response)) = ctx.get_pricing_data(payload).await;
@@ -651,6 +1205,183 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_get_pricing_data_resolves_legacy_job_id() {
+        let ctx = TestContext::new().await;
+
+        let payload = PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 0,
+            },
+            callback_url: None,
+        };
+
+        let legacy_job_id =
+            generate_job_id_with_version(&payload, JobIdVersion::V1, &PricingConfig::default());
+        ctx.create_job(&legacy_job_id, JobStatus::Completed).await;
+
+        let (status, Json(response)) = ctx.get_pricing_data(payload).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response.job_id, legacy_job_id);
+    }
+
+    #[tokio::test]
+    async fn test_retry_failed_job_from_stored_request() {
+        let ctx = TestContext::new().await;
+
+        let payload = PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 0,
+            },
+            callback_url: None,
+        };
+
+        let (_, Json(created)) = ctx.get_pricing_data(payload).await;
+        update_job_status(
+            ctx.offchain_processor_db.clone(),
+            &created.job_id,
+            JobStatus::Failed,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let (status, Json(response)) = ctx.retry_job(&created.job_id).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response.message.unwrap_or_default(),
+            "Reprocessing initiated from the stored request."
+        );
+        let job = get_job_request(ctx.offchain_processor_db.clone(), &created.job_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Queued);
+    }
+
+    #[tokio::test]
+    async fn test_retry_job_without_stored_request() {
+        let ctx = TestContext::new().await;
+        ctx.create_job("legacy_job_id", JobStatus::Failed).await;
+
+        let (status, _) = ctx.retry_job("legacy_job_id").await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_retry_job_not_failed() {
+        let ctx = TestContext::new().await;
+        ctx.create_job("pending_job_id", JobStatus::Pending).await;
+
+        let (status, _) = ctx.retry_job("pending_job_id").await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_queued_job() {
+        let ctx = TestContext::new().await;
+
+        let payload = PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 0,
+            },
+            callback_url: None,
+        };
+
+        let (_, Json(created)) = ctx.get_pricing_data(payload.clone()).await;
+        let (status, _) = ctx.cancel_job(&created.job_id).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let job = get_job_request(ctx.offchain_processor_db.clone(), &created.job_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Cancelled);
+
+        // Resubmitting a cancelled job queues it again
+        let (status, Json(response)) = ctx.get_pricing_data(payload).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            response.message.unwrap_or_default(),
+            "Previous job request was cancelled. Reprocessing initiated."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_completed_job() {
+        let ctx = TestContext::new().await;
+        ctx.create_job("completed_job_id", JobStatus::Completed)
+            .await;
+
+        let (status, Json(response)) = ctx.cancel_job("completed_job_id").await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            response.message.unwrap_or_default(),
+            "Job can no longer be cancelled."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_status_transition_is_rejected() {
+        let ctx = TestContext::new().await;
+        ctx.create_job("completed_job_id", JobStatus::Completed)
+            .await;
+
+        let error = set_job_status(
+            ctx.offchain_processor_db.clone(),
+            "completed_job_id",
+            JobStatus::Computing,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(is_invalid_status_transition(&error));
+    }
+
     #[tokio::test]
     async fn test_get_pricing_data_invalid_params() {
         let ctx = TestContext::new().await;
@@ -663,12 +1394,16 @@ mod tests {
                 reserve_price: (0, 100),
                 alpha: 2500,
                 k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
             },
             client_info: ClientInfo {
                 client_address: Felt::from_hex("0x123").unwrap(),
                 vault_address: Felt::from_hex("0x456").unwrap(),
                 timestamp: 0,
             },
+            callback_url: None,
         };
 
         let (status, Json(response)) = ctx.get_pricing_data(payload).await;
@@ -679,4 +1414,404 @@ mod tests {
             "Invalid time range for TWAP calculation."
         );
     }
+
+    #[tokio::test]
+    async fn test_get_pricing_data_invalid_callback_url() {
+        let ctx = TestContext::new().await;
+
+        let payload = PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 0,
+            },
+            callback_url: Some("ftp://keeper.example/hook".to_string()),
+        };
+
+        let (status, Json(response)) = ctx.get_pricing_data(payload).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.message.unwrap_or_default(),
+            "Invalid callback_url."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_pricing_data_rejects_internal_callback_url() {
+        let ctx = TestContext::new().await;
+
+        let mut payload = vault_request("0x456");
+        payload.callback_url = Some("http://169.254.169.254/latest/meta-data".to_string());
+        let (status, _) = ctx.get_pricing_data(payload).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let mut payload = vault_request("0x456");
+        payload.callback_url = Some("http://localhost:8080/hook".to_string());
+        let (status, _) = ctx.get_pricing_data(payload).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_every_submitter_of_a_job_is_subscribed() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+
+        let mut first = vault_request("0x456");
+        first.callback_url = Some("https://first.example/hook".to_string());
+        let mut second = vault_request("0x456");
+        second.callback_url = Some("https://second.example/hook".to_string());
+
+        let (status, Json(created)) = ctx.get_pricing_data(first).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, Json(again)) = ctx.get_pricing_data(second).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(created.job_id, again.job_id);
+
+        assert_eq!(
+            get_job_webhooks(db.clone(), &created.job_id).await.unwrap(),
+            vec![
+                "https://first.example/hook".to_string(),
+                "https://second.example/hook".to_string()
+            ]
+        );
+        let stored = get_job_request(db, &created.job_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .request
+            .unwrap();
+        assert!(stored.get("callback_url").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_pricing_data_invalid_pricing_config() {
+        let ctx = TestContext::new().await;
+
+        let payload = PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: Some(PricingConfigOverrides {
+                    num_paths: Some(0),
+                    ..Default::default()
+                }),
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 0,
+            },
+            callback_url: None,
+        };
+
+        let (status, Json(response)) = ctx.get_pricing_data(payload).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(response
+            .message
+            .unwrap_or_default()
+            .starts_with("Invalid pricing_config: num_paths must be between 1 and"));
+    }
+
+    #[tokio::test]
+    async fn test_get_pricing_data_unknown_program_id() {
+        let ctx = TestContext::new().await;
+
+        let payload = PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: Some(Felt::from_hex("0x2a").unwrap()),
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 0,
+            },
+            callback_url: None,
+        };
+
+        let (status, Json(response)) = ctx.get_pricing_data(payload).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.message,
+            Some("Unknown program_id: 0x2a".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_webhook_logs_new_delivery() {
+        let ctx = TestContext::new().await;
+
+        let payload = PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 0,
+            },
+            callback_url: Some("https://keeper.example/hook".to_string()),
+        };
+
+        let (_, Json(created)) = ctx.get_pricing_data(payload).await;
+        let (status, _) = ctx.replay_webhook(&created.job_id).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        ctx.cancel_job(&created.job_id).await;
+        let (status, Json(response)) = ctx.replay_webhook(&created.job_id).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(
+            response.message.unwrap_or_default(),
+            "Webhook replay initiated."
+        );
+
+        let deliveries = get_webhook_deliveries(ctx.offchain_processor_db.clone(), &created.job_id)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].url, "https://keeper.example/hook");
+        assert_eq!(deliveries[0].payload["status"], "Cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_replay_webhook_without_callback_url() {
+        let ctx = TestContext::new().await;
+        ctx.create_job("failed_job_id", JobStatus::Failed).await;
+
+        let (status, _) = ctx.replay_webhook("failed_job_id").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, _) = ctx.replay_webhook("missing_job_id").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_job_uses_the_mock_switch_of_the_state() {
+        let mut ctx = TestContext::new().await;
+        ctx.app_state.mock_pricing_data = true;
+        ctx.create_job("mock_job", JobStatus::Queued).await;
+
+        let pricing = compute_job(
+            &ctx.app_state,
+            "mock_job",
+            &vault_request("0x456"),
+            None,
+            &CancellationToken::new(),
+            "",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(pricing.twap, MOCK_PRICING_DATA.0);
+        let job = get_job_request(ctx.offchain_processor_db.clone(), "mock_job")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Computed);
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_callback_resumes_to_completed() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+        ctx.create_job("confirmed_job", JobStatus::CallbackConfirmed)
+            .await;
+
+        run_job(
+            &ctx.app_state,
+            "confirmed_job",
+            &vault_request("0x456"),
+            None,
+            &CancellationToken::new(),
+            "",
+        )
+        .await
+        .unwrap();
+
+        let job = get_job_request(db, "confirmed_job").await.unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_submitted_callback_resumes_without_recomputing() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+        let result =
+            serde_json::to_value(mock_pricing_result(1, PricingConfig::default())).unwrap();
+
+        ctx.create_job_with_result("sent_job", JobStatus::Computed, result.clone())
+            .await;
+        record_callback_submission(db.clone(), "sent_job", "0x1")
+            .await
+            .unwrap();
+        ctx.create_job_with_result("unsent_job", JobStatus::CallbackSubmitted, result)
+            .await;
+        ctx.create_job("resultless_job", JobStatus::CallbackSubmitted)
+            .await;
+
+        // With the lease gone both jobs stop right before the callback, instead
+        // of being sent back to fetching data
+        let lost = CancellationToken::new();
+        lost.cancel();
+        for job_id in ["sent_job", "unsent_job"] {
+            let error = run_job(
+                &ctx.app_state,
+                job_id,
+                &vault_request("0x456"),
+                None,
+                &lost,
+                "",
+            )
+            .await
+            .unwrap_err();
+            assert!(error.is::<LeaseLost>(), "{}: {:?}", job_id, error);
+            let job = get_job_request(db.clone(), job_id).await.unwrap().unwrap();
+            assert_eq!(job.status, JobStatus::CallbackSubmitted);
+        }
+
+        let error = run_job(
+            &ctx.app_state,
+            "resultless_job",
+            &vault_request("0x456"),
+            None,
+            &lost,
+            "",
+        )
+        .await
+        .unwrap_err();
+        assert!(!is_interrupted(&error));
+    }
+
+    fn vault_request(vault_address: &str) -> PitchLakeJobRequest {
+        PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex(vault_address).unwrap(),
+                timestamp: 0,
+            },
+            callback_url: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_pricing_data_dedupes_and_answers_per_request() {
+        let ctx = TestContext::new().await;
+        ctx.create_job(
+            &generate_job_id(&vault_request("0x789"), &PricingConfig::default()),
+            JobStatus::Completed,
+        )
+        .await;
+
+        let mut invalid = vault_request("0xabc");
+        invalid.params.twap = (100, 0);
+        let payloads = vec![
+            vault_request("0x456"),
+            vault_request("0x789"),
+            vault_request("0x456"),
+            invalid,
+            vault_request("0xdef"),
+        ];
+
+        let (status, Json(response)) = ctx.get_batch_pricing_data(payloads).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let codes: Vec<_> = response.jobs.iter().map(|item| item.code).collect();
+        assert_eq!(codes, vec![201, 200, 201, 400, 201]);
+        assert_eq!(response.jobs[0].job.job_id, response.jobs[2].job.job_id);
+        assert_eq!(
+            response.jobs[2].job.message.as_deref(),
+            Some("Same job as request 0 of this batch.")
+        );
+        assert_eq!(response.jobs[1].job.status, Some(JobStatus::Completed));
+        assert_eq!(
+            response.jobs[3].job.message.as_deref(),
+            Some("Invalid time range for TWAP calculation.")
+        );
+
+        // Both new jobs were queued together and are claimed together
+        let db = ctx.offchain_processor_db.clone();
+        let first = db_access::queries::claim_next_job(db.clone(), "worker-a", 60.0)
+            .await
+            .unwrap()
+            .unwrap();
+        let rest = db_access::queries::claim_batch_jobs(
+            db.clone(),
+            first.batch_id.as_deref().unwrap(),
+            "worker-a",
+            60.0,
+            100,
+        )
+        .await
+        .unwrap();
+        let mut claimed: Vec<_> = std::iter::once(first.job_id)
+            .chain(rest.into_iter().map(|job| job.job_id))
+            .collect();
+        claimed.sort();
+        let mut expected = vec![
+            response.jobs[0].job.job_id.clone(),
+            response.jobs[4].job.job_id.clone(),
+        ];
+        expected.sort();
+        assert_eq!(claimed, expected);
+    }
+
+    #[tokio::test]
+    async fn test_batch_pricing_data_rejects_empty_batch() {
+        let ctx = TestContext::new().await;
+
+        let (status, Json(response)) = ctx.get_batch_pricing_data(Vec::new()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(response.jobs.is_empty());
+        assert_eq!(
+            response.message.as_deref(),
+            Some("A batch must contain between 1 and 100 requests.")
+        );
+    }
 }
@@ -0,0 +1,355 @@
+use std::{convert::Infallible, sync::Arc};
+
+use crate::types::{parse_job_result, ErrorResponse, JobEventResponse};
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use db_access::{
+    models::JobStatusEvent,
+    queries::{get_job_request, get_job_status_events},
+    OffchainProcessorDbConnection,
+};
+use eyre::Result;
+use tokio::sync::{
+    broadcast::{error::RecvError, Receiver},
+    mpsc,
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+// Stream every status change of a job as Server-Sent Events. The stream
+// closes after the event that finishes the job, and a finished job without
+// newer events answers 204 so EventSource stops reconnecting.
+pub async fn get_job_status_events(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    // Resume after the last event a reconnecting client saw
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(0);
+
+    tracing::info!(
+        "Streaming status events for job_id: {} (last_event_id={})",
+        job_id,
+        last_event_id
+    );
+
+    // Subscribe before reading the backlog so nothing falls in between
+    let receiver = state.job_events.subscribe();
+
+    let job = match get_job_request(state.offchain_processor_db.clone(), &job_id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            tracing::info!("Job not found for job_id: {}", job_id);
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Job not found".to_string(),
+                }),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            tracing::error!("Failed to get job status for job_id {}: {:?}", job_id, e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "An internal error occurred. Please try again later.".to_string(),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    let backlog =
+        match get_job_status_events(state.offchain_processor_db.clone(), &job_id, last_event_id)
+            .await
+        {
+            Ok(backlog) => backlog,
+            Err(e) => {
+                tracing::error!("Failed to load status events for job {}: {:?}", job_id, e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: "An internal error occurred. Please try again later.".to_string(),
+                    }),
+                )
+                    .into_response();
+            }
+        };
+
+    // Nothing new for a job that is already done
+    if backlog.is_empty() && !job.status.is_in_progress() {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+
+    let (sender, events) = mpsc::channel(16);
+    tokio::spawn(forward_job_events(
+        state.offchain_processor_db.clone(),
+        job_id,
+        last_event_id,
+        backlog,
+        receiver,
+        sender,
+    ));
+
+    Sse::new(ReceiverStream::new(events))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+// Replays the backlog of events after `last_event_id`, then forwards live
+// changes until the job finishes or the client goes away.
+async fn forward_job_events(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: String,
+    mut last_event_id: i64,
+    mut backlog: Vec<JobStatusEvent>,
+    mut receiver: Receiver<JobStatusEvent>,
+    sender: mpsc::Sender<Result<Event, Infallible>>,
+) {
+    loop {
+        for event in backlog.drain(..) {
+            last_event_id = event.id;
+            let finishes_job = !event.to_status.is_in_progress();
+
+            match to_sse_event(db.clone(), event).await {
+                Ok(event) => {
+                    if sender.send(Ok(event)).await.is_err() {
+                        tracing::debug!("Client stopped listening to job {}", job_id);
+                        return;
+                    }
+                }
+                Err(e) => tracing::error!("Failed to build event for job {}: {:?}", job_id, e),
+            }
+
+            if finishes_job {
+                return;
+            }
+        }
+
+        // Waiting on the sender too notices a client that left while the job is idle
+        let received = tokio::select! {
+            received = receiver.recv() => received,
+            _ = sender.closed() => {
+                tracing::debug!("Client stopped listening to job {}", job_id);
+                return;
+            }
+        };
+
+        match received {
+            Ok(event) if event.job_id == job_id && event.id > last_event_id => backlog.push(event),
+            Ok(_) => {}
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!(
+                    "Status stream for job {} lagged by {} events, catching up",
+                    job_id,
+                    skipped
+                );
+                match get_job_status_events(db.clone(), &job_id, last_event_id).await {
+                    Ok(missed) => backlog = missed,
+                    Err(e) => {
+                        tracing::error!("Failed to load status events for job {}: {:?}", job_id, e);
+                        return;
+                    }
+                }
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn to_sse_event(
+    db: Arc<OffchainProcessorDbConnection>,
+    event: JobStatusEvent,
+) -> Result<Event> {
+    let mut response = JobEventResponse {
+        job_id: event.job_id,
+        status: event.to_status,
+        previous_status: event.from_status,
+        changed_at: event.changed_at,
+        result: None,
+        error: None,
+        tx_hash: None,
+    };
+
+    if !event.to_status.is_in_progress() {
+        if let Some(job) = get_job_request(db, &response.job_id).await? {
+            (response.result, response.error) =
+                job.result.map(parse_job_result).unwrap_or_default();
+            response.tx_hash = job.callback_tx_hash;
+        }
+    }
+
+    Ok(Event::default()
+        .id(event.id.to_string())
+        .event("status")
+        .json_data(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{handlers::fixtures::TestContext, types::JobEventResponse};
+    use axum::http::StatusCode;
+    use db_access::{
+        models::JobStatus,
+        queries::{set_job_status, update_job_status},
+    };
+    use serde_json::json;
+
+    // Pulls the (id, payload) pairs out of a raw SSE body
+    fn parse_events(body: &str) -> Vec<(i64, JobEventResponse)> {
+        body.split("\n\n")
+            .filter_map(|frame| {
+                let mut id = None;
+                let mut data = None;
+                for line in frame.lines() {
+                    if let Some(value) = line.strip_prefix("id: ") {
+                        id = value.parse().ok();
+                    } else if let Some(value) = line.strip_prefix("data: ") {
+                        data = serde_json::from_str(value).ok();
+                    }
+                }
+                Some((id?, data?))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_job_events_not_found() {
+        let ctx = TestContext::new().await;
+
+        let (status, _) = ctx.get_job_status_events("missing_job", None).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_job_events_stream_until_finished() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+        ctx.create_job("event_job", JobStatus::Queued).await;
+
+        let transitions = async {
+            // Give the stream time to replay the backlog first
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            set_job_status(db.clone(), "event_job", JobStatus::FetchingData)
+                .await
+                .unwrap();
+            update_job_status(
+                db.clone(),
+                "event_job",
+                JobStatus::Failed,
+                Some(json!({ "error": "No block headers in range" })),
+            )
+            .await
+            .unwrap();
+        };
+
+        let ((status, body), ()) = tokio::time::timeout(Duration::from_secs(10), async {
+            tokio::join!(ctx.get_job_status_events("event_job", None), transitions)
+        })
+        .await
+        .expect("stream should close once the job fails");
+        assert_eq!(status, StatusCode::OK);
+
+        let events = parse_events(&body);
+        let statuses: Vec<_> = events.iter().map(|(_, event)| event.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                JobStatus::Queued,
+                JobStatus::FetchingData,
+                JobStatus::Failed
+            ]
+        );
+        let (_, last) = events.last().unwrap();
+        assert_eq!(
+            last.error.as_ref().unwrap().message,
+            "No block headers in range"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_job_events_resume_after_last_event_id() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+        ctx.create_job("event_job", JobStatus::Queued).await;
+        for status in [
+            JobStatus::FetchingData,
+            JobStatus::Computing,
+            JobStatus::Failed,
+        ] {
+            set_job_status(db.clone(), "event_job", status)
+                .await
+                .unwrap();
+        }
+
+        let (_, body) = ctx.get_job_status_events("event_job", None).await;
+        let events = parse_events(&body);
+        assert_eq!(events.len(), 4);
+
+        let (_, body) = ctx
+            .get_job_status_events("event_job", Some(events[1].0))
+            .await;
+        let statuses: Vec<_> = parse_events(&body)
+            .into_iter()
+            .map(|(_, event)| event.status)
+            .collect();
+        assert_eq!(statuses, vec![JobStatus::Computing, JobStatus::Failed]);
+    }
+
+    #[tokio::test]
+    async fn test_job_events_finished_job_without_new_events() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+        ctx.create_job("event_job", JobStatus::Queued).await;
+        set_job_status(db.clone(), "event_job", JobStatus::Cancelled)
+            .await
+            .unwrap();
+
+        let (_, body) = ctx.get_job_status_events("event_job", None).await;
+        let last_event_id = parse_events(&body).last().unwrap().0;
+
+        let (status, body) = ctx
+            .get_job_status_events("event_job", Some(last_event_id))
+            .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_job_events_stop_when_client_leaves_idle_job() {
+        let ctx = TestContext::new().await;
+        ctx.create_job("idle_job", JobStatus::Queued).await;
+
+        // No further status change is coming for the job
+        let (_events, receiver) = tokio::sync::broadcast::channel(16);
+        let (sender, client) = tokio::sync::mpsc::channel(16);
+        drop(client);
+
+        let forwarding = super::forward_job_events(
+            ctx.offchain_processor_db.clone(),
+            "idle_job".to_string(),
+            i64::MAX,
+            Vec::new(),
+            receiver,
+            sender,
+        );
+        tokio::time::timeout(Duration::from_secs(5), forwarding)
+            .await
+            .expect("forwarding should stop once the client is gone");
+    }
+}
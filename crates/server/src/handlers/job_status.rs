@@ -1,9 +1,11 @@
-use crate::types::{ErrorResponse, GetJobStatusResponseEnum, JobResponse};
+use crate::types::{
+    parse_job_result, ErrorResponse, GetJobStatusResponseEnum, JobResponse, PitchLakeJobRequest,
+};
 use crate::AppState;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::Json;
-use db_access::queries::get_job_request;
+use db_access::queries::{get_job_request, get_job_status_history};
 
 #[axum::debug_handler]
 pub async fn get_job_status(
@@ -12,19 +14,45 @@ pub async fn get_job_status(
 ) -> (StatusCode, Json<GetJobStatusResponseEnum>) {
     tracing::info!("Getting status for job_id: {}", job_id);
 
-    match get_job_request(state.offchain_processor_db, &job_id).await {
-        Ok(Some(job)) => {
+    let job = match get_job_request(state.offchain_processor_db.clone(), &job_id).await {
+        Ok(job) => job,
+        Err(e) => return internal_error(&job_id, e),
+    };
+
+    match job {
+        Some(job) => {
             tracing::info!("Found job status: {:?} for job_id: {}", job.status, job_id);
+            let history = match get_job_status_history(state.offchain_processor_db, &job_id).await {
+                Ok(history) => history,
+                Err(e) => return internal_error(&job_id, e),
+            };
+            // Jobs created before requests were persisted have nothing to echo.
+            // Webhook URLs belong to their submitters and are never echoed.
+            let request = job
+                .request
+                .and_then(|request| serde_json::from_value::<PitchLakeJobRequest>(request).ok())
+                .map(|request| PitchLakeJobRequest {
+                    callback_url: None,
+                    ..request
+                });
+            let (result, error) = job.result.map(parse_job_result).unwrap_or_default();
             (
                 StatusCode::OK,
                 Json(GetJobStatusResponseEnum::Success(JobResponse {
                     job_id: job.job_id,
                     message: None,
                     status: Some(job.status),
+                    result,
+                    error,
+                    tx_hash: job.callback_tx_hash,
+                    created_at: Some(job.created_at),
+                    updated_at: Some(job.updated_at),
+                    request,
+                    history,
                 })),
             )
         }
-        Ok(None) => {
+        None => {
             tracing::info!("Job not found for job_id: {}", job_id);
             (
                 StatusCode::NOT_FOUND,
@@ -33,26 +61,34 @@ pub async fn get_job_status(
                 })),
             )
         }
-        Err(e) => {
-            tracing::error!("Failed to get job status for job_id {}: {:?}", job_id, e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(GetJobStatusResponseEnum::Error(ErrorResponse {
-                    error: "An internal error occurred. Please try again later.".to_string(),
-                })),
-            )
-        }
     }
 }
 
+fn internal_error(job_id: &str, e: sqlx::Error) -> (StatusCode, Json<GetJobStatusResponseEnum>) {
+    tracing::error!("Failed to get job status for job_id {}: {:?}", job_id, e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(GetJobStatusResponseEnum::Error(ErrorResponse {
+            error: "An internal error occurred. Please try again later.".to_string(),
+        })),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use core::panic;
 
-    use crate::{handlers::fixtures::TestContext, types::GetJobStatusResponseEnum};
+    use crate::{
+        handlers::fixtures::TestContext,
+        types::{
+            ClientInfo, GetJobStatusResponseEnum, PitchLakeJobRequest, PitchLakeJobRequestParams,
+            PricingResult,
+        },
+    };
     use axum::{http::StatusCode, Json};
-    use db_access::models::JobStatus;
+    use db_access::{models::JobStatus, queries::set_job_status};
     use serde_json::json;
+    use starknet::core::types::Felt;
 
     #[tokio::test]
     async fn test_get_job_status_not_found() {
@@ -133,5 +169,134 @@ mod tests {
         assert_eq!(status, StatusCode::OK);
         assert_eq!(response.job_id, job_id);
         assert_eq!(response.status.unwrap(), JobStatus::Completed);
+        assert_eq!(
+            response.result,
+            Some(PricingResult {
+                twap: 12345.0,
+                cap_level: 2345.0,
+                reserve_price: 3456.0,
+                volatility: None,
+                reserve_price_diagnostics: None,
+                reserve_price_sensitivities: None,
+                analytic_cross_check: None,
+                data_quality: None,
+                outliers: None,
+                seed: None,
+                config: None,
+            })
+        );
+        assert!(response.error.is_none());
+        assert!(response.created_at.is_some());
+        assert!(response.updated_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_job_status_failed_reports_error() {
+        let ctx = TestContext::new().await;
+        let job_id = "failed_job_id";
+
+        ctx.create_job_with_result(
+            job_id,
+            JobStatus::Failed,
+            json!({ "error": "Starknet callback failed" }),
+        )
+        .await;
+
+        let (status, Json(response)) = ctx.get_job_status(job_id).await;
+
+        let response = match response {
+            GetJobStatusResponseEnum::Success(success_res) => success_res,
+            GetJobStatusResponseEnum::Error(_) => panic!("Unexpected response status"),
+        };
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(response.result.is_none());
+        assert_eq!(
+            response.error.unwrap().message,
+            "Starknet callback failed".to_string()
+        );
+        assert!(response.tx_hash.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_job_status_reports_history() {
+        let ctx = TestContext::new().await;
+        let job_id = "lifecycle_job_id";
+
+        ctx.create_job(job_id, JobStatus::Queued).await;
+        for status in [JobStatus::FetchingData, JobStatus::Computing] {
+            set_job_status(ctx.offchain_processor_db.clone(), job_id, status)
+                .await
+                .unwrap();
+        }
+
+        let (status, Json(response)) = ctx.get_job_status(job_id).await;
+
+        let response = match response {
+            GetJobStatusResponseEnum::Success(success_res) => success_res,
+            GetJobStatusResponseEnum::Error(_) => panic!("Unexpected response status"),
+        };
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(response.status.unwrap(), JobStatus::Computing);
+        let transitions: Vec<_> = response
+            .history
+            .iter()
+            .map(|change| (change.from_status, change.to_status))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                (None, JobStatus::Queued),
+                (Some(JobStatus::Queued), JobStatus::FetchingData),
+                (Some(JobStatus::FetchingData), JobStatus::Computing),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_job_status_echoes_request() {
+        let ctx = TestContext::new().await;
+
+        let payload = PitchLakeJobRequest {
+            identifiers: vec!["test-id".to_string()],
+            params: PitchLakeJobRequestParams {
+                twap: (0, 100),
+                cap_level: (0, 200),
+                reserve_price: (0, 300),
+                alpha: 2500,
+                k: -1000,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 42,
+            },
+            callback_url: Some("https://keeper.example/hook".to_string()),
+        };
+
+        let (_, Json(created)) = ctx.get_pricing_data(payload).await;
+        let (status, Json(response)) = ctx.get_job_status(&created.job_id).await;
+
+        let response = match response {
+            GetJobStatusResponseEnum::Success(success_res) => success_res,
+            GetJobStatusResponseEnum::Error(_) => panic!("Unexpected response status"),
+        };
+
+        assert_eq!(status, StatusCode::OK);
+        let request = response.request.expect("request should be echoed");
+        assert_eq!(request.identifiers, vec!["test-id".to_string()]);
+        assert_eq!(request.params.cap_level, (0, 200));
+        assert_eq!(request.params.reserve_price, (0, 300));
+        assert_eq!(request.params.k, -1000);
+        assert_eq!(
+            request.client_info.vault_address,
+            Felt::from_hex("0x456").unwrap()
+        );
+        assert_eq!(request.client_info.timestamp, 42);
+        assert_eq!(request.callback_url, None);
     }
 }
@@ -1,7 +1,9 @@
 pub mod api_key;
+pub mod compute_pricing;
 #[cfg(test)]
 pub mod fixtures;
 pub mod get_pricing_data;
 pub mod health_check;
+pub mod job_events;
 pub mod job_status;
 pub mod latest_block;
@@ -0,0 +1,261 @@
+use std::fs::File;
+use std::path::Path;
+
+use db_access::models::BlockHeader;
+use eyre::{eyre, Result};
+use polars::prelude::*;
+use serde::Deserialize;
+
+/// Reads block headers dumped to a file, so pricing can run without the
+/// indexer database.
+///
+/// The format follows the extension:
+/// - `.json`: an array of header objects, as written by
+///   `db_access::utils::save_blockheaders_to_file`. Fields other than
+///   `number`, `timestamp` and `base_fee_per_gas` are ignored.
+/// - `.csv` (with a header line) and `.parquet`: a table with `number`,
+///   `timestamp` and `base_fee_per_gas` columns.
+///
+/// Timestamps are unix seconds, as integers or as decimal or `0x` hex
+/// strings. Base fees are hex strings, as the indexer stores them, or
+/// integers.
+pub fn read_headers(path: &Path) -> Result<Vec<BlockHeader>> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => read_json(path),
+        Some("csv") => {
+            let df = CsvReadOptions::default()
+                .with_has_header(true)
+                .try_into_reader_with_file_path(Some(path.to_path_buf()))?
+                .finish()?;
+            read_table(&df)
+        }
+        Some("parquet") => read_table(&ParquetReader::new(open(path)?).finish()?),
+        _ => Err(eyre!(
+            "Unsupported header dump {}: expected a .json, .csv or .parquet file",
+            path.display()
+        )),
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    File::open(path).map_err(|e| eyre!("Failed to open {}: {}", path.display(), e))
+}
+
+// A header as written in a JSON dump
+#[derive(Deserialize)]
+struct HeaderRecord {
+    number: i64,
+    timestamp: Option<Scalar>,
+    base_fee_per_gas: Option<Scalar>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Integer(u64),
+    Text(String),
+}
+
+fn read_json(path: &Path) -> Result<Vec<BlockHeader>> {
+    let records: Vec<HeaderRecord> = serde_json::from_reader(std::io::BufReader::new(open(path)?))
+        .map_err(|e| eyre!("Failed to parse {}: {}", path.display(), e))?;
+
+    records
+        .into_iter()
+        .map(|record| {
+            let timestamp = match record.timestamp {
+                Some(Scalar::Integer(timestamp)) => Some(timestamp as i64),
+                Some(Scalar::Text(timestamp)) => Some(parse_timestamp(&timestamp)?),
+                None => None,
+            };
+            let base_fee = record.base_fee_per_gas.map(|base_fee| match base_fee {
+                Scalar::Integer(base_fee) => format!("{:#x}", base_fee),
+                Scalar::Text(base_fee) => base_fee,
+            });
+            Ok(header(record.number, timestamp, base_fee))
+        })
+        .collect()
+}
+
+fn read_table(df: &DataFrame) -> Result<Vec<BlockHeader>> {
+    let column = |name: &str| {
+        df.column(name)
+            .map_err(|_| eyre!("Header dump has no {} column", name))
+    };
+
+    let numbers = column("number")?.cast(&DataType::Int64)?;
+
+    let timestamps = column("timestamp")?;
+    let timestamps: Vec<Option<i64>> = match timestamps.dtype() {
+        DataType::String => timestamps
+            .str()?
+            .into_iter()
+            .map(|timestamp| timestamp.map(parse_timestamp).transpose())
+            .collect::<Result<_>>()?,
+        _ => timestamps
+            .cast(&DataType::Int64)?
+            .i64()?
+            .into_iter()
+            .collect(),
+    };
+
+    let base_fees = column("base_fee_per_gas")?;
+    let base_fees: Vec<Option<String>> = match base_fees.dtype() {
+        DataType::String => base_fees
+            .str()?
+            .into_iter()
+            .map(|base_fee| base_fee.map(str::to_string))
+            .collect(),
+        _ => base_fees
+            .cast(&DataType::UInt64)?
+            .u64()?
+            .into_iter()
+            .map(|base_fee| base_fee.map(|base_fee| format!("{:#x}", base_fee)))
+            .collect(),
+    };
+
+    numbers
+        .i64()?
+        .into_iter()
+        .zip(timestamps)
+        .zip(base_fees)
+        .map(|((number, timestamp), base_fee)| {
+            let number = number.ok_or_else(|| eyre!("Header without a number"))?;
+            Ok(header(number, timestamp, base_fee))
+        })
+        .collect()
+}
+
+// Unix seconds, in decimal or `0x` hex
+fn parse_timestamp(timestamp: &str) -> Result<i64> {
+    let parsed = match timestamp.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => timestamp.parse(),
+    };
+    parsed.map_err(|e| eyre!("Invalid timestamp {}: {}", timestamp, e))
+}
+
+fn header(number: i64, timestamp: Option<i64>, base_fee_per_gas: Option<String>) -> BlockHeader {
+    BlockHeader {
+        block_hash: None,
+        number,
+        gas_limit: None,
+        gas_used: None,
+        base_fee_per_gas,
+        nonce: None,
+        transaction_root: None,
+        receipts_root: None,
+        state_root: None,
+        timestamp: timestamp.map(|timestamp| timestamp.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A file in the temp directory that is removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("{}-{}", uuid::Uuid::new_v4(), name));
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn summary(headers: &[BlockHeader]) -> Vec<(i64, Option<&str>, Option<&str>)> {
+        headers
+            .iter()
+            .map(|header| {
+                (
+                    header.number,
+                    header.timestamp.as_deref(),
+                    header.base_fee_per_gas.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_json_dump_with_hex_timestamps() {
+        let file = TempFile::new(
+            "headers.json",
+            r#"[
+                {"number": 5, "timestamp": "0x6553f100", "base_fee_per_gas": "0x3b9aca00", "block_hash": "0xab"},
+                {"number": 6, "timestamp": 1700000012, "base_fee_per_gas": null}
+            ]"#,
+        );
+
+        let headers = read_headers(&file.0).unwrap();
+
+        assert_eq!(
+            summary(&headers),
+            vec![
+                (5, Some("1700000000"), Some("0x3b9aca00")),
+                (6, Some("1700000012"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_csv_dump_by_column_name() {
+        let file = TempFile::new(
+            "headers.csv",
+            "timestamp,number,base_fee_per_gas\n1700000000,5,0x3b9aca00\n1700000012,6,0x3b9aca01\n",
+        );
+
+        let headers = read_headers(&file.0).unwrap();
+
+        assert_eq!(
+            summary(&headers),
+            vec![
+                (5, Some("1700000000"), Some("0x3b9aca00")),
+                (6, Some("1700000012"), Some("0x3b9aca01")),
+            ]
+        );
+
+        let missing = TempFile::new("missing.csv", "number,timestamp\n1,2\n");
+        assert_eq!(
+            read_headers(&missing.0).unwrap_err().to_string(),
+            "Header dump has no base_fee_per_gas column"
+        );
+    }
+
+    #[test]
+    fn test_read_parquet_dump_with_integer_base_fees() {
+        let file = TempFile::new("headers.parquet", "");
+        let mut df = df!(
+            "number" => [5i64, 6],
+            "timestamp" => [1700000000i64, 1700000012],
+            "base_fee_per_gas" => [1000000000u64, 1000000001],
+        )
+        .unwrap();
+        ParquetWriter::new(File::create(&file.0).unwrap())
+            .finish(&mut df)
+            .unwrap();
+
+        let headers = read_headers(&file.0).unwrap();
+
+        assert_eq!(
+            summary(&headers),
+            vec![
+                (5, Some("1700000000"), Some("0x3b9aca00")),
+                (6, Some("1700000012"), Some("0x3b9aca01")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_extension_is_rejected() {
+        assert!(read_headers(Path::new("headers.txt")).is_err());
+    }
+}
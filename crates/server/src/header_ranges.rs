@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use db_access::{
+    models::BlockHeader, queries::get_block_headers_by_time_range, IndexerDbConnection,
+};
+use eyre::Result;
+
+/// Block headers covering a set of time ranges, fetched with one query per
+/// group of overlapping ranges.
+///
+/// Vaults settling at the same round boundary ask for largely the same
+/// headers, so a batch reads them from the indexer once and every job takes
+/// its own slice.
+#[derive(Debug, Default)]
+pub struct HeaderRanges {
+    spans: Vec<((i64, i64), Vec<BlockHeader>)>,
+}
+
+impl HeaderRanges {
+    pub async fn fetch(
+        db: Arc<IndexerDbConnection>,
+        ranges: impl IntoIterator<Item = (i64, i64)>,
+    ) -> Result<Self> {
+        let mut spans = Vec::new();
+        for (start, end) in merge_ranges(ranges) {
+            tracing::debug!("Fetching shared block headers for {} to {}", start, end);
+            let headers =
+                get_block_headers_by_time_range(db.clone(), start.to_string(), end.to_string())
+                    .await?;
+            spans.push(((start, end), headers));
+        }
+
+        Ok(Self { spans })
+    }
+
+    /// Headers already at hand, e.g. read from a dump, as a single span from
+    /// the earliest to the latest timestamp. Headers without a readable
+    /// timestamp are dropped.
+    pub fn from_headers(headers: Vec<BlockHeader>) -> Self {
+        let mut headers: Vec<_> = headers
+            .into_iter()
+            .filter(|header| timestamp(header).is_some())
+            .collect();
+        headers.sort_by_key(|header| header.number);
+
+        let start = headers.iter().filter_map(timestamp).min();
+        let end = headers.iter().filter_map(timestamp).max();
+        match start.zip(end) {
+            Some(span) => Self {
+                spans: vec![(span, headers)],
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// Earliest and latest timestamps covered by any span.
+    pub fn span(&self) -> Option<(i64, i64)> {
+        let start = self.spans.iter().map(|((start, _), _)| *start).min()?;
+        let end = self.spans.iter().map(|((_, end), _)| *end).max()?;
+        Some((start, end))
+    }
+
+    /// Headers with a timestamp in `start..=end`, as the indexer would have
+    /// returned them. `None` if the range was not fetched.
+    pub fn get(&self, (start, end): (i64, i64)) -> Option<Vec<BlockHeader>> {
+        let (_, headers) = self
+            .spans
+            .iter()
+            .find(|((span_start, span_end), _)| *span_start <= start && end <= *span_end)?;
+
+        Some(
+            headers
+                .iter()
+                .filter(|header| {
+                    timestamp(header)
+                        .is_some_and(|timestamp| start <= timestamp && timestamp <= end)
+                })
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Number of queries the ranges were fetched with.
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
+fn timestamp(header: &BlockHeader) -> Option<i64> {
+    header.timestamp.as_deref()?.parse().ok()
+}
+
+// Joins overlapping and touching ranges, sorted by start
+fn merge_ranges(ranges: impl IntoIterator<Item = (i64, i64)>) -> Vec<(i64, i64)> {
+    let mut ranges: Vec<_> = ranges.into_iter().collect();
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= last_end.saturating_add(1) => {
+                *last_end = (*last_end).max(end);
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(number: i64, timestamp: i64) -> BlockHeader {
+        BlockHeader {
+            block_hash: None,
+            number,
+            gas_limit: None,
+            gas_used: None,
+            base_fee_per_gas: Some("0x1".to_string()),
+            nonce: None,
+            transaction_root: None,
+            receipts_root: None,
+            state_root: None,
+            timestamp: Some(timestamp.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_merge_ranges_joins_overlapping_and_touching() {
+        let merged = merge_ranges([(200, 300), (0, 100), (50, 150), (151, 160), (400, 500)]);
+
+        assert_eq!(merged, vec![(0, 160), (200, 300), (400, 500)]);
+    }
+
+    #[test]
+    fn test_get_slices_the_covering_span() {
+        let ranges = HeaderRanges {
+            spans: vec![(
+                (0, 100),
+                vec![header(1, 0), header(2, 40), header(3, 60), header(4, 100)],
+            )],
+        };
+
+        let numbers: Vec<_> = ranges
+            .get((40, 100))
+            .unwrap()
+            .iter()
+            .map(|header| header.number)
+            .collect();
+        assert_eq!(numbers, vec![2, 3, 4]);
+
+        // Not fetched, the caller has to query the indexer itself
+        assert!(ranges.get((50, 150)).is_none());
+    }
+
+    #[test]
+    fn test_from_headers_spans_all_timestamps() {
+        let ranges = HeaderRanges::from_headers(vec![header(3, 60), header(1, 0), header(2, 40)]);
+
+        assert_eq!(ranges.span(), Some((0, 60)));
+        let numbers: Vec<_> = ranges
+            .get((0, 60))
+            .unwrap()
+            .iter()
+            .map(|header| header.number)
+            .collect();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        assert!(HeaderRanges::from_headers(vec![]).span().is_none());
+    }
+}
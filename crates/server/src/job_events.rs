@@ -0,0 +1,117 @@
+use std::{sync::Arc, time::Duration};
+
+use db_access::{
+    models::JobStatusEvent,
+    queries::{get_job_status_event, JOB_STATUS_EVENTS_CHANNEL},
+    OffchainProcessorDbConnection,
+};
+use eyre::Result;
+use sqlx::postgres::PgListener;
+use tokio::{
+    sync::broadcast::{self, Receiver, Sender},
+    task::JoinHandle,
+};
+
+/// How many status changes a slow subscriber may fall behind before it has to
+/// catch up from the database.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// In-process fan-out of job status changes.
+///
+/// Every change recorded in `job_status_history` is announced by Postgres on
+/// [`JOB_STATUS_EVENTS_CHANNEL`]; a single listener task forwards it here, so
+/// changes made by the worker, the handlers and bulk recovery queries all reach
+/// subscribers the same way.
+#[derive(Clone)]
+pub struct JobEvents {
+    sender: Sender<JobStatusEvent>,
+}
+
+impl Default for JobEvents {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl JobEvents {
+    pub fn subscribe(&self) -> Receiver<JobStatusEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Starts forwarding status changes from the database. The listener
+    /// reconnects on its own if the connection drops.
+    pub fn spawn_listener(&self, db: Arc<OffchainProcessorDbConnection>) -> JoinHandle<()> {
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = listen(db.clone(), &sender).await {
+                    tracing::error!("Job status listener failed: {:?}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        })
+    }
+}
+
+async fn listen(
+    db: Arc<OffchainProcessorDbConnection>,
+    sender: &Sender<JobStatusEvent>,
+) -> Result<()> {
+    let mut listener = PgListener::connect_with(&db.db_connection().pool).await?;
+    listener.listen(JOB_STATUS_EVENTS_CHANNEL).await?;
+    tracing::info!(
+        "Listening for job status changes on {}",
+        JOB_STATUS_EVENTS_CHANNEL
+    );
+
+    loop {
+        let notification = listener.recv().await?;
+        let Ok(id) = notification.payload().parse::<i64>() else {
+            tracing::warn!(
+                "Ignoring malformed job status notification: {}",
+                notification.payload()
+            );
+            continue;
+        };
+
+        if let Some(event) = get_job_status_event(db.clone(), id).await? {
+            // Nobody listening is fine
+            let _ = sender.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::fixtures::TestContext;
+    use db_access::{models::JobStatus, queries::set_job_status};
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_status_changes_are_broadcast() {
+        let ctx = TestContext::new().await;
+        let mut receiver = ctx.app_state.job_events.subscribe();
+
+        ctx.create_job("event_job", JobStatus::Queued).await;
+        set_job_status(
+            ctx.offchain_processor_db.clone(),
+            "event_job",
+            JobStatus::FetchingData,
+        )
+        .await
+        .unwrap();
+
+        let mut statuses = Vec::new();
+        while statuses.len() < 2 {
+            let event = timeout(Duration::from_secs(10), receiver.recv())
+                .await
+                .expect("status change should be broadcast")
+                .unwrap();
+            assert_eq!(event.job_id, "event_job");
+            statuses.push(event.to_status);
+        }
+        assert_eq!(statuses, vec![JobStatus::Queued, JobStatus::FetchingData]);
+    }
+}
@@ -0,0 +1,470 @@
+use starknet_crypto::{poseidon_hash_many, poseidon_hash_single, Felt};
+
+use crate::pricing_data::{
+    config::{PricingConfig, PricingConfigOverrides},
+    model::pitch_lake_v1,
+};
+use crate::types::{PitchLakeJobRequest, PitchLakeJobRequestParams};
+
+/// Domain separator mixed into every v2 job ID ("FOSSIL_JOB_ID_V2").
+const JOB_ID_V2_TAG: &[u8] = b"FOSSIL_JOB_ID_V2";
+
+/// Marks the start of `params.pricing_config` in the encoding.
+const PRICING_CONFIG_TAG: &[u8] = b"pricing_config";
+
+/// Marks `params.program_id` in the encoding.
+const PROGRAM_ID_TAG: &[u8] = b"program_id";
+
+/// Largest byte chunk that always fits in a felt without reduction.
+const FELT_CHUNK_BYTES: usize = 31;
+
+/// Versions of the job ID derivation.
+///
+/// New jobs always get a [`JobIdVersion::V2`] ID. V1 IDs are still derived so
+/// that jobs created before the switch can be found again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobIdVersion {
+    /// Separator-less string concatenation of identifiers and params, packed
+    /// into a single felt. Ambiguous and truncating; kept for lookups only.
+    V1,
+    /// Poseidon hash over a canonical, length-prefixed felt encoding of the
+    /// identifiers, params and client/vault addresses.
+    V2,
+}
+
+impl JobIdVersion {
+    pub const LATEST: Self = Self::V2;
+}
+
+/// Derives the job ID for `request` with the latest derivation. `defaults`
+/// is the config the request's program runs with when it overrides nothing.
+pub fn generate_job_id(request: &PitchLakeJobRequest, defaults: &PricingConfig) -> String {
+    generate_job_id_with_version(request, JobIdVersion::LATEST, defaults)
+}
+
+/// Derives the job ID for `request` with a specific derivation version.
+pub fn generate_job_id_with_version(
+    request: &PitchLakeJobRequest,
+    version: JobIdVersion,
+    defaults: &PricingConfig,
+) -> String {
+    match version {
+        JobIdVersion::V1 => legacy_job_id(&request.identifiers, &request.params),
+        JobIdVersion::V2 => poseidon_hash_many(&encode_job_request(request, defaults)).to_string(),
+    }
+}
+
+/// Canonical felt encoding of the fields that identify a job.
+///
+/// Every variable-length field is prefixed with its length and every scalar
+/// is mapped into a felt injectively, so two different requests can never
+/// produce the same encoding. `client_info.timestamp` and `callback_url` are
+/// deliberately left out: resubmitting the same round must resolve to the
+/// same job. An explicit `params.seed`, `params.pricing_config` and
+/// `params.program_id` change the result and are appended last, so requests
+/// without them keep the ID they always had. Override fields set to their
+/// value in `defaults` and the default program ID are encoded as if they were
+/// left out, as they price the same job.
+pub fn encode_job_request(request: &PitchLakeJobRequest, defaults: &PricingConfig) -> Vec<Felt> {
+    let params = &request.params;
+    let mut felts = vec![Felt::from_bytes_be_slice(JOB_ID_V2_TAG)];
+
+    felts.push(Felt::from(request.identifiers.len() as u64));
+    for identifier in &request.identifiers {
+        encode_bytes(identifier.as_bytes(), &mut felts);
+    }
+
+    for (start, end) in [params.twap, params.cap_level, params.reserve_price] {
+        felts.push(Felt::from(start));
+        felts.push(Felt::from(end));
+    }
+    felts.push(Felt::from(params.alpha));
+    felts.push(Felt::from(params.k));
+
+    felts.push(request.client_info.client_address);
+    felts.push(request.client_info.vault_address);
+
+    if let Some(seed) = params.seed {
+        felts.push(Felt::from(seed));
+    }
+    // The tag does not fit in a u64, so it can't be mistaken for a seed
+    if let Some(overrides) = normalized_overrides(params, defaults) {
+        felts.push(Felt::from_bytes_be_slice(PRICING_CONFIG_TAG));
+        // Unset fields are skipped and the rest serialized in declaration
+        // order, so equal overrides always give the same bytes
+        let json = serde_json::to_vec(&overrides).expect("pricing config serializes to JSON");
+        encode_bytes(&json, &mut felts);
+    }
+    if let Some(program_id) = params.program_id.filter(|&id| id != pitch_lake_v1()) {
+        felts.push(Felt::from_bytes_be_slice(PROGRAM_ID_TAG));
+        felts.push(program_id);
+    }
+
+    felts
+}
+
+// The fields of a request's overrides that change `defaults`, unless there
+// are none
+fn normalized_overrides(
+    params: &PitchLakeJobRequestParams,
+    defaults: &PricingConfig,
+) -> Option<PricingConfigOverrides> {
+    params
+        .pricing_config
+        .as_ref()
+        .map(|overrides| overrides.without_defaults(defaults))
+        .filter(|overrides| *overrides != PricingConfigOverrides::default())
+}
+
+// Byte length followed by 31-byte big-endian chunks, so arbitrarily long
+// strings are encoded without truncation or modular reduction.
+fn encode_bytes(bytes: &[u8], felts: &mut Vec<Felt>) {
+    felts.push(Felt::from(bytes.len() as u64));
+    felts.extend(
+        bytes
+            .chunks(FELT_CHUNK_BYTES)
+            .map(Felt::from_bytes_be_slice),
+    );
+}
+
+// The original derivation. `(1, 23)` and `(12, 3)` collide and long inputs
+// are truncated, which is why it is only used to find pre-existing jobs.
+fn legacy_job_id(identifiers: &[String], params: &PitchLakeJobRequestParams) -> String {
+    let mut input = identifiers.join("");
+
+    // Concatenate all time ranges as part of the job ID generation
+    input.push_str(&format!(
+        "{}{}{}{}{}{}{}{}",
+        params.twap.0,
+        params.twap.1,
+        params.cap_level.0,
+        params.cap_level.1,
+        params.reserve_price.0,
+        params.reserve_price.1,
+        params.alpha,
+        params.k,
+    ));
+
+    poseidon_hash_single(Felt::from_bytes_be_slice(input.as_bytes())).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ClientInfo;
+    use proptest::prelude::*;
+
+    fn job_id(request: &PitchLakeJobRequest) -> String {
+        generate_job_id(request, &PricingConfig::default())
+    }
+
+    fn request(identifiers: &[&str], twap: (i64, i64)) -> PitchLakeJobRequest {
+        PitchLakeJobRequest {
+            identifiers: identifiers.iter().map(|s| s.to_string()).collect(),
+            params: PitchLakeJobRequestParams {
+                twap,
+                cap_level: (0, 100),
+                reserve_price: (0, 100),
+                alpha: 2500,
+                k: 0,
+                seed: None,
+                pricing_config: None,
+                program_id: None,
+            },
+            client_info: ClientInfo {
+                client_address: Felt::from_hex("0x123").unwrap(),
+                vault_address: Felt::from_hex("0x456").unwrap(),
+                timestamp: 0,
+            },
+            callback_url: None,
+        }
+    }
+
+    #[test]
+    fn test_v2_separates_adjacent_numbers() {
+        let a = request(&["id"], (1, 23));
+        let b = request(&["id"], (12, 3));
+
+        assert_eq!(
+            generate_job_id_with_version(&a, JobIdVersion::V1, &PricingConfig::default()),
+            generate_job_id_with_version(&b, JobIdVersion::V1, &PricingConfig::default())
+        );
+        assert_ne!(job_id(&a), job_id(&b));
+    }
+
+    #[test]
+    fn test_v2_separates_identifier_boundaries() {
+        let a = request(&["ab", "c"], (0, 100));
+        let b = request(&["a", "bc"], (0, 100));
+
+        assert_ne!(job_id(&a), job_id(&b));
+    }
+
+    #[test]
+    fn test_v2_handles_long_identifiers() {
+        let long = "x".repeat(100);
+        let longer = format!("{}y", long);
+        let a = request(&[&long], (0, 100));
+        let b = request(&[&longer], (0, 100));
+
+        assert_ne!(job_id(&a), job_id(&b));
+    }
+
+    #[test]
+    fn test_v2_covers_vault_address() {
+        let a = request(&["id"], (0, 100));
+        let mut b = request(&["id"], (0, 100));
+        b.client_info.vault_address = Felt::from_hex("0x789").unwrap();
+
+        assert_ne!(job_id(&a), job_id(&b));
+    }
+
+    #[test]
+    fn test_v2_ignores_client_timestamp() {
+        let a = request(&["id"], (0, 100));
+        let mut b = request(&["id"], (0, 100));
+        b.client_info.timestamp = 1_741_243_059;
+
+        assert_eq!(job_id(&a), job_id(&b));
+    }
+
+    #[test]
+    fn test_v2_covers_pricing_config() {
+        let a = request(&["id"], (0, 100));
+        let mut b = request(&["id"], (0, 100));
+        b.params.pricing_config = Some(PricingConfigOverrides {
+            num_paths: Some(20_000),
+            ..Default::default()
+        });
+        let mut c = b.clone();
+        c.params.pricing_config = Some(PricingConfigOverrides {
+            num_paths: Some(30_000),
+            ..Default::default()
+        });
+
+        assert_ne!(job_id(&a), job_id(&b));
+        assert_ne!(job_id(&b), job_id(&c));
+    }
+
+    #[test]
+    fn test_v2_covers_program_id() {
+        let a = request(&["id"], (0, 100));
+        let mut b = request(&["id"], (0, 100));
+        b.params.program_id = Some(Felt::from(1u8));
+        let mut c = b.clone();
+        c.params.program_id = Some(Felt::from(2u8));
+
+        assert_ne!(job_id(&a), job_id(&b));
+        assert_ne!(job_id(&b), job_id(&c));
+    }
+
+    #[test]
+    fn test_v2_ignores_empty_pricing_config() {
+        let a = request(&["id"], (0, 100));
+        let mut b = request(&["id"], (0, 100));
+        b.params.pricing_config = Some(PricingConfigOverrides::default());
+
+        assert_eq!(job_id(&a), job_id(&b));
+    }
+
+    #[test]
+    fn test_v2_ignores_overrides_of_default_values() {
+        let defaults = PricingConfig {
+            num_paths: 20_000,
+            ..Default::default()
+        };
+        let mut a = request(&["id"], (0, 100));
+        a.params.pricing_config = Some(PricingConfigOverrides {
+            risk_free_rate: Some(0.01),
+            ..Default::default()
+        });
+        let mut b = a.clone();
+        b.params.pricing_config = Some(PricingConfigOverrides {
+            num_paths: Some(20_000),
+            twap_window: Some(defaults.twap_window),
+            risk_free_rate: Some(0.01),
+            ..Default::default()
+        });
+        let mut c = request(&["id"], (0, 100));
+        c.params.pricing_config = Some(PricingConfigOverrides {
+            num_paths: Some(20_000),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            generate_job_id(&a, &defaults),
+            generate_job_id(&b, &defaults)
+        );
+        assert_eq!(
+            generate_job_id(&c, &defaults),
+            generate_job_id(&request(&["id"], (0, 100)), &defaults)
+        );
+        assert_ne!(job_id(&c), job_id(&request(&["id"], (0, 100))));
+    }
+
+    #[test]
+    fn test_v2_ignores_pricing_config_key_order() {
+        let mut a = request(&["id"], (0, 100));
+        a.params.pricing_config =
+            serde_json::from_str(r#"{ "num_paths": 5000, "antithetic": true, "mu": 0.1 }"#)
+                .unwrap();
+        let mut b = a.clone();
+        b.params.pricing_config =
+            serde_json::from_str(r#"{ "mu": 0.1, "antithetic": true, "num_paths": 5000 }"#)
+                .unwrap();
+
+        assert_eq!(job_id(&a), job_id(&b));
+    }
+
+    #[test]
+    fn test_v2_ignores_default_program_id() {
+        let a = request(&["id"], (0, 100));
+        let mut b = request(&["id"], (0, 100));
+        b.params.program_id = Some(pitch_lake_v1());
+
+        assert_eq!(job_id(&a), job_id(&b));
+    }
+
+    fn arb_felt() -> impl Strategy<Value = Felt> {
+        any::<[u8; FELT_CHUNK_BYTES]>().prop_map(|bytes| Felt::from_bytes_be_slice(&bytes))
+    }
+
+    // An unset override, or one set to anything but `default`
+    fn arb_override<T>(
+        values: impl Strategy<Value = T>,
+        default: T,
+    ) -> impl Strategy<Value = Option<T>>
+    where
+        T: std::fmt::Debug + PartialEq + 'static,
+    {
+        prop::option::of(values.prop_filter("set to the default", move |value| *value != default))
+    }
+
+    prop_compose! {
+        // Overrides of a few fields of each kind, possibly none of them. None
+        // is set to its default, so different overrides price different jobs.
+        fn arb_overrides()(
+            num_paths in arb_override(any::<usize>(), PricingConfig::default().num_paths),
+            twap_window in arb_override(any::<usize>(), PricingConfig::default().twap_window),
+            risk_free_rate in arb_override(-1.0..1.0f64, PricingConfig::default().risk_free_rate),
+            antithetic in arb_override(any::<bool>(), PricingConfig::default().antithetic),
+            mrj_starts in arb_override(any::<usize>(), PricingConfig::default().mrj_starts),
+        ) -> PricingConfigOverrides {
+            PricingConfigOverrides {
+                num_paths,
+                twap_window,
+                risk_free_rate,
+                antithetic,
+                mrj_starts,
+                ..Default::default()
+            }
+        }
+    }
+
+    fn arb_program_id() -> impl Strategy<Value = Option<Felt>> {
+        prop_oneof![
+            Just(None),
+            Just(Some(pitch_lake_v1())),
+            arb_felt().prop_map(Some),
+        ]
+    }
+
+    prop_compose! {
+        fn arb_request()(
+            identifiers in prop::collection::vec(".{0,40}", 0..4),
+            ranges in any::<[i64; 6]>(),
+            alpha in any::<u128>(),
+            k in any::<i128>(),
+            seed in any::<Option<u64>>(),
+            pricing_config in prop::option::of(arb_overrides()),
+            program_id in arb_program_id(),
+            client_address in arb_felt(),
+            vault_address in arb_felt(),
+        ) -> PitchLakeJobRequest {
+            PitchLakeJobRequest {
+                identifiers,
+                params: PitchLakeJobRequestParams {
+                    twap: (ranges[0], ranges[1]),
+                    cap_level: (ranges[2], ranges[3]),
+                    reserve_price: (ranges[4], ranges[5]),
+                    alpha,
+                    k,
+                    seed,
+                    pricing_config,
+                    program_id,
+                },
+                client_info: ClientInfo {
+                    client_address,
+                    vault_address,
+                    timestamp: 0,
+                },
+                callback_url: None,
+            }
+        }
+    }
+
+    // What tells jobs apart, spelled out independently of the encoding
+    fn identity(request: &PitchLakeJobRequest) -> impl PartialEq + '_ {
+        (
+            &request.identifiers,
+            request.params.twap,
+            request.params.cap_level,
+            request.params.reserve_price,
+            request.params.alpha,
+            request.params.k,
+            request.params.seed,
+            // Leaving the overrides out is the same as setting none
+            request.params.pricing_config.clone().unwrap_or_default(),
+            request.params.program_id.unwrap_or_else(pitch_lake_v1),
+            request.client_info.client_address,
+            request.client_info.vault_address,
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn prop_distinct_requests_have_distinct_encodings(a in arb_request(), b in arb_request()) {
+            prop_assume!(identity(&a) != identity(&b));
+            let defaults = PricingConfig::default();
+            prop_assert_ne!(encode_job_request(&a, &defaults), encode_job_request(&b, &defaults));
+        }
+
+        #[test]
+        fn prop_distinct_requests_have_distinct_ids(a in arb_request(), b in arb_request()) {
+            prop_assume!(identity(&a) != identity(&b));
+            prop_assert_ne!(job_id(&a), job_id(&b));
+        }
+
+        #[test]
+        fn prop_explicit_defaults_share_an_id(a in arb_request(), explicit in any::<[bool; 5]>()) {
+            let defaults = PricingConfig::default();
+            let mut b = a.clone();
+            let overrides = b.params.pricing_config.get_or_insert_with(Default::default);
+            if explicit[0] {
+                overrides.num_paths.get_or_insert(defaults.num_paths);
+            }
+            if explicit[1] {
+                overrides.twap_window.get_or_insert(defaults.twap_window);
+            }
+            if explicit[2] {
+                overrides.risk_free_rate.get_or_insert(defaults.risk_free_rate);
+            }
+            if explicit[3] {
+                overrides.antithetic.get_or_insert(defaults.antithetic);
+            }
+            if explicit[4] {
+                overrides.mrj_starts.get_or_insert(defaults.mrj_starts);
+            }
+            b.params.program_id = Some(b.params.program_id.unwrap_or_else(pitch_lake_v1));
+            prop_assert_eq!(job_id(&a), job_id(&b));
+        }
+
+        #[test]
+        fn prop_single_field_change_changes_id(a in arb_request(), k in any::<i128>()) {
+            prop_assume!(k != a.params.k);
+            let mut b = a.clone();
+            b.params.k = k;
+            prop_assert_ne!(job_id(&a), job_id(&b));
+        }
+    }
+}
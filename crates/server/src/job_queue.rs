@@ -0,0 +1,571 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use db_access::{
+    models::{JobStatus, QueuedJob},
+    queries::{
+        claim_batch_jobs, claim_next_job, complete_queued_job, extend_job_lease,
+        fail_exhausted_jobs, fail_orphaned_jobs, update_job_status,
+    },
+    OffchainProcessorDbConnection,
+};
+use eyre::Result;
+use tokio::{
+    runtime::Handle,
+    sync::Semaphore,
+    task::{JoinError, JoinSet},
+    time::interval,
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{
+    env_config::{env_secs, env_value_where},
+    handlers::get_pricing_data::{prefetch_headers, pricing_headers, process_job},
+    pricing_data::model::PricingHeaders,
+    types::PitchLakeJobRequest,
+    webhooks::resume_pending_webhooks,
+    AppState,
+};
+
+/// Tuning knobs for the job queue worker.
+#[derive(Debug, Clone)]
+pub struct JobQueueConfig {
+    /// Unique id stored in `job_queue.locked_by` while this worker holds a job.
+    pub worker_id: String,
+    /// How often the queue is polled when no notification arrives.
+    pub poll_interval: Duration,
+    /// How long a claimed job stays leased before another worker may take it.
+    pub lease_duration: Duration,
+    /// How many jobs are processed at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for JobQueueConfig {
+    fn default() -> Self {
+        Self {
+            worker_id: Uuid::new_v4().to_string(),
+            poll_interval: Duration::from_secs(5),
+            lease_duration: Duration::from_secs(300),
+            concurrency: 4,
+        }
+    }
+}
+
+impl JobQueueConfig {
+    /// Reads `JOB_QUEUE_POLL_INTERVAL_SECS`, `JOB_QUEUE_LEASE_SECS` and
+    /// `JOB_QUEUE_CONCURRENCY`, falling back to the defaults when unset and
+    /// warning about unparsable values or a zero concurrency.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            poll_interval: env_secs("JOB_QUEUE_POLL_INTERVAL_SECS", default.poll_interval),
+            lease_duration: env_secs("JOB_QUEUE_LEASE_SECS", default.lease_duration),
+            concurrency: env_value_where("JOB_QUEUE_CONCURRENCY", default.concurrency, |&n| n > 0),
+            ..default
+        }
+    }
+}
+
+/// Returned by a job that stopped because another worker took over its lease.
+#[derive(Debug)]
+pub struct LeaseLost;
+
+impl fmt::Display for LeaseLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Lease on the job was lost to another worker")
+    }
+}
+
+impl std::error::Error for LeaseLost {}
+
+/// Background worker draining the persisted `job_queue`.
+///
+/// Jobs are leased rather than removed when claimed. If the server dies while
+/// processing, the lease expires and the job is picked up again on the next
+/// start, until its attempt budget is spent.
+pub struct JobQueueWorker {
+    /// Shared with the routes, whose `job_notifier` wakes the worker.
+    state: AppState,
+    config: JobQueueConfig,
+    /// One permit per job being processed, `config.concurrency` in total.
+    slots: Arc<Semaphore>,
+    tasks: JoinSet<()>,
+}
+
+impl JobQueueWorker {
+    pub fn new(state: AppState, config: JobQueueConfig) -> Self {
+        Self {
+            state,
+            slots: Arc::new(Semaphore::new(config.concurrency.max(1))),
+            config,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    /// Cleans up state left behind by a previous run before the worker starts.
+    pub async fn recover(&self) -> Result<()> {
+        let orphaned = fail_orphaned_jobs(self.db()).await?;
+        if !orphaned.is_empty() {
+            tracing::warn!(
+                "Marked {} orphaned job(s) as failed: {:?}",
+                orphaned.len(),
+                orphaned
+            );
+        }
+
+        // Webhooks cut off by the restart pick up from their last attempt
+        let resumed = resume_pending_webhooks(self.db()).await?;
+        if resumed > 0 {
+            tracing::info!("Resumed {} pending webhook delivery(ies)", resumed);
+        }
+
+        self.fail_exhausted().await
+    }
+
+    /// Runs forever, processing up to `config.concurrency` jobs at a time.
+    pub async fn run(mut self) {
+        tracing::info!(
+            "Job queue worker {} started (poll_interval={:?}, lease={:?}, concurrency={})",
+            self.config.worker_id,
+            self.config.poll_interval,
+            self.config.lease_duration,
+            self.config.concurrency
+        );
+
+        loop {
+            match self.run_next().await {
+                // Keep claiming while there is work and a free slot
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => tracing::error!("Job queue worker error: {:?}", e),
+            }
+
+            tokio::select! {
+                _ = self.state.job_notifier.notified() => {}
+                _ = tokio::time::sleep(self.config.poll_interval) => {}
+                Some(finished) = self.tasks.join_next() => log_finished(finished),
+            }
+        }
+    }
+
+    /// Claims a job, or as many jobs of its batch as there are free slots, and
+    /// starts processing them. Returns `false` if nothing was claimed because
+    /// the queue was empty or every slot is busy.
+    pub async fn run_next(&mut self) -> Result<bool> {
+        while let Some(finished) = self.tasks.try_join_next() {
+            log_finished(finished);
+        }
+
+        let free = self.slots.available_permits();
+        if free == 0 {
+            return Ok(false);
+        }
+
+        self.fail_exhausted().await?;
+
+        let lease_secs = self.config.lease_duration.as_secs_f64();
+        let Some(job) = claim_next_job(self.db(), &self.config.worker_id, lease_secs).await? else {
+            return Ok(false);
+        };
+
+        tracing::info!(
+            "Claimed job {} (attempt {}/{})",
+            job.job_id,
+            job.attempts,
+            job.max_attempts
+        );
+
+        let mut claimed = vec![job];
+        if let (Some(batch_id), true) = (claimed[0].batch_id.clone(), free > 1) {
+            let rest = claim_batch_jobs(
+                self.db(),
+                &batch_id,
+                &self.config.worker_id,
+                lease_secs,
+                (free - 1) as i64,
+            )
+            .await?;
+            tracing::info!("Claimed {} more job(s) of batch {}", rest.len(), batch_id);
+            claimed.extend(rest);
+        }
+
+        let mut batch = Vec::with_capacity(claimed.len());
+        for job in claimed {
+            if let Some(payload) = self.decode_payload(&job).await? {
+                batch.push((job, payload));
+            }
+        }
+
+        // Jobs of a batch share one read of their block headers
+        let headers = if batch.len() > 1 && !self.state.mock_pricing_data {
+            let payloads: Vec<_> = batch.iter().map(|(_, payload)| payload).collect();
+            prefetch_headers(self.state.indexer_db.clone(), &payloads).await
+        } else {
+            None
+        };
+
+        for (job, payload) in batch {
+            let prefetched = headers
+                .as_ref()
+                .and_then(|headers| pricing_headers(headers, &payload.params));
+            let slot = self
+                .slots
+                .clone()
+                .try_acquire_owned()
+                .expect("no more jobs are claimed than there are free slots");
+            let state = self.state.clone();
+            let config = self.config.clone();
+
+            self.tasks.spawn(async move {
+                process_claimed(state, &config, job, payload, prefetched).await;
+                drop(slot);
+            });
+        }
+
+        Ok(true)
+    }
+
+    // Reads the request stored with a claimed job. A malformed request will not
+    // decode on a later attempt either, so the job is failed right away.
+    async fn decode_payload(&self, job: &QueuedJob) -> Result<Option<PitchLakeJobRequest>> {
+        let decoded = job
+            .request
+            .clone()
+            .ok_or_else(|| eyre::eyre!("no request stored with the job"))
+            .and_then(|request| Ok(serde_json::from_value::<PitchLakeJobRequest>(request)?));
+
+        match decoded {
+            Ok(payload) => Ok(Some(payload)),
+            Err(e) => {
+                let error = format!("Failed to decode queued request: {}", e);
+                tracing::error!("{} (job_id={})", error, job.job_id);
+                update_job_status(
+                    self.db(),
+                    &job.job_id,
+                    JobStatus::Failed,
+                    Some(serde_json::json!({ "error": error })),
+                )
+                .await?;
+                complete_queued_job(self.db(), &job.job_id, &self.config.worker_id).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn db(&self) -> Arc<OffchainProcessorDbConnection> {
+        self.state.offchain_processor_db.clone()
+    }
+
+    async fn fail_exhausted(&self) -> Result<()> {
+        let failed = fail_exhausted_jobs(self.db()).await?;
+        if !failed.is_empty() {
+            tracing::warn!(
+                "Marked {} job(s) as failed after exhausting their attempts: {:?}",
+                failed.len(),
+                failed
+            );
+        }
+        Ok(())
+    }
+}
+
+// Processes a claimed job and drops it from the queue once it reached a final
+// status. A job that was interrupted, e.g. because its lease was lost or it was
+// cancelled, is left to whoever moved it on.
+async fn process_claimed(
+    state: AppState,
+    config: &JobQueueConfig,
+    job: QueuedJob,
+    payload: PitchLakeJobRequest,
+    prefetched: Option<PricingHeaders>,
+) {
+    let offchain_processor_db = state.offchain_processor_db.clone();
+    let lease = CancellationToken::new();
+    let processed = process_with_heartbeat(state, config, &job, payload, prefetched, &lease).await;
+
+    match processed {
+        Ok(_) if lease.is_cancelled() => {
+            tracing::warn!("Job {} stopped after losing its lease", job.job_id)
+        }
+        Ok(false) => tracing::warn!("Job {} stopped before finishing", job.job_id),
+        Ok(true) => {
+            match complete_queued_job(offchain_processor_db, &job.job_id, &config.worker_id).await {
+                Ok(true) => {}
+                Ok(false) => tracing::warn!(
+                    "Job {} was taken over by another worker before leaving the queue",
+                    job.job_id
+                ),
+                Err(e) => tracing::error!(
+                    "Failed to remove job {} from the queue: {:?}",
+                    job.job_id,
+                    e
+                ),
+            }
+        }
+        // Leave the lease in place: the job is retried once it expires
+        Err(e) => tracing::error!("Job {} aborted: {:?}", job.job_id, e),
+    }
+}
+
+// Runs the job off the async runtime, like the pricing computation always has,
+// and keeps its lease alive meanwhile so long computations are not mistaken for
+// crashed workers. Once the lease is lost `lease` is cancelled, which stops the
+// job before it sends its callback.
+async fn process_with_heartbeat(
+    state: AppState,
+    config: &JobQueueConfig,
+    job: &QueuedJob,
+    payload: PitchLakeJobRequest,
+    prefetched: Option<PricingHeaders>,
+    lease: &CancellationToken,
+) -> Result<bool, JoinError> {
+    let lease_secs = config.lease_duration.as_secs_f64();
+    let handle = Handle::current();
+    let offchain_processor_db = state.offchain_processor_db.clone();
+
+    let processing = tokio::task::spawn_blocking({
+        let job_id = job.job_id.clone();
+        let lease = lease.clone();
+        move || handle.block_on(process_job(state, job_id, payload, prefetched, lease))
+    });
+    tokio::pin!(processing);
+
+    let mut heartbeat = interval((config.lease_duration / 3).max(Duration::from_secs(1)));
+    heartbeat.tick().await;
+
+    loop {
+        tokio::select! {
+            result = &mut processing => return result,
+            _ = heartbeat.tick() => {
+                match extend_job_lease(
+                    offchain_processor_db.clone(),
+                    &job.job_id,
+                    &config.worker_id,
+                    lease_secs,
+                )
+                .await
+                {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        tracing::warn!("Lost lease on job {}", job.job_id);
+                        lease.cancel();
+                        return processing.await;
+                    }
+                    Err(e) => tracing::warn!(
+                        "Failed to extend lease on job {}: {:?}",
+                        job.job_id,
+                        e
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn log_finished(finished: Result<(), JoinError>) {
+    if let Err(e) = finished {
+        tracing::error!("Job queue task failed: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::fixtures::TestContext;
+    use db_access::queries::{create_queued_job_batch, create_queued_job_request, get_job_request};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_claimed_job_is_leased() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+
+        create_queued_job_request(
+            db.clone(),
+            "queued_job",
+            JobStatus::Queued,
+            &json!({}),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let job = claim_next_job(db.clone(), "worker-a", 60.0)
+            .await
+            .unwrap()
+            .expect("job should be claimable");
+        assert_eq!(job.job_id, "queued_job");
+        assert_eq!(job.attempts, 1);
+        assert_eq!(job.locked_by.as_deref(), Some("worker-a"));
+
+        // A second worker must not see a job under a live lease
+        let other = claim_next_job(db.clone(), "worker-b", 60.0).await.unwrap();
+        assert!(other.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expired_lease_is_reclaimed() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+
+        create_queued_job_request(
+            db.clone(),
+            "crashed_job",
+            JobStatus::Queued,
+            &json!({}),
+            None,
+        )
+        .await
+        .unwrap();
+
+        // Simulate a worker that died right after claiming
+        claim_next_job(db.clone(), "dead-worker", -1.0)
+            .await
+            .unwrap()
+            .expect("job should be claimable");
+
+        let job = claim_next_job(db.clone(), "worker-b", 60.0)
+            .await
+            .unwrap()
+            .expect("expired lease should be reclaimable");
+        assert_eq!(job.attempts, 2);
+        assert_eq!(job.locked_by.as_deref(), Some("worker-b"));
+    }
+
+    #[tokio::test]
+    async fn test_stale_worker_cannot_complete_reclaimed_job() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+
+        create_queued_job_request(
+            db.clone(),
+            "reclaimed_job",
+            JobStatus::Queued,
+            &json!({}),
+            None,
+        )
+        .await
+        .unwrap();
+
+        // The first worker's lease runs out while it is still processing
+        claim_next_job(db.clone(), "stale-worker", -1.0)
+            .await
+            .unwrap()
+            .expect("job should be claimable");
+        claim_next_job(db.clone(), "worker-b", 60.0)
+            .await
+            .unwrap()
+            .expect("expired lease should be reclaimable");
+
+        assert!(
+            !complete_queued_job(db.clone(), "reclaimed_job", "stale-worker")
+                .await
+                .unwrap()
+        );
+        assert!(
+            extend_job_lease(db.clone(), "reclaimed_job", "worker-b", 60.0)
+                .await
+                .unwrap()
+        );
+        assert!(complete_queued_job(db.clone(), "reclaimed_job", "worker-b")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_recover_fails_orphaned_and_exhausted_jobs() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+
+        // Pending job from before the queue existed
+        ctx.create_job("orphaned_job", JobStatus::Pending).await;
+
+        // Queued job that crashed on every attempt
+        create_queued_job_request(
+            db.clone(),
+            "exhausted_job",
+            JobStatus::Queued,
+            &json!({}),
+            None,
+        )
+        .await
+        .unwrap();
+        for _ in 0..3 {
+            claim_next_job(db.clone(), "dead-worker", -1.0)
+                .await
+                .unwrap();
+        }
+
+        let mut worker = JobQueueWorker::new(ctx.app_state.clone(), JobQueueConfig::default());
+        worker.recover().await.unwrap();
+
+        for job_id in ["orphaned_job", "exhausted_job"] {
+            let job = get_job_request(db.clone(), job_id).await.unwrap().unwrap();
+            assert_eq!(job.status, JobStatus::Failed);
+            assert!(job.result.unwrap().get("error").is_some());
+        }
+        assert!(!worker.run_next().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_batch_claim_is_limited_to_free_slots() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+
+        let jobs: Vec<_> = (0..4)
+            .map(|i| (format!("batch_job_{}", i), json!({})))
+            .collect();
+        create_queued_job_batch(db.clone(), "batch", JobStatus::Queued, &jobs, &[])
+            .await
+            .unwrap();
+
+        let first = claim_next_job(db.clone(), "worker-a", 60.0)
+            .await
+            .unwrap()
+            .expect("job should be claimable");
+        let rest = claim_batch_jobs(db.clone(), "batch", "worker-a", 60.0, 2)
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 2);
+
+        // The job left over is free for the next claim
+        let last = claim_next_job(db.clone(), "worker-b", 60.0)
+            .await
+            .unwrap()
+            .expect("job beyond the limit should stay claimable");
+        assert!(last.job_id != first.job_id && rest.iter().all(|job| job.job_id != last.job_id));
+    }
+
+    #[tokio::test]
+    async fn test_busy_worker_claims_nothing() {
+        let ctx = TestContext::new().await;
+        let db = ctx.offchain_processor_db.clone();
+
+        create_queued_job_request(
+            db.clone(),
+            "waiting_job",
+            JobStatus::Queued,
+            &json!({}),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let mut worker = JobQueueWorker::new(
+            ctx.app_state.clone(),
+            JobQueueConfig {
+                concurrency: 1,
+                ..JobQueueConfig::default()
+            },
+        );
+        let _busy = worker.slots.clone().try_acquire_owned().unwrap();
+
+        assert!(!worker.run_next().await.unwrap());
+        let job = claim_next_job(db.clone(), "worker-b", 60.0)
+            .await
+            .unwrap()
+            .expect("a busy worker must leave the job in the queue");
+        assert_eq!(job.attempts, 1);
+    }
+}
@@ -1,21 +1,41 @@
 #![deny(unused_crate_dependencies)]
+use dotenv as _;
 use tracing_subscriber as _;
+// Only used by the benchmarks
+#[cfg(test)]
+use criterion as _;
 
+pub mod backtest;
+pub mod callback;
+pub mod cli;
+pub mod env_config;
 pub mod handlers;
+pub mod header_dump;
+pub mod header_ranges;
+pub mod job_events;
+pub mod job_id;
+pub mod job_queue;
 pub mod middlewares;
 pub mod pricing_data;
 pub mod types;
+pub mod webhooks;
 
 // src/lib.rs
+use crate::handlers::compute_pricing::compute_concurrency;
+use crate::job_events::JobEvents;
 use crate::middlewares::auth::simple_apikey_auth;
+use crate::pricing_data::config::PricingConfigFile;
 use axum::{
     middleware::from_fn_with_state,
     routing::{get, post},
     Router,
 };
 use db_access::{IndexerDbConnection, OffchainProcessorDbConnection};
+use eyre::Result;
+use std::env;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
 use tower_http::{
     cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
     trace::TraceLayer,
@@ -25,16 +45,48 @@ use tower_http::{
 pub struct AppState {
     pub offchain_processor_db: Arc<OffchainProcessorDbConnection>,
     pub indexer_db: Arc<IndexerDbConnection>,
+    /// Wakes the job queue worker when a job is enqueued.
+    pub job_notifier: Arc<Notify>,
+    /// Job status changes, streamed to `/job_status/{job_id}/events` clients.
+    pub job_events: JobEvents,
+    /// One permit per compute-only request allowed to run at a time.
+    pub compute_slots: Arc<Semaphore>,
+    /// Jobs and compute-only requests answer with the mock pricing data.
+    pub mock_pricing_data: bool,
+    /// Deployment-wide pricing model parameters from `PRICING_CONFIG_FILE`.
+    pub pricing_config: Arc<PricingConfigFile>,
 }
 
-pub async fn create_app(
-    offchain_processor_db: Arc<OffchainProcessorDbConnection>,
-    indexer_db: Arc<IndexerDbConnection>,
-) -> Router {
-    let app_state = AppState {
-        offchain_processor_db,
-        indexer_db,
-    };
+impl AppState {
+    /// Reads `USE_MOCK_PRICING_DATA`, `COMPUTE_CONCURRENCY` and
+    /// `PRICING_CONFIG_FILE` once for the routes and the job queue worker
+    /// alike, and starts listening for job status changes.
+    pub fn new(
+        offchain_processor_db: Arc<OffchainProcessorDbConnection>,
+        indexer_db: Arc<IndexerDbConnection>,
+        job_notifier: Arc<Notify>,
+    ) -> Result<Self> {
+        let pricing_config = Arc::new(PricingConfigFile::from_env()?);
+
+        let job_events = JobEvents::default();
+        job_events.spawn_listener(offchain_processor_db.clone());
+
+        let mock_pricing_data = env::var("USE_MOCK_PRICING_DATA")
+            .is_ok_and(|value| value.eq_ignore_ascii_case("true"));
+
+        Ok(Self {
+            offchain_processor_db,
+            indexer_db,
+            job_notifier,
+            job_events,
+            compute_slots: Arc::new(Semaphore::new(compute_concurrency())),
+            mock_pricing_data,
+            pricing_config,
+        })
+    }
+}
+
+pub async fn create_app(app_state: AppState) -> Router {
 
     // Define the CORS layer
     let allowed_origins = std::env::var("ALLOWED_ORIGINS")
@@ -55,6 +107,26 @@ pub async fn create_app(
             "/pricing_data",
             post(handlers::get_pricing_data::get_pricing_data),
         )
+        .route(
+            "/pricing_data/compute",
+            post(handlers::compute_pricing::compute_pricing_data),
+        )
+        .route(
+            "/pricing_data/batch",
+            post(handlers::get_pricing_data::get_batch_pricing_data),
+        )
+        .route(
+            "/pricing_data/{job_id}/retry",
+            post(handlers::get_pricing_data::retry_job),
+        )
+        .route(
+            "/pricing_data/{job_id}/cancel",
+            post(handlers::get_pricing_data::cancel_job),
+        )
+        .route(
+            "/pricing_data/{job_id}/webhooks/replay",
+            post(handlers::get_pricing_data::replay_webhook),
+        )
         .layer(from_fn_with_state(app_state.clone(), simple_apikey_auth));
     //.layer(cors_layer.clone());
 
@@ -65,6 +137,10 @@ pub async fn create_app(
             "/job_status/{job_id}",
             get(handlers::job_status::get_job_status),
         )
+        .route(
+            "/job_status/{job_id}/events",
+            get(handlers::job_events::get_job_status_events),
+        )
         .route(
             "/latest_block",
             get(handlers::latest_block::get_latest_block_number),
@@ -1,7 +1,12 @@
 use db_access::{IndexerDbConnection, OffchainProcessorDbConnection};
 use dotenv::dotenv;
-use server::create_app;
+use server::{
+    create_app,
+    job_queue::{JobQueueConfig, JobQueueWorker},
+    AppState,
+};
 use std::{error::Error, sync::Arc};
+use tokio::sync::Notify;
 use tracing::info;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Registry};
 
@@ -15,7 +20,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Perform db migrations
     offchain_processor_db.migrate().await?;
 
-    let app = create_app(offchain_processor_db, indexer_db).await;
+    let app_state = AppState::new(offchain_processor_db, indexer_db, Arc::new(Notify::new()))?;
+    let worker = JobQueueWorker::new(app_state.clone(), JobQueueConfig::from_env());
+
+    let app = create_app(app_state).await;
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
 
     let fmt_layer = fmt::layer()
@@ -32,6 +40,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .with(filter_layer)
         .init();
 
+    // Pick up jobs left behind by a previous run before accepting new ones
+    worker.recover().await?;
+    tokio::spawn(worker.run());
+
     info!("Server is listening on {}", listener.local_addr()?);
     axum::serve(listener, app.into_make_service()).await?;
     Ok(())
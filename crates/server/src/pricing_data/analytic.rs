@@ -0,0 +1,166 @@
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use super::config::{DivergenceAction, PricingConfig};
+
+/// Turnbull–Wakeman/Levy approximation of the arithmetic TWAP of a GBM:
+/// log mean and log variance of the lognormal with the same first two
+/// moments as the average of `exp(X_1) .. exp(X_n)`, where `X_i` is the sum
+/// of `i` independent normal steps of mean `step_drift` and variance
+/// `step_variance`.
+///
+/// With `g = step_drift + step_variance / 2`, `E[exp(X_i)] = exp(i g)` and,
+/// for `i <= j`, `E[exp(X_i + X_j)] = exp((i + j) step_drift + (3i + j)
+/// step_variance / 2)`.
+pub fn average_log_moments(step_drift: f64, step_variance: f64, n_periods: usize) -> (f64, f64) {
+    let n = n_periods.max(1);
+    let g = step_drift + step_variance / 2.0;
+    let first: Vec<f64> = (1..=n).map(|i| (i as f64 * g).exp()).collect();
+
+    // Pairs (i, j) with j > i are summed through the tail sums of `first`
+    let mut later = 0.0;
+    let mut second = 0.0;
+    for i in (1..=n).rev() {
+        let scale = (i as f64 * 1.5f64.mul_add(step_variance, step_drift)).exp();
+        second += scale * 2.0f64.mul_add(later, first[i - 1]);
+        later += first[i - 1];
+    }
+
+    let n = n as f64;
+    let log_first = (first.iter().sum::<f64>() / n).ln();
+    let log_second = (second / (n * n)).ln();
+    let log_variance = 2.0f64.mul_add(-log_first, log_second).max(0.0);
+    (log_first - log_variance / 2.0, log_variance)
+}
+
+/// Monte Carlo reserve price checked against the closed-form one.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AnalyticCrossCheck {
+    /// Turnbull–Wakeman/Levy price of the capped call on the TWAP.
+    pub analytic_reserve_price: f64,
+    /// Monte Carlo minus analytic reserve price.
+    pub gap: f64,
+    /// `gap` as a share of the analytic reserve price, missing when that is
+    /// zero.
+    pub relative_gap: Option<f64>,
+    /// Whether the gap is beyond `analytic_divergence_threshold`.
+    pub diverged: bool,
+}
+
+impl AnalyticCrossCheck {
+    pub fn new(reserve_price: f64, analytic_reserve_price: f64, config: &PricingConfig) -> Self {
+        let gap = reserve_price - analytic_reserve_price;
+        let relative_gap = (analytic_reserve_price != 0.0).then(|| gap / analytic_reserve_price);
+        let diverged = config
+            .analytic_divergence_threshold
+            .is_some_and(|threshold| {
+                relative_gap.map_or(gap != 0.0, |relative| relative.abs() > threshold)
+            });
+
+        Self {
+            analytic_reserve_price,
+            gap,
+            relative_gap,
+            diverged,
+        }
+    }
+
+    /// Fails a diverged check when `config.analytic_divergence_action` is
+    /// `fail`, and only warns about it otherwise.
+    pub fn enforce(&self, config: &PricingConfig) -> Result<()> {
+        if !self.diverged {
+            return Ok(());
+        }
+        let message = format!(
+            "Reserve price {} diverges from the analytic price {} by more than {}",
+            self.gap + self.analytic_reserve_price,
+            self.analytic_reserve_price,
+            config.analytic_divergence_threshold.unwrap_or_default()
+        );
+        match config.analytic_divergence_action {
+            DivergenceAction::Flag => {
+                tracing::warn!("{}", message);
+                Ok(())
+            }
+            DivergenceAction::Fail => Err(eyre!(message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing_data::rng::pricing_rng;
+    use rand_distr::{Distribution, Normal};
+
+    #[test]
+    fn test_single_period_is_exactly_lognormal() {
+        let (log_mean, log_variance) = average_log_moments(0.01, 0.04, 1);
+
+        assert!((log_mean - 0.01).abs() < 1e-12);
+        assert!((log_variance - 0.04).abs() < 1e-12);
+        assert_eq!(average_log_moments(0.0, 0.0, 50), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_moments_match_simulated_averages() {
+        let (step_drift, step_variance, n_periods) = (0.001, 0.02f64.powi(2), 240);
+        let step = Normal::new(step_drift, step_variance.sqrt()).unwrap();
+        let mut rng = pricing_rng(5);
+        let averages: Vec<f64> = (0..20_000)
+            .map(|_| {
+                let mut log_price = 0.0;
+                let mut sum = 0.0;
+                for _ in 0..n_periods {
+                    log_price += step.sample(&mut rng);
+                    sum += f64::exp(log_price);
+                }
+                sum / n_periods as f64
+            })
+            .collect();
+        let n = averages.len() as f64;
+        let mean = averages.iter().sum::<f64>() / n;
+        let second = averages.iter().map(|a| a * a).sum::<f64>() / n;
+
+        let (log_mean, log_variance) = average_log_moments(step_drift, step_variance, n_periods);
+
+        let expected_mean = (log_mean + log_variance / 2.0).exp();
+        let expected_second = 2.0f64.mul_add(log_mean, 2.0 * log_variance).exp();
+        assert!(
+            (mean / expected_mean - 1.0).abs() < 0.01,
+            "{} vs {}",
+            mean,
+            expected_mean
+        );
+        assert!(
+            (second / expected_second - 1.0).abs() < 0.02,
+            "{} vs {}",
+            second,
+            expected_second
+        );
+    }
+
+    #[test]
+    fn test_divergence_beyond_the_threshold_flags_or_fails() {
+        let flag = PricingConfig {
+            analytic_divergence_threshold: Some(0.1),
+            ..Default::default()
+        };
+        let fail = PricingConfig {
+            analytic_divergence_action: DivergenceAction::Fail,
+            ..flag.clone()
+        };
+
+        let close = AnalyticCrossCheck::new(105.0, 100.0, &fail);
+        let far = AnalyticCrossCheck::new(120.0, 100.0, &fail);
+
+        assert_eq!(close.relative_gap, Some(0.05));
+        assert!(!close.diverged && close.enforce(&fail).is_ok());
+        assert!(far.diverged);
+        assert!(far.enforce(&flag).is_ok());
+        assert!(far.enforce(&fail).is_err());
+        // Without a threshold the gap is only reported
+        assert!(!AnalyticCrossCheck::new(120.0, 100.0, &PricingConfig::default()).diverged);
+        assert!(AnalyticCrossCheck::new(1.0, 0.0, &fail).diverged);
+    }
+}
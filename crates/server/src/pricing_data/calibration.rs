@@ -0,0 +1,759 @@
+use eyre::{eyre, Result};
+use ndarray::prelude::*;
+use optimization::{Function, Function1, GradientDescent, Minimizer};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::config::PricingConfig;
+
+/// Smallest normal variance accepted, as a share of the variance of the
+/// series. Below it the normal component has collapsed onto a few points.
+const MIN_VARIANCE_SHARE: f64 = 1e-6;
+
+/// Starting point the MRJ fit always tries first.
+const DEFAULT_START: [f64; 3] = [-3.928e-02, 2.873e-04, 4.617e-02];
+
+/// Parameters of the mean-reverting jump model
+/// `p(t) = a + φ p(t-1) + ε(t) + J(t)`, with normal shocks `ε ~ N(0, σ²)`
+/// and, with probability `λ`, jumps `J ~ N(μ_j, σ_j²)`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct MrjParams {
+    pub a: f64,
+    pub phi: f64,
+    pub mu_j: f64,
+    pub sigma_sq: f64,
+    pub sigma_sq_j: f64,
+    pub lambda: f64,
+}
+
+impl MrjParams {
+    /// From `[a, phi, mu_j, sigma_sq, sigma_sq_j, lambda]`.
+    pub fn from_slice(params: &[f64]) -> Self {
+        Self {
+            a: params[0],
+            phi: params[1],
+            mu_j: params[2],
+            sigma_sq: params[3],
+            sigma_sq_j: params[4],
+            lambda: params[5],
+        }
+    }
+
+    /// `[a, phi, mu_j, sigma_sq, sigma_sq_j, lambda]`, as taken by
+    /// [`super::simulation::PathModel`].
+    pub fn to_vec(&self) -> Vec<f64> {
+        vec![
+            self.a,
+            self.phi,
+            self.mu_j,
+            self.sigma_sq,
+            self.sigma_sq_j,
+            self.lambda,
+        ]
+    }
+
+    /// Why the parameters do not describe a stationary process with a
+    /// normal and a jump component, if they don't.
+    pub fn violation(&self) -> Option<String> {
+        if self.to_vec().iter().any(|param| !param.is_finite()) {
+            Some("non-finite parameter".to_string())
+        } else if self.phi.abs() >= 1.0 {
+            Some(format!("phi = {} is not in (-1, 1)", self.phi))
+        } else if self.sigma_sq <= 0.0 {
+            Some(format!("sigma_sq = {} is not positive", self.sigma_sq))
+        } else if self.sigma_sq_j <= 0.0 {
+            Some(format!("sigma_sq_j = {} is not positive", self.sigma_sq_j))
+        } else if !(0.0..=1.0).contains(&self.lambda) {
+            Some(format!("lambda = {} is not in [0, 1]", self.lambda))
+        } else {
+            None
+        }
+    }
+
+    // Coordinates every point of which is within bounds:
+    // [a, atanh(phi), mu_j, ln(sigma_sq), ln(sigma_sq_j), logit(lambda)]
+    fn to_unbounded(self) -> Option<[f64; 6]> {
+        if self.violation().is_some() || self.lambda <= 0.0 || self.lambda >= 1.0 {
+            return None;
+        }
+        Some([
+            self.a,
+            self.phi.atanh(),
+            self.mu_j,
+            self.sigma_sq.ln(),
+            self.sigma_sq_j.ln(),
+            (self.lambda / (1.0 - self.lambda)).ln(),
+        ])
+    }
+
+    fn from_unbounded(x: &[f64]) -> Self {
+        Self {
+            a: x[0],
+            phi: x[1].tanh(),
+            mu_j: x[2],
+            sigma_sq: x[3].exp(),
+            sigma_sq_j: x[4].exp(),
+            lambda: 1.0 / (1.0 + (-x[5]).exp()),
+        }
+    }
+
+    // Derivative of each parameter with respect to its unbounded coordinate
+    fn unbounded_jacobian(&self) -> [f64; 6] {
+        [
+            1.0,
+            1.0 - self.phi * self.phi,
+            1.0,
+            self.sigma_sq,
+            self.sigma_sq_j,
+            self.lambda * (1.0 - self.lambda),
+        ]
+    }
+}
+
+/// Optimizer an MRJ fit was found with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MrjOptimizer {
+    /// Gradient descent with the analytic gradient, tried first from each
+    /// start.
+    GradientDescent,
+    /// Nelder–Mead over bounded parameters, when gradient descent did not
+    /// converge within bounds.
+    NelderMead,
+}
+
+/// How the MRJ parameters behind a reserve price were fitted.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MrjCalibration {
+    pub params: MrjParams,
+    pub log_likelihood: f64,
+    pub optimizer: MrjOptimizer,
+    /// Norm of the gradient of the negative log-likelihood per observation at
+    /// the fit, in coordinates where the bounds are unreachable
+    /// (`atanh(phi)`, log variances, `logit(lambda)`).
+    pub gradient_norm: f64,
+    pub observations: usize,
+    /// Starting points tried.
+    pub starts: usize,
+    /// Starting points from which an optimizer converged within bounds.
+    pub converged_starts: usize,
+    /// Whether the optimizer that found `params` converged. When no start
+    /// converges, the in-bounds fit with the highest likelihood is kept.
+    pub converged: bool,
+}
+
+/// Fits the MRJ model to a de-seasonalised, detrended log base fee series by
+/// maximum likelihood.
+///
+/// Each of `config.mrj_starts` starting points is optimized by gradient
+/// descent, and by Nelder–Mead over bounded parameters when gradient descent
+/// ends out of bounds or with a gradient norm above
+/// `config.mrj_gradient_tolerance`. The converged fit with the highest
+/// likelihood is kept, or the in-bounds one with the highest likelihood when
+/// none converged.
+///
+/// # Errors
+///
+/// Fails when the series is too short or constant, when every start ends
+/// out of bounds and when the best fit is degenerate.
+pub fn calibrate_mrj(series: ArrayView1<f64>, config: &PricingConfig) -> Result<MrjCalibration> {
+    if series.len() < 8 {
+        return Err(eyre!(
+            "MRJ calibration needs at least 8 points, got {}",
+            series.len()
+        ));
+    }
+    let pt = series.slice(s![1..]).to_owned();
+    let pt_1 = series.slice(s![..-1]).to_owned();
+    let var_pt = pt.var(0.0);
+    if !(var_pt > 0.0 && var_pt.is_finite()) {
+        return Err(eyre!(
+            "Degenerate MRJ calibration: the series has no variance"
+        ));
+    }
+
+    let starts = starting_points(&pt, &pt_1, config.mrj_starts);
+    let fits: Vec<Result<MrjCalibration, String>> = starts
+        .par_iter()
+        .map(|start| fit_from(*start, &pt, &pt_1, config))
+        .collect();
+
+    let converged_starts = fits
+        .iter()
+        .filter(|fit| fit.as_ref().is_ok_and(|fit| fit.converged))
+        .count();
+    // Converged fits before the others, then by likelihood
+    let best = fits
+        .iter()
+        .filter_map(|fit| fit.as_ref().ok())
+        .max_by(|a, b| {
+            a.converged
+                .cmp(&b.converged)
+                .then(a.log_likelihood.total_cmp(&b.log_likelihood))
+        })
+        .cloned();
+    let Some(mut best) = best else {
+        let reasons: Vec<&str> = fits
+            .iter()
+            .filter_map(|fit| fit.as_ref().err())
+            .map(String::as_str)
+            .collect();
+        return Err(eyre!(
+            "MRJ calibration ended out of bounds from all of {} starts: {}",
+            starts.len(),
+            reasons.join("; ")
+        ));
+    };
+
+    if best.params.sigma_sq < MIN_VARIANCE_SHARE * var_pt {
+        return Err(eyre!(
+            "Degenerate MRJ calibration: sigma_sq = {:e} collapsed against a series variance of {:e}",
+            best.params.sigma_sq,
+            var_pt
+        ));
+    }
+
+    best.starts = starts.len();
+    best.converged_starts = converged_starts;
+    if !best.converged {
+        tracing::warn!(
+            "MRJ calibration did not converge from any of {} starts, keeping the best fit with a gradient norm of {:e}",
+            starts.len(),
+            best.gradient_norm
+        );
+    }
+    tracing::info!(
+        "MRJ calibration: {:?} with {:?}, log-likelihood {}, {} of {} starts converged",
+        best.params,
+        best.optimizer,
+        best.log_likelihood,
+        converged_starts,
+        starts.len()
+    );
+    Ok(best)
+}
+
+// The fixed start the model was calibrated from, then starts around an AR(1)
+// fit of the series with more and more weight on jumps
+fn starting_points(pt: &Array1<f64>, pt_1: &Array1<f64>, count: usize) -> Vec<MrjParams> {
+    let var_pt = pt.var(0.0);
+    let mut starts = vec![MrjParams {
+        a: DEFAULT_START[0],
+        phi: DEFAULT_START[1],
+        mu_j: DEFAULT_START[2],
+        sigma_sq: var_pt,
+        sigma_sq_j: var_pt,
+        lambda: 0.2,
+    }];
+
+    let (mean_pt, mean_pt_1) = (pt.mean().unwrap_or(0.0), pt_1.mean().unwrap_or(0.0));
+    let covariance = ((pt - mean_pt) * (pt_1 - mean_pt_1)).mean().unwrap_or(0.0);
+    let variance = pt_1.var(0.0);
+    let phi = if variance > 0.0 {
+        (covariance / variance).clamp(-0.99, 0.99)
+    } else {
+        0.0
+    };
+    let a = mean_pt - phi * mean_pt_1;
+    let residual_variance = (pt - a - phi * pt_1)
+        .mapv(|residual| residual * residual)
+        .mean()
+        .filter(|variance| *variance > 0.0)
+        .unwrap_or(var_pt);
+
+    for i in 1..count {
+        let lambda = (0.05 * i as f64).min(0.5);
+        starts.push(MrjParams {
+            a,
+            phi,
+            mu_j: 0.0,
+            sigma_sq: 0.5 * residual_variance,
+            sigma_sq_j: 2.0 * i as f64 * residual_variance,
+            lambda,
+        });
+    }
+
+    starts.truncate(count.max(1));
+    starts
+}
+
+// Gradient descent from `start`, falling back to Nelder–Mead. The converged
+// fit, or the better of the two that ended within bounds, and otherwise why
+// neither did
+fn fit_from(
+    start: MrjParams,
+    pt: &Array1<f64>,
+    pt_1: &Array1<f64>,
+    config: &PricingConfig,
+) -> Result<MrjCalibration, String> {
+    let observations = pt.len();
+    let calibration = |params: MrjParams, optimizer: MrjOptimizer, converged: bool| {
+        let fit = MrjCalibration {
+            params,
+            log_likelihood: -neg_log_likelihood(&params.to_vec(), pt, pt_1),
+            optimizer,
+            gradient_norm: unbounded_gradient_norm(&params, pt, pt_1),
+            observations,
+            starts: 1,
+            converged_starts: usize::from(converged),
+            converged,
+        };
+        if fit.log_likelihood.is_finite() {
+            Ok(fit)
+        } else {
+            Err("non-finite log-likelihood".to_string())
+        }
+    };
+
+    let minimizer = GradientDescent::new().max_iterations(Some(config.mrj_max_iterations));
+    let solution = minimizer.minimize(&MrjLikelihood { pt, pt_1 }, start.to_vec());
+    let descended = MrjParams::from_slice(&solution.position);
+
+    let descended = match descended.violation() {
+        Some(violation) => Err(violation),
+        None => match calibration(descended, MrjOptimizer::GradientDescent, false) {
+            Ok(fit) if fit.gradient_norm <= config.mrj_gradient_tolerance => {
+                return Ok(MrjCalibration {
+                    converged: true,
+                    converged_starts: 1,
+                    ..fit
+                });
+            }
+            fit => fit,
+        },
+    };
+    let gradient_descent_failure = match &descended {
+        Ok(fit) => format!("gradient norm {:e}", fit.gradient_norm),
+        Err(reason) => reason.clone(),
+    };
+
+    let Some(unbounded_start) = start.to_unbounded() else {
+        return descended.map_err(|_| {
+            format!(
+                "gradient descent ended with {}, start out of bounds",
+                gradient_descent_failure
+            )
+        });
+    };
+    let simplex = nelder_mead(
+        |x| neg_log_likelihood(&MrjParams::from_unbounded(x).to_vec(), pt, pt_1),
+        &unbounded_start,
+        config.mrj_max_iterations,
+    );
+    let simplex_fit = calibration(
+        MrjParams::from_unbounded(&simplex.position),
+        MrjOptimizer::NelderMead,
+        simplex.converged,
+    );
+
+    match (descended, simplex_fit) {
+        (_, Ok(fit)) if fit.converged => Ok(fit),
+        (Ok(descended), Ok(fit)) if descended.log_likelihood > fit.log_likelihood => Ok(descended),
+        (_, Ok(fit)) => Ok(fit),
+        (Ok(descended), Err(_)) => Ok(descended),
+        (Err(_), Err(reason)) => Err(format!(
+            "gradient descent ended with {}, Nelder–Mead with {}",
+            gradient_descent_failure, reason
+        )),
+    }
+}
+
+// Negative log-likelihood of `[a, phi, mu_j, sigma_sq, sigma_sq_j, lambda]`,
+// with its analytic gradient for gradient descent
+struct MrjLikelihood<'a> {
+    pt: &'a Array1<f64>,
+    pt_1: &'a Array1<f64>,
+}
+
+impl Function for MrjLikelihood<'_> {
+    fn value(&self, position: &[f64]) -> f64 {
+        neg_log_likelihood(position, self.pt, self.pt_1)
+    }
+}
+
+impl Function1 for MrjLikelihood<'_> {
+    fn gradient(&self, position: &[f64]) -> Vec<f64> {
+        neg_log_likelihood_gradient(&MrjParams::from_slice(position), self.pt, self.pt_1).to_vec()
+    }
+}
+
+/// Outcome of [`nelder_mead`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct NelderMeadResult {
+    pub position: Vec<f64>,
+    pub value: f64,
+    pub iterations: usize,
+    /// Whether the simplex shrank to a point before `max_iterations`.
+    pub converged: bool,
+}
+
+/// Minimizes `f` by the Nelder–Mead simplex method, from a simplex around
+/// `start` with a side of 0.1 or a tenth of the coordinate, whichever is
+/// larger. Non-finite values count as +∞.
+pub fn nelder_mead(
+    f: impl Fn(&[f64]) -> f64,
+    start: &[f64],
+    max_iterations: usize,
+) -> NelderMeadResult {
+    let value = |x: &[f64]| {
+        let value = f(x);
+        if value.is_finite() {
+            value
+        } else {
+            f64::INFINITY
+        }
+    };
+    let n = start.len();
+
+    let mut simplex: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n + 1);
+    simplex.push((start.to_vec(), value(start)));
+    for i in 0..n {
+        let mut vertex = start.to_vec();
+        vertex[i] += (0.1 * vertex[i].abs()).max(0.1);
+        let fx = value(&vertex);
+        simplex.push((vertex, fx));
+    }
+
+    let point = |centroid: &[f64], towards: &[f64], coefficient: f64| -> Vec<f64> {
+        centroid
+            .iter()
+            .zip(towards)
+            .map(|(c, t)| c + coefficient * (t - c))
+            .collect()
+    };
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < max_iterations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, worst) = (simplex[0].1, simplex[n].1);
+        let size = simplex[1..]
+            .iter()
+            .flat_map(|(vertex, _)| vertex.iter().zip(&simplex[0].0).map(|(v, b)| (v - b).abs()))
+            .fold(0.0, f64::max);
+        if (worst - best).abs() <= 1e-10 * (1.0 + best.abs()) && size <= 1e-7 {
+            converged = true;
+            break;
+        }
+        iterations += 1;
+
+        let centroid: Vec<f64> = (0..n)
+            .map(|i| {
+                simplex[..n]
+                    .iter()
+                    .map(|(vertex, _)| vertex[i])
+                    .sum::<f64>()
+                    / n as f64
+            })
+            .collect();
+        let reflected = point(&centroid, &simplex[n].0, -1.0);
+        let f_reflected = value(&reflected);
+
+        if f_reflected < simplex[0].1 {
+            let expanded = point(&centroid, &simplex[n].0, -2.0);
+            let f_expanded = value(&expanded);
+            simplex[n] = if f_expanded < f_reflected {
+                (expanded, f_expanded)
+            } else {
+                (reflected, f_reflected)
+            };
+        } else if f_reflected < simplex[n - 1].1 {
+            simplex[n] = (reflected, f_reflected);
+        } else {
+            let (towards, f_towards) = if f_reflected < simplex[n].1 {
+                (reflected, f_reflected)
+            } else {
+                (simplex[n].0.clone(), simplex[n].1)
+            };
+            let contracted = point(&centroid, &towards, 0.5);
+            let f_contracted = value(&contracted);
+            if f_contracted < f_towards {
+                simplex[n] = (contracted, f_contracted);
+            } else {
+                // Shrink towards the best vertex
+                let best_vertex = simplex[0].0.clone();
+                for (vertex, fx) in simplex[1..].iter_mut() {
+                    *vertex = point(&best_vertex, vertex, 0.5);
+                    *fx = value(vertex);
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let (position, value) = simplex.swap_remove(0);
+    NelderMeadResult {
+        position,
+        value,
+        iterations,
+        converged,
+    }
+}
+
+// Norm of the gradient per observation in the unbounded coordinates
+fn unbounded_gradient_norm(params: &MrjParams, pt: &Array1<f64>, pt_1: &Array1<f64>) -> f64 {
+    let gradient = neg_log_likelihood_gradient(params, pt, pt_1);
+    let jacobian = params.unbounded_jacobian();
+    let norm = gradient
+        .iter()
+        .zip(jacobian)
+        .map(|(g, j)| (g * j).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    norm / pt.len().max(1) as f64
+}
+
+/// Calculates the probability density function (PDF) for the Mean-Reverting Jump (MRJ) model.
+///
+/// This function computes the PDF of the MRJ model given the model parameters and observed prices.
+///
+/// # Arguments
+///
+/// * `params` - A slice of f64 values representing the model parameters:
+///   [a, phi, `mu_j`, `sigma_sq`, `sigma_sq_j`, `lambda`]
+/// * `pt` - An `Array1<f64>` of observed prices at time t
+/// * `pt_1` - An `Array1<f64>` of observed prices at time t-1
+///
+/// # Returns
+///
+/// * `Array1<f64>` - The calculated PDF values
+///
+/// # Notes
+///
+/// The MRJ model combines a mean-reverting process with a jump component. The PDF is a mixture
+/// of two normal distributions, weighted by the jump probability (lambda).
+fn mrjpdf(params: &[f64], pt: &Array1<f64>, pt_1: &Array1<f64>) -> Array1<f64> {
+    let (a, phi, mu_j, sigma_sq, sigma_sq_j, lambda) = (
+        params[0], params[1], params[2], params[3], params[4], params[5],
+    );
+
+    let term1 = lambda
+        * (-((pt - a - phi * pt_1 - mu_j).mapv(|x| x.powi(2))) / (2.0 * (sigma_sq + sigma_sq_j)))
+            .mapv(f64::exp)
+        / ((2.0 * std::f64::consts::PI * (sigma_sq + sigma_sq_j)).sqrt());
+
+    let term2 = (1.0 - lambda)
+        * (-((pt - a - phi * pt_1).mapv(|x| x.powi(2))) / (2.0 * sigma_sq)).mapv(f64::exp)
+        / ((2.0 * std::f64::consts::PI * sigma_sq).sqrt());
+
+    term1 + term2
+}
+
+/// Calculates the negative log-likelihood for the mean-reverting jump diffusion model.
+///
+/// This function computes the negative log-likelihood of the observed data given the model parameters.
+/// It's used in parameter estimation for the mean-reverting jump diffusion model.
+///
+/// # Arguments
+///
+/// * `params` - A slice of f64 values representing the model parameters:
+///   [`a`, `phi`, `mu_j`, `sigma_sq`, `sigma_sq_j`, `lambda`]
+/// * `pt` - An `Array1<f64>` of observed prices at time t
+/// * `pt_1` - An `Array1<f64>` of observed prices at time t-1
+///
+/// # Returns
+///
+/// * `f64` - The negative log-likelihood value
+///
+/// # Notes
+///
+/// The function adds a small constant (1e-10) to each PDF value before taking the logarithm
+/// to avoid potential issues with zero values.
+fn neg_log_likelihood(params: &[f64], pt: &Array1<f64>, pt_1: &Array1<f64>) -> f64 {
+    let pdf_vals = mrjpdf(params, pt, pt_1);
+    -pdf_vals.mapv(|x| (x + 1e-10).ln()).sum()
+}
+
+/// Gradient of [`neg_log_likelihood`] with respect to
+/// [`a`, `phi`, `mu_j`, `sigma_sq`, `sigma_sq_j`, `lambda`].
+fn neg_log_likelihood_gradient(
+    params: &MrjParams,
+    pt: &Array1<f64>,
+    pt_1: &Array1<f64>,
+) -> [f64; 6] {
+    let normal = |x: f64, variance: f64| {
+        (-x * x / (2.0 * variance)).exp() / (2.0 * std::f64::consts::PI * variance).sqrt()
+    };
+    let jump_variance = params.sigma_sq + params.sigma_sq_j;
+
+    let mut gradient = [0.0; 6];
+    for (p, p_1) in pt.iter().zip(pt_1) {
+        let residual = p - params.a - params.phi * p_1;
+        let jump_residual = residual - params.mu_j;
+        let jump_density = normal(jump_residual, jump_variance);
+        let normal_density = normal(residual, params.sigma_sq);
+        let jump = params.lambda * jump_density;
+        let no_jump = (1.0 - params.lambda) * normal_density;
+        let pdf = jump + no_jump + 1e-10;
+
+        // Derivatives of the pdf, before dividing by it
+        let d_residual =
+            jump * jump_residual / jump_variance + no_jump * residual / params.sigma_sq;
+        let d_jump_variance = jump
+            * (jump_residual * jump_residual / (2.0 * jump_variance * jump_variance)
+                - 1.0 / (2.0 * jump_variance));
+        let d_sigma_sq = no_jump
+            * (residual * residual / (2.0 * params.sigma_sq * params.sigma_sq)
+                - 1.0 / (2.0 * params.sigma_sq));
+
+        gradient[0] -= d_residual / pdf;
+        gradient[1] -= d_residual * p_1 / pdf;
+        gradient[2] -= jump * jump_residual / jump_variance / pdf;
+        gradient[3] -= (d_jump_variance + d_sigma_sq) / pdf;
+        gradient[4] -= d_jump_variance / pdf;
+        gradient[5] -= (jump_density - normal_density) / pdf;
+    }
+    gradient
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing_data::rng::pricing_rng;
+    use rand::Rng;
+    use rand_distr::{Distribution, Normal};
+
+    const TRUE_PARAMS: MrjParams = MrjParams {
+        a: 0.01,
+        phi: 0.6,
+        mu_j: 0.5,
+        sigma_sq: 0.01,
+        sigma_sq_j: 0.04,
+        lambda: 0.05,
+    };
+
+    fn mrj_series(params: &MrjParams, n: usize, seed: u64) -> Array1<f64> {
+        let mut rng = pricing_rng(seed);
+        let shock = Normal::new(0.0, params.sigma_sq.sqrt()).unwrap();
+        let jump = Normal::new(params.mu_j, params.sigma_sq_j.sqrt()).unwrap();
+        let mut series = Array1::zeros(n);
+        for t in 1..n {
+            let mut value = params.a + params.phi * series[t - 1] + shock.sample(&mut rng);
+            if rng.gen::<f64>() < params.lambda {
+                value += jump.sample(&mut rng);
+            }
+            series[t] = value;
+        }
+        series
+    }
+
+    #[test]
+    fn test_gradient_matches_finite_differences() {
+        let series = mrj_series(&TRUE_PARAMS, 300, 1);
+        let (pt, pt_1) = (
+            series.slice(s![1..]).to_owned(),
+            series.slice(s![..-1]).to_owned(),
+        );
+        let params = MrjParams {
+            a: 0.02,
+            phi: 0.5,
+            mu_j: 0.3,
+            sigma_sq: 0.015,
+            sigma_sq_j: 0.05,
+            lambda: 0.1,
+        };
+
+        let gradient = neg_log_likelihood_gradient(&params, &pt, &pt_1);
+
+        let x = params.to_vec();
+        for (i, analytic) in gradient.iter().enumerate() {
+            let h = 1e-6 * x[i].abs().max(1e-3);
+            let (mut up, mut down) = (x.clone(), x.clone());
+            up[i] += h;
+            down[i] -= h;
+            let numerical = (neg_log_likelihood(&up, &pt, &pt_1)
+                - neg_log_likelihood(&down, &pt, &pt_1))
+                / (2.0 * h);
+            assert!(
+                (analytic - numerical).abs() <= 1e-4 * (1.0 + numerical.abs()),
+                "parameter {}: analytic {} numerical {}",
+                i,
+                analytic,
+                numerical
+            );
+        }
+    }
+
+    #[test]
+    fn test_calibration_recovers_parameters() {
+        let series = mrj_series(&TRUE_PARAMS, 3000, 2);
+
+        let calibration = calibrate_mrj(series.view(), &PricingConfig::default()).unwrap();
+
+        let fitted = calibration.params;
+        assert!((fitted.phi - TRUE_PARAMS.phi).abs() < 0.05, "{:?}", fitted);
+        assert!(
+            (fitted.sigma_sq - TRUE_PARAMS.sigma_sq).abs() < 0.003,
+            "{:?}",
+            fitted
+        );
+        assert!(
+            (fitted.lambda - TRUE_PARAMS.lambda).abs() < 0.03,
+            "{:?}",
+            fitted
+        );
+        assert!(fitted.violation().is_none());
+        assert!(calibration.log_likelihood.is_finite());
+        assert_eq!(calibration.observations, 2999);
+        assert_eq!(calibration.starts, PricingConfig::default().mrj_starts);
+        assert!(calibration.converged && calibration.converged_starts >= 1);
+    }
+
+    #[test]
+    fn test_unconverged_fit_is_reported() {
+        let series = mrj_series(&TRUE_PARAMS, 300, 3);
+        let config = PricingConfig {
+            mrj_max_iterations: 1,
+            ..Default::default()
+        };
+
+        let calibration = calibrate_mrj(series.view(), &config).unwrap();
+
+        assert!(!calibration.converged);
+        assert_eq!(calibration.converged_starts, 0);
+        assert!(calibration.params.violation().is_none());
+    }
+
+    #[test]
+    fn test_constant_series_is_degenerate() {
+        let result = calibrate_mrj(
+            Array1::from_elem(100, 0.3).view(),
+            &PricingConfig::default(),
+        );
+
+        assert!(result.unwrap_err().to_string().contains("Degenerate"));
+    }
+
+    #[test]
+    fn test_nelder_mead_minimizes_rosenbrock() {
+        let rosenbrock = |x: &[f64]| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2);
+
+        let result = nelder_mead(rosenbrock, &[-1.2, 1.0], 5000);
+
+        assert!(result.converged);
+        assert!((result.position[0] - 1.0).abs() < 1e-3, "{:?}", result);
+        assert!((result.position[1] - 1.0).abs() < 1e-3, "{:?}", result);
+    }
+
+    #[test]
+    fn test_params_out_of_bounds_are_reported() {
+        let violation = |params: MrjParams| params.violation().unwrap();
+
+        assert!(violation(MrjParams {
+            phi: 1.0,
+            ..TRUE_PARAMS
+        })
+        .contains("phi"));
+        assert!(violation(MrjParams {
+            sigma_sq: 0.0,
+            ..TRUE_PARAMS
+        })
+        .contains("sigma_sq"));
+        assert!(violation(MrjParams {
+            lambda: 1.5,
+            ..TRUE_PARAMS
+        })
+        .contains("lambda"));
+        assert_eq!(TRUE_PARAMS.violation(), None);
+    }
+}
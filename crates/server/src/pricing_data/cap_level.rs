@@ -2,10 +2,21 @@ use db_access::models::BlockHeader;
 use eyre::{anyhow as err, Result};
 use polars::prelude::*;
 
+use super::config::{PricingConfig, VolatilityEstimator};
+use super::outliers::select_base_fees;
+use super::rng::pricing_rng_stream;
 use super::utils::{
-    add_twaps, drop_nulls, group_by_1h_or_1m_intervals, prepare_data_frame,
-    replace_timestamp_with_date,
+    add_twaps, bucket_secs, drop_nulls, prepare_data_frame, replace_timestamp_with_date, resample,
+    twap_window_buckets,
 };
+use super::volatility::{
+    bootstrap_volatility, ewma_std, fit_garch, non_overlapping, realized_volatility,
+    VolatilityDiagnostics, VolatilityEstimate,
+};
+
+/// Stream of the seeded generator the bootstrap estimator draws from, apart
+/// from the streams the reserve price simulation uses.
+const BOOTSTRAP_STREAM: u64 = u64::MAX;
 
 /// Calculate cap level to use for the upcoming round
 ///
@@ -15,10 +26,48 @@ use super::utils::{
 /// - Requires `5 * 30d = 150d` of block headers for zkvm/mainnet (testnet uses shorter vaults. i.e 5 * 12m = 1h of block headers)
 ///
 /// cl = (λ - k) / (α * (1 + k)): 0% <= cl < ∞%
-/// - λ = 2.33 x volatility: 0% <= λ < ∞%
+/// - λ = `config.lambda_multiplier` (2.33 by default) x volatility: 0% <= λ < ∞%
 /// - k: -100.00% < k < ∞%
 /// - a: 0.00% < a <= 100%
-pub async fn calculate_cap_level(alpha: u128, k: i128, blocks: Vec<BlockHeader>) -> Result<f64> {
+pub async fn calculate_cap_level(
+    alpha: u128,
+    k: i128,
+    blocks: Vec<BlockHeader>,
+    seed: u64,
+    config: &PricingConfig,
+) -> Result<CapLevelEstimate> {
+    // Validate alpha and k bounds before the volatility is computed
+    cap_level_from_volatility(alpha, k, 0.0, config)?;
+
+    // Calculate volatility
+    let volatility = calculate_volatility(blocks, seed, config).await?;
+    tracing::info!(
+        "Calculated volatiltiy: {} ({:?})",
+        volatility.volatility,
+        volatility.diagnostics.estimator()
+    );
+
+    Ok(CapLevelEstimate {
+        cap_level: cap_level_from_volatility(alpha, k, volatility.volatility, config)?,
+        volatility,
+    })
+}
+
+/// A cap level with the volatility it was computed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapLevelEstimate {
+    pub cap_level: f64,
+    pub volatility: VolatilityEstimate,
+}
+
+/// cl = (λ - k) / (α * (1 + k)) for a given volatility, see
+/// [`calculate_cap_level`].
+pub fn cap_level_from_volatility(
+    alpha: u128,
+    k: i128,
+    volatility: f64,
+    config: &PricingConfig,
+) -> Result<f64> {
     // Validate alpha and k bounds
     if alpha > 10_000 || alpha == 0 {
         return Err(err!("Invalid alpha value: {}", alpha));
@@ -27,12 +76,8 @@ pub async fn calculate_cap_level(alpha: u128, k: i128, blocks: Vec<BlockHeader>)
         return Err(err!("Invalid k value: {}", k));
     }
 
-    // Calculate volatility
-    let volatility = calculate_volatility(blocks).await?;
-    tracing::info!("Calculated volatiltiy: {}", volatility);
-
     // Get percentage values for each variable
-    let lambda = 2.33 * volatility;
+    let lambda = config.lambda_multiplier * volatility;
     let alpha = (alpha as f64) / 10_000.0;
     let k = (k as f64) / 10_000.0;
 
@@ -49,29 +94,224 @@ pub async fn calculate_cap_level(alpha: u128, k: i128, blocks: Vec<BlockHeader>)
 /// - For a 3 hour vault, we will pass 5 * 3 = 15 hours of block headers
 /// - TWAP & return window: 15 * (1/5) = 3 hours
 /// - Volatility window: 15 * (3/5) = 9 hours
-pub async fn calculate_volatility(block_headers: Vec<BlockHeader>) -> Result<f64> {
+///
+/// The 1/5 and 3x ratios and the 720 hour cap come from `config`, as does
+/// the estimator applied to the returns. `seed` drives the bootstrap
+/// estimator.
+pub async fn calculate_volatility(
+    block_headers: Vec<BlockHeader>,
+    seed: u64,
+    config: &PricingConfig,
+) -> Result<VolatilityEstimate> {
+    volatility_series(block_headers, config)?.estimate(config.volatility_estimator, seed, config)
+}
+
+/// Base fees grouped by hour (or minute), with the rolling TWAPs and returns
+/// the volatility is computed from.
+#[derive(Debug, Clone)]
+pub struct VolatilitySeries {
+    /// `date`, `base_fee`, `TWAP_30d` and `30d_returns` columns, from the
+    /// first row with a return.
+    pub frame: DataFrame,
+    /// Rows in the TWAP and return window.
+    pub twap_window: usize,
+    /// Rows the volatility is taken over.
+    pub vol_window: usize,
+    /// Seconds per row of `frame`.
+    pub bucket_secs: u64,
+    /// Start of each bucket, in milliseconds since the epoch, and the sum of
+    /// the squared log changes of the base fee into each of its blocks from
+    /// the block before. Changes from blocks before the previous bucket are
+    /// left out, so gaps do not count as one bucket.
+    pub realized_variances: Vec<(i64, f64)>,
+}
+
+impl VolatilitySeries {
+    /// Sample standard deviation of the returns in the final `vol_window` rows.
+    pub fn volatility(&self) -> Result<f64> {
+        let final_chunk = self.final_window();
+
+        if final_chunk.height() == 0 {
+            return Err(err!(
+                "No rows left after slicing to final volatility window."
+            ));
+        }
+
+        // Compute standard deviation of returns in that final chunk
+        let volatility = final_chunk
+            .column("30d_returns")?
+            .f64()?
+            .std(1) // sample std dev
+            .ok_or_else(|| eyre::eyre!("No data to compute volatility"))?;
+
+        Ok(volatility)
+    }
+
+    /// Estimates the volatility with `estimator`. Every estimator but
+    /// `realized` works on the returns in the final `vol_window` rows, `garch`
+    /// on every `twap_window`-th of them. All of them estimate the standard
+    /// deviation of the same return, the change of the TWAP over
+    /// `twap_window` rows.
+    pub fn estimate(
+        &self,
+        estimator: VolatilityEstimator,
+        seed: u64,
+        config: &PricingConfig,
+    ) -> Result<VolatilityEstimate> {
+        let returns = self.final_returns()?;
+        let observations = returns.len();
+        let no_data = || {
+            err!(
+                "Not enough data for the {:?} volatility estimator",
+                estimator
+            )
+        };
+
+        let (volatility, diagnostics) = match estimator {
+            VolatilityEstimator::SampleStd => (
+                self.volatility()?,
+                VolatilityDiagnostics::SampleStd { observations },
+            ),
+            VolatilityEstimator::Ewma => (
+                ewma_std(&returns, config.ewma_lambda).ok_or_else(no_data)?,
+                VolatilityDiagnostics::Ewma {
+                    observations,
+                    lambda: config.ewma_lambda,
+                },
+            ),
+            VolatilityEstimator::Garch => {
+                // Overlapping returns would be fitted as volatility clusters
+                let returns = non_overlapping(&returns, self.twap_window);
+                let fit = fit_garch(&returns).ok_or_else(no_data)?;
+                let volatility = if fit.converged {
+                    fit.forecast
+                } else {
+                    tracing::warn!(
+                        "GARCH fit did not converge ({:?}), using the sample standard deviation",
+                        fit
+                    );
+                    self.volatility()?
+                };
+                (
+                    volatility,
+                    VolatilityDiagnostics::Garch {
+                        observations: returns.len(),
+                        omega: fit.omega,
+                        alpha: fit.alpha,
+                        beta: fit.beta,
+                        log_likelihood: fit.log_likelihood,
+                        persistence: fit.persistence(),
+                        converged: fit.converged,
+                    },
+                )
+            }
+            VolatilityEstimator::Realized => {
+                let window_start = self.final_window_start()?;
+                let variances: Vec<f64> = self
+                    .realized_variances
+                    .iter()
+                    .filter(|(bucket, _)| *bucket >= window_start)
+                    .map(|(_, variance)| *variance)
+                    .collect();
+                (
+                    realized_volatility(&variances, self.twap_window).ok_or_else(no_data)?,
+                    VolatilityDiagnostics::Realized {
+                        buckets: variances.len(),
+                        horizon: self.twap_window,
+                    },
+                )
+            }
+            VolatilityEstimator::Bootstrap => {
+                let mut rng = pricing_rng_stream(seed, BOOTSTRAP_STREAM);
+                let bootstrap = bootstrap_volatility(
+                    &returns,
+                    self.twap_window,
+                    config.bootstrap_samples,
+                    config.bootstrap_confidence,
+                    &mut rng,
+                )
+                .ok_or_else(no_data)?;
+                (
+                    bootstrap.mean,
+                    VolatilityDiagnostics::Bootstrap {
+                        observations,
+                        samples: config.bootstrap_samples,
+                        block_length: bootstrap.block_length,
+                        std_error: bootstrap.std_error,
+                        confidence: config.bootstrap_confidence,
+                        lower: bootstrap.lower,
+                        upper: bootstrap.upper,
+                    },
+                )
+            }
+        };
+
+        Ok(VolatilityEstimate {
+            volatility,
+            diagnostics,
+        })
+    }
+
+    fn final_window(&self) -> DataFrame {
+        let start_idx = self.frame.height().saturating_sub(self.vol_window);
+        self.frame.slice(start_idx as i64, self.vol_window)
+    }
+
+    fn final_returns(&self) -> Result<Vec<f64>> {
+        Ok(self
+            .final_window()
+            .column("30d_returns")?
+            .f64()?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    // Milliseconds since the epoch of the first row in the final window
+    fn final_window_start(&self) -> Result<i64> {
+        self.final_window()
+            .column("date")?
+            .datetime()?
+            .get(0)
+            .ok_or_else(|| err!("No rows left after slicing to final volatility window."))
+    }
+}
+
+/// The series [`calculate_volatility`] works through, see there.
+pub fn volatility_series(
+    block_headers: Vec<BlockHeader>,
+    config: &PricingConfig,
+) -> Result<VolatilitySeries> {
     // Prepare data frame
-    let mut df = prepare_data_frame(block_headers)?;
+    let mut df = select_base_fees(prepare_data_frame(block_headers)?, config)?;
 
     // Convert timestamps to dates
     df = replace_timestamp_with_date(df)?;
+    let bucket_secs = bucket_secs(&df, config)?;
+    let realized_variances = realized_variances(&df, bucket_secs)?;
 
     // Group by 1-hour intervals for 30d vaults (or by 1-minute for < 30d vaults)
-    df = group_by_1h_or_1m_intervals(df)?;
+    // unless the config sets the bucket size, filling buckets without blocks
+    df = resample(df, config)?;
 
-    // For 30d vaults (zkvm/mainnet), twap_window is `720` (30d in hours)
+    // For 30d vaults (zkvm/mainnet), twap_window is `720` hourly buckets (30d)
     // For testnet, twap_window is 20% of the data size
     // - if a 12 min vault passes 5 * 12 = 60min (1h) of block headers
     // - TWAP window: 60 * (1/5) = 12min
-    let twap_window = ((df.height() as f64) * 0.2).floor().clamp(1.0, 720.0) as usize;
+    // Both are counted in buckets, capped by the `config.twap_window` window
+    let max_twap_window = twap_window_buckets(config, bucket_secs)?;
+    let twap_window = ((df.height() as f64) * config.volatility_twap_ratio)
+        .floor()
+        .clamp(1.0, max_twap_window as f64) as usize;
 
-    // For 30d vaults, vol_window is `2160` (90d in hours)
-    let vol_window = 3 * twap_window;
+    // For 30d vaults, vol_window is `2160` hourly buckets (90d)
+    let vol_window = config.volatility_window_multiplier * twap_window;
 
     tracing::info!(
-        "Using twap_window={} hours, vol_window={} hours",
+        "Using twap_window={} buckets, vol_window={} buckets of {}s",
         twap_window,
-        vol_window
+        vol_window,
+        bucket_secs
     );
 
     // 1. Calculate rolling TWAPs
@@ -82,29 +322,50 @@ pub async fn calculate_volatility(block_headers: Vec<BlockHeader>) -> Result<f64
     df = calculate_returns(df, twap_window)?;
     df = drop_nulls(&df, "30d_returns")?;
 
-    // 3. Calculate volatility over final `vol_window` rows (standard deviation of returns)
-    let start_idx = df.height().saturating_sub(vol_window);
-    let final_chunk = df.slice(start_idx as i64, vol_window);
-
-    if final_chunk.height() == 0 {
-        return Err(err!(
-            "No rows left after slicing to final volatility window."
-        ));
-    }
-
-    // Compute standard deviation of returns in that final chunk
-    let volatility = final_chunk
-        .column("30d_returns")?
-        .f64()?
-        .std(1) // sample std dev
-        .ok_or_else(|| eyre::eyre!("No data to compute volatility"))?;
-
-    Ok(volatility)
+    // 3. The volatility is taken over the final `vol_window` rows
 
     // (NOT NEEDED)
     // Compute rolling volatility
     //df = _compute_volatilitys(df, vol_window)?;
     //df = drop_nulls(&df, "volatility_X")?;
+
+    Ok(VolatilitySeries {
+        frame: df,
+        twap_window,
+        vol_window,
+        bucket_secs,
+        realized_variances,
+    })
+}
+
+// Sum of the squared log changes of the base fee into the blocks of each
+// bucket, from a block in the same or the previous bucket
+fn realized_variances(df: &DataFrame, bucket_secs: u64) -> Result<Vec<(i64, f64)>> {
+    let bucket_millis = bucket_secs as i64 * 1000;
+    let dates = df.column("date")?.datetime()?;
+    let base_fees = df.column("base_fee")?.f64()?;
+
+    let mut variances: Vec<(i64, f64)> = Vec::new();
+    let mut previous: Option<(i64, f64)> = None;
+    for (date, base_fee) in dates.into_iter().zip(base_fees) {
+        let (Some(date), Some(base_fee)) = (date, base_fee) else {
+            continue;
+        };
+        let bucket = date.div_euclid(bucket_millis) * bucket_millis;
+        if let Some((previous_bucket, previous_fee)) = previous {
+            let adjacent = bucket - previous_bucket <= bucket_millis;
+            if adjacent && previous_fee > 0.0 && base_fee > 0.0 {
+                let change = (base_fee / previous_fee).ln().powi(2);
+                match variances.last_mut() {
+                    Some((last, variance)) if *last == bucket => *variance += change,
+                    _ => variances.push((bucket, change)),
+                }
+            }
+        }
+        previous = Some((bucket, base_fee));
+    }
+
+    Ok(variances)
 }
 
 /// Calculates 30-day returns based on TWAP values (column "TWAP_30d").
@@ -152,3 +413,78 @@ fn _compute_volatilitys(df: DataFrame, window_size: usize) -> Result<DataFrame>
     // Fill nulls if needed.
     Ok(df.fill_null(FillNullStrategy::Backward(None))?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing_data::rng::pricing_rng;
+    use rand_distr::{Distribution, Normal};
+
+    // A block a minute, with a base fee following a GBM without drift
+    fn gbm_headers(blocks: usize, block_std: f64, seed: u64) -> Vec<BlockHeader> {
+        let step = Normal::new(0.0, block_std).unwrap();
+        let mut rng = pricing_rng(seed);
+        let mut log_base_fee = 0.0;
+        (0..blocks)
+            .map(|i| {
+                log_base_fee += step.sample(&mut rng);
+                BlockHeader {
+                    block_hash: None,
+                    number: i as i64,
+                    gas_limit: None,
+                    gas_used: None,
+                    base_fee_per_gas: Some(format!(
+                        "{:#x}",
+                        (1e10 * f64::exp(log_base_fee)) as u128
+                    )),
+                    nonce: None,
+                    transaction_root: None,
+                    receipts_root: None,
+                    state_root: None,
+                    timestamp: Some((60 * i).to_string()),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_estimators_agree_on_a_gbm() {
+        let config = PricingConfig {
+            twap_window: 1,
+            bucket_secs: Some(600),
+            volatility_window_multiplier: 500,
+            ewma_lambda: 0.999,
+            ..Default::default()
+        };
+        let block_std = 0.005;
+        let series = volatility_series(gbm_headers(30_200, block_std, 1), &config).unwrap();
+        // The price moves by 10 blocks of variance per bucket, and a return of
+        // the TWAP over 6 buckets across 6 buckets keeps 73 / 18 buckets of it
+        let expected = (block_std.powi(2) * 10.0 * 73.0 / 18.0).sqrt();
+
+        assert_eq!((series.twap_window, series.vol_window), (6, 3000));
+        for estimator in [
+            VolatilityEstimator::SampleStd,
+            VolatilityEstimator::Ewma,
+            VolatilityEstimator::Garch,
+            VolatilityEstimator::Realized,
+            VolatilityEstimator::Bootstrap,
+        ] {
+            let volatility = series.estimate(estimator, 7, &config).unwrap().volatility;
+            // Only the realized variance does not depend on a few thousand
+            // overlapping returns
+            let tolerance = if estimator == VolatilityEstimator::Realized {
+                0.05
+            } else {
+                0.2
+            };
+            assert!(
+                (volatility / expected - 1.0).abs() < tolerance,
+                "{:?}: {} vs {}",
+                estimator,
+                volatility,
+                expected
+            );
+        }
+    }
+}
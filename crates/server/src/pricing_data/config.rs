@@ -0,0 +1,841 @@
+use std::{collections::HashMap, env, fs};
+
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use starknet_crypto::Felt;
+
+/// Parameters of the pricing model.
+///
+/// The defaults are the values the model was calibrated with for 30 day
+/// mainnet vaults. They can be overridden for a program ID in the file at
+/// `PRICING_CONFIG_FILE`, and per request through `params.pricing_config`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PricingConfig {
+    /// Monte Carlo paths simulated for the reserve price, or per batch when
+    /// `target_relative_error` is set.
+    pub num_paths: usize,
+    /// Hours in the reserve price TWAP, which is also how far ahead the
+    /// simulation runs. Without `bucket_secs` it counts buckets of the
+    /// automatic size instead, i.e. minutes for less than 7 days of headers.
+    pub twap_window: usize,
+    /// Rate the expected payoff is discounted with.
+    pub risk_free_rate: f64,
+    /// Drift of the stochastic trend.
+    pub mu: f64,
+    /// Step of the stochastic trend for an hour of simulation, in days.
+    /// Scaled to the bucket size, as every bucket is one step.
+    pub dt: f64,
+    /// λ = `lambda_multiplier` × volatility in the cap level.
+    pub lambda_multiplier: f64,
+    /// Share of the cap level headers used as TWAP and return window when
+    /// estimating volatility. Never more than `twap_window` hours.
+    pub volatility_twap_ratio: f64,
+    /// Volatility window as a multiple of the TWAP window.
+    pub volatility_window_multiplier: usize,
+    /// How the cap level volatility is estimated from the returns in the
+    /// volatility window.
+    pub volatility_estimator: VolatilityEstimator,
+    /// Decay of the `ewma` estimator: the weight of each return is this
+    /// multiple of the weight of the next one.
+    pub ewma_lambda: f64,
+    /// Resamples drawn by the `bootstrap` estimator.
+    pub bootstrap_samples: usize,
+    /// Coverage of the `bootstrap` estimator's confidence bounds.
+    pub bootstrap_confidence: f64,
+    /// Simulate paths in pairs with mirrored normal shocks.
+    pub antithetic: bool,
+    /// Correct the estimate with the exactly priced payoff on the geometric
+    /// TWAP of the stochastic trend alone.
+    pub control_variate: bool,
+    /// Keep adding batches of `num_paths` paths until the standard error is at
+    /// most this share of the reserve price. A single batch when unset.
+    pub target_relative_error: Option<f64>,
+    /// Most paths simulated while chasing `target_relative_error`.
+    pub max_num_paths: usize,
+    /// Also estimate how the reserve price moves with k, the cap level, the
+    /// volatility and the risk-free rate, by central finite differences on
+    /// the same random numbers.
+    pub sensitivities: bool,
+    /// Share of its value each input is moved by for `sensitivities`, or the
+    /// absolute move of an input that is zero.
+    pub sensitivity_bump: f64,
+    /// Share of the analytic reserve price by which the Monte Carlo one may
+    /// differ before `analytic_divergence_action` is taken. The gap is only
+    /// reported when unset.
+    pub analytic_divergence_threshold: Option<f64>,
+    /// What happens to a job whose reserve price diverges from the analytic
+    /// one.
+    pub analytic_divergence_action: DivergenceAction,
+    /// Iterations of each optimizer fitting the MRJ model from a start.
+    pub mrj_max_iterations: usize,
+    /// Starting points the MRJ fit is optimized from.
+    pub mrj_starts: usize,
+    /// Largest gradient norm per observation at which gradient descent counts
+    /// as converged. Nelder–Mead takes over above it.
+    pub mrj_gradient_tolerance: f64,
+    /// Seconds per bucket base fees are averaged over before any window is
+    /// applied, dividing an hour. When unset, a minute for less than 7 days of
+    /// headers and an hour otherwise.
+    pub bucket_secs: Option<u64>,
+    /// What fills buckets without any block.
+    pub gap_fill: GapFill,
+    /// Robust filter flagging base fee spikes. Outliers are always counted,
+    /// and replaced only when `base_fee_input` is `filtered`.
+    pub outlier_filter: OutlierFilter,
+    /// Scaled median absolute deviations from the median beyond which a base
+    /// fee is an outlier, for the `mad` and `hampel` filters.
+    pub outlier_threshold: f64,
+    /// Blocks on each side of a base fee in the `hampel` window.
+    pub outlier_window: usize,
+    /// Share of base fees clipped at each end by the `winsorize` filter.
+    pub winsorize_quantile: f64,
+    /// Whether the TWAP, volatility and reserve price are computed from raw
+    /// or filtered base fees.
+    pub base_fee_input: BaseFeeInput,
+    /// Least share of each requested range between its first and last
+    /// header for the headers to be priced.
+    pub min_header_coverage: f64,
+    /// Longest time between two consecutive blocks of a range, in seconds.
+    pub max_block_gap_secs: i64,
+    /// Duplicate block numbers and out-of-order timestamps tolerated per range.
+    pub max_header_anomalies: usize,
+    /// What happens to a job whose headers violate a data quality threshold.
+    pub data_quality_action: DataQualityAction,
+}
+
+/// How buckets without any block are filled when resampling base fees.
+///
+/// Rolling windows count buckets, so every bucket between the first and the
+/// last block has to be present for a window to span the time it should.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapFill {
+    /// Repeat the last bucket with blocks.
+    #[default]
+    ForwardFill,
+    /// Linearly interpolate between the buckets around the gap.
+    Interpolate,
+    /// Fail the computation.
+    Error,
+}
+
+/// Estimator of the volatility behind the cap level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VolatilityEstimator {
+    /// Sample standard deviation of the TWAP returns.
+    #[default]
+    SampleStd,
+    /// Exponentially weighted standard deviation of the TWAP returns, the
+    /// latest weighted most.
+    Ewma,
+    /// One step ahead forecast of a GARCH(1,1) fitted to the TWAP returns by
+    /// maximum likelihood.
+    Garch,
+    /// Realized volatility of the block to block base fee changes within each
+    /// bucket, scaled to the TWAP window.
+    Realized,
+    /// Mean of the sample standard deviations of block bootstrap resamples of
+    /// the TWAP returns, with confidence bounds.
+    Bootstrap,
+}
+
+impl VolatilityEstimator {
+    pub const ALL: [Self; 5] = [
+        Self::SampleStd,
+        Self::Ewma,
+        Self::Garch,
+        Self::Realized,
+        Self::Bootstrap,
+    ];
+}
+
+/// How base fee outliers are detected, and what replaces them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlierFilter {
+    /// Clip to `outlier_threshold` scaled MADs around the median of the range.
+    Mad,
+    /// Replace with the median of the `outlier_window` blocks around it when
+    /// more than `outlier_threshold` scaled MADs away from it.
+    #[default]
+    Hampel,
+    /// Clip to the `winsorize_quantile` and 1 - `winsorize_quantile`
+    /// quantiles of the range.
+    Winsorize,
+}
+
+/// Base fees the pricing values are computed from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BaseFeeInput {
+    /// As the blocks report them.
+    #[default]
+    Raw,
+    /// With outliers replaced by `outlier_filter`.
+    Filtered,
+}
+
+/// What happens to a job whose Monte Carlo and analytic reserve prices
+/// diverge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DivergenceAction {
+    /// Mark the result as diverged and log a warning.
+    #[default]
+    Flag,
+    /// Fail the job.
+    Fail,
+}
+
+/// What happens to a job whose headers violate a data quality threshold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataQualityAction {
+    /// Price the headers anyway and log every violation as a warning.
+    #[default]
+    Warn,
+    /// Fail the job with every violation.
+    Fail,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        Self {
+            num_paths: 15_000,
+            twap_window: 720,
+            risk_free_rate: 0.05,
+            mu: 0.05 / 12.0,
+            dt: 1.0 / 24.0,
+            lambda_multiplier: 2.33,
+            volatility_twap_ratio: 0.2,
+            volatility_window_multiplier: 3,
+            volatility_estimator: VolatilityEstimator::default(),
+            ewma_lambda: 0.94,
+            bootstrap_samples: 1000,
+            bootstrap_confidence: 0.95,
+            antithetic: false,
+            control_variate: false,
+            target_relative_error: None,
+            max_num_paths: 120_000,
+            sensitivities: false,
+            sensitivity_bump: 0.01,
+            analytic_divergence_threshold: None,
+            analytic_divergence_action: DivergenceAction::default(),
+            mrj_max_iterations: 2400,
+            mrj_starts: 1,
+            mrj_gradient_tolerance: 1e-3,
+            bucket_secs: None,
+            gap_fill: GapFill::default(),
+            outlier_filter: OutlierFilter::default(),
+            outlier_threshold: 3.0,
+            outlier_window: 25,
+            winsorize_quantile: 0.01,
+            base_fee_input: BaseFeeInput::default(),
+            min_header_coverage: 0.9,
+            max_block_gap_secs: 3600,
+            max_header_anomalies: 0,
+            data_quality_action: DataQualityAction::default(),
+        }
+    }
+}
+
+impl PricingConfig {
+    /// Most paths accepted, to keep a single computation within memory.
+    pub const MAX_NUM_PATHS: usize = 200_000;
+    /// Most paths accepted in total when simulating in batches.
+    pub const MAX_ADAPTIVE_NUM_PATHS: usize = 1_000_000;
+    /// Longest TWAP window accepted: one year in hours.
+    pub const MAX_TWAP_WINDOW: usize = 24 * 365;
+    /// Largest bucket accepted: one hour. Smaller buckets must divide it, so
+    /// windows set in hours span a whole number of buckets.
+    pub const MAX_BUCKET_SECS: u64 = 3600;
+    /// Most bootstrap resamples accepted.
+    pub const MAX_BOOTSTRAP_SAMPLES: usize = 100_000;
+    /// Most MRJ optimizer iterations accepted.
+    pub const MAX_MRJ_ITERATIONS: usize = 100_000;
+    /// Most MRJ starting points accepted.
+    pub const MAX_MRJ_STARTS: usize = 16;
+    /// Widest Hampel window accepted, in blocks on each side.
+    pub const MAX_OUTLIER_WINDOW: usize = 10_000;
+
+    /// Checks every value is usable before a computation starts.
+    pub fn validate(&self) -> Result<()> {
+        if self.num_paths == 0 || self.num_paths > Self::MAX_NUM_PATHS {
+            return Err(eyre!(
+                "num_paths must be between 1 and {}, got {}",
+                Self::MAX_NUM_PATHS,
+                self.num_paths
+            ));
+        }
+        if self.twap_window == 0 || self.twap_window > Self::MAX_TWAP_WINDOW {
+            return Err(eyre!(
+                "twap_window must be between 1 and {} hours, got {}",
+                Self::MAX_TWAP_WINDOW,
+                self.twap_window
+            ));
+        }
+        if !self.risk_free_rate.is_finite() || !(-1.0..=1.0).contains(&self.risk_free_rate) {
+            return Err(eyre!(
+                "risk_free_rate must be between -1 and 1, got {}",
+                self.risk_free_rate
+            ));
+        }
+        if !self.mu.is_finite() {
+            return Err(eyre!("mu must be finite, got {}", self.mu));
+        }
+        if !self.dt.is_finite() || self.dt <= 0.0 || self.dt > 1.0 {
+            return Err(eyre!("dt must be in (0, 1], got {}", self.dt));
+        }
+        if !self.lambda_multiplier.is_finite() || self.lambda_multiplier <= 0.0 {
+            return Err(eyre!(
+                "lambda_multiplier must be positive, got {}",
+                self.lambda_multiplier
+            ));
+        }
+        if !self.volatility_twap_ratio.is_finite()
+            || self.volatility_twap_ratio <= 0.0
+            || self.volatility_twap_ratio > 1.0
+        {
+            return Err(eyre!(
+                "volatility_twap_ratio must be in (0, 1], got {}",
+                self.volatility_twap_ratio
+            ));
+        }
+        if self.volatility_window_multiplier == 0 {
+            return Err(eyre!("volatility_window_multiplier must be at least 1"));
+        }
+        if !(self.ewma_lambda > 0.0 && self.ewma_lambda < 1.0) {
+            return Err(eyre!(
+                "ewma_lambda must be in (0, 1), got {}",
+                self.ewma_lambda
+            ));
+        }
+        if self.bootstrap_samples < 2 || self.bootstrap_samples > Self::MAX_BOOTSTRAP_SAMPLES {
+            return Err(eyre!(
+                "bootstrap_samples must be between 2 and {}, got {}",
+                Self::MAX_BOOTSTRAP_SAMPLES,
+                self.bootstrap_samples
+            ));
+        }
+        if !(self.bootstrap_confidence > 0.0 && self.bootstrap_confidence < 1.0) {
+            return Err(eyre!(
+                "bootstrap_confidence must be in (0, 1), got {}",
+                self.bootstrap_confidence
+            ));
+        }
+        if let Some(bucket_secs) = self.bucket_secs {
+            if bucket_secs == 0 || Self::MAX_BUCKET_SECS % bucket_secs != 0 {
+                return Err(eyre!(
+                    "bucket_secs must divide {}, got {}",
+                    Self::MAX_BUCKET_SECS,
+                    bucket_secs
+                ));
+            }
+        }
+        if self.mrj_max_iterations == 0 || self.mrj_max_iterations > Self::MAX_MRJ_ITERATIONS {
+            return Err(eyre!(
+                "mrj_max_iterations must be between 1 and {}, got {}",
+                Self::MAX_MRJ_ITERATIONS,
+                self.mrj_max_iterations
+            ));
+        }
+        if self.mrj_starts == 0 || self.mrj_starts > Self::MAX_MRJ_STARTS {
+            return Err(eyre!(
+                "mrj_starts must be between 1 and {}, got {}",
+                Self::MAX_MRJ_STARTS,
+                self.mrj_starts
+            ));
+        }
+        if !(self.mrj_gradient_tolerance > 0.0 && self.mrj_gradient_tolerance.is_finite()) {
+            return Err(eyre!(
+                "mrj_gradient_tolerance must be positive, got {}",
+                self.mrj_gradient_tolerance
+            ));
+        }
+        if !(self.sensitivity_bump > 0.0 && self.sensitivity_bump < 1.0) {
+            return Err(eyre!(
+                "sensitivity_bump must be in (0, 1), got {}",
+                self.sensitivity_bump
+            ));
+        }
+        if let Some(threshold) = self.analytic_divergence_threshold {
+            if !threshold.is_finite() || threshold <= 0.0 {
+                return Err(eyre!(
+                    "analytic_divergence_threshold must be positive, got {}",
+                    threshold
+                ));
+            }
+        }
+        if !self.outlier_threshold.is_finite() || self.outlier_threshold <= 0.0 {
+            return Err(eyre!(
+                "outlier_threshold must be positive, got {}",
+                self.outlier_threshold
+            ));
+        }
+        if self.outlier_window == 0 || self.outlier_window > Self::MAX_OUTLIER_WINDOW {
+            return Err(eyre!(
+                "outlier_window must be between 1 and {}, got {}",
+                Self::MAX_OUTLIER_WINDOW,
+                self.outlier_window
+            ));
+        }
+        if !(0.0..0.5).contains(&self.winsorize_quantile) {
+            return Err(eyre!(
+                "winsorize_quantile must be in [0, 0.5), got {}",
+                self.winsorize_quantile
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.min_header_coverage) {
+            return Err(eyre!(
+                "min_header_coverage must be in [0, 1], got {}",
+                self.min_header_coverage
+            ));
+        }
+        if self.max_block_gap_secs <= 0 {
+            return Err(eyre!(
+                "max_block_gap_secs must be positive, got {}",
+                self.max_block_gap_secs
+            ));
+        }
+        if let Some(target) = self.target_relative_error {
+            if !target.is_finite() || target <= 0.0 || target >= 1.0 {
+                return Err(eyre!(
+                    "target_relative_error must be in (0, 1), got {}",
+                    target
+                ));
+            }
+            if self.max_num_paths < self.num_paths
+                || self.max_num_paths > Self::MAX_ADAPTIVE_NUM_PATHS
+            {
+                return Err(eyre!(
+                    "max_num_paths must be between num_paths ({}) and {}, got {}",
+                    self.num_paths,
+                    Self::MAX_ADAPTIVE_NUM_PATHS,
+                    self.max_num_paths
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Partial [`PricingConfig`]: every value that is set replaces the one it is
+/// applied to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PricingConfigOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_paths: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub twap_window: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk_free_rate: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mu: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lambda_multiplier: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volatility_twap_ratio: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volatility_window_multiplier: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volatility_estimator: Option<VolatilityEstimator>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ewma_lambda: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bootstrap_samples: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bootstrap_confidence: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub antithetic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_variate: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_relative_error: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_num_paths: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensitivities: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensitivity_bump: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analytic_divergence_threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analytic_divergence_action: Option<DivergenceAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mrj_max_iterations: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mrj_starts: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mrj_gradient_tolerance: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gap_fill: Option<GapFill>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outlier_filter: Option<OutlierFilter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outlier_threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outlier_window: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub winsorize_quantile: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_fee_input: Option<BaseFeeInput>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_header_coverage: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_block_gap_secs: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_header_anomalies: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_quality_action: Option<DataQualityAction>,
+}
+
+impl PricingConfigOverrides {
+    pub fn apply(&self, config: PricingConfig) -> PricingConfig {
+        PricingConfig {
+            num_paths: self.num_paths.unwrap_or(config.num_paths),
+            twap_window: self.twap_window.unwrap_or(config.twap_window),
+            risk_free_rate: self.risk_free_rate.unwrap_or(config.risk_free_rate),
+            mu: self.mu.unwrap_or(config.mu),
+            dt: self.dt.unwrap_or(config.dt),
+            lambda_multiplier: self.lambda_multiplier.unwrap_or(config.lambda_multiplier),
+            volatility_twap_ratio: self
+                .volatility_twap_ratio
+                .unwrap_or(config.volatility_twap_ratio),
+            volatility_window_multiplier: self
+                .volatility_window_multiplier
+                .unwrap_or(config.volatility_window_multiplier),
+            volatility_estimator: self
+                .volatility_estimator
+                .unwrap_or(config.volatility_estimator),
+            ewma_lambda: self.ewma_lambda.unwrap_or(config.ewma_lambda),
+            bootstrap_samples: self.bootstrap_samples.unwrap_or(config.bootstrap_samples),
+            bootstrap_confidence: self
+                .bootstrap_confidence
+                .unwrap_or(config.bootstrap_confidence),
+            antithetic: self.antithetic.unwrap_or(config.antithetic),
+            control_variate: self.control_variate.unwrap_or(config.control_variate),
+            target_relative_error: self.target_relative_error.or(config.target_relative_error),
+            max_num_paths: self.max_num_paths.unwrap_or(config.max_num_paths),
+            sensitivities: self.sensitivities.unwrap_or(config.sensitivities),
+            sensitivity_bump: self.sensitivity_bump.unwrap_or(config.sensitivity_bump),
+            analytic_divergence_threshold: self
+                .analytic_divergence_threshold
+                .or(config.analytic_divergence_threshold),
+            analytic_divergence_action: self
+                .analytic_divergence_action
+                .unwrap_or(config.analytic_divergence_action),
+            mrj_max_iterations: self.mrj_max_iterations.unwrap_or(config.mrj_max_iterations),
+            mrj_starts: self.mrj_starts.unwrap_or(config.mrj_starts),
+            mrj_gradient_tolerance: self
+                .mrj_gradient_tolerance
+                .unwrap_or(config.mrj_gradient_tolerance),
+            bucket_secs: self.bucket_secs.or(config.bucket_secs),
+            gap_fill: self.gap_fill.unwrap_or(config.gap_fill),
+            outlier_filter: self.outlier_filter.unwrap_or(config.outlier_filter),
+            outlier_threshold: self.outlier_threshold.unwrap_or(config.outlier_threshold),
+            outlier_window: self.outlier_window.unwrap_or(config.outlier_window),
+            winsorize_quantile: self.winsorize_quantile.unwrap_or(config.winsorize_quantile),
+            base_fee_input: self.base_fee_input.unwrap_or(config.base_fee_input),
+            min_header_coverage: self
+                .min_header_coverage
+                .unwrap_or(config.min_header_coverage),
+            max_block_gap_secs: self.max_block_gap_secs.unwrap_or(config.max_block_gap_secs),
+            max_header_anomalies: self
+                .max_header_anomalies
+                .unwrap_or(config.max_header_anomalies),
+            data_quality_action: self
+                .data_quality_action
+                .unwrap_or(config.data_quality_action),
+        }
+    }
+
+    /// The overrides that change something on top of `base`: fields set to the
+    /// value `base` already has are left unset.
+    pub fn without_defaults(&self, base: &PricingConfig) -> Self {
+        fn unless<T: PartialEq>(value: Option<T>, base: T) -> Option<T> {
+            value.filter(|value| *value != base)
+        }
+        fn unless_some<T: PartialEq>(value: Option<T>, base: Option<T>) -> Option<T> {
+            value.filter(|value| Some(value) != base.as_ref())
+        }
+
+        Self {
+            num_paths: unless(self.num_paths, base.num_paths),
+            twap_window: unless(self.twap_window, base.twap_window),
+            risk_free_rate: unless(self.risk_free_rate, base.risk_free_rate),
+            mu: unless(self.mu, base.mu),
+            dt: unless(self.dt, base.dt),
+            lambda_multiplier: unless(self.lambda_multiplier, base.lambda_multiplier),
+            volatility_twap_ratio: unless(self.volatility_twap_ratio, base.volatility_twap_ratio),
+            volatility_window_multiplier: unless(
+                self.volatility_window_multiplier,
+                base.volatility_window_multiplier,
+            ),
+            volatility_estimator: unless(self.volatility_estimator, base.volatility_estimator),
+            ewma_lambda: unless(self.ewma_lambda, base.ewma_lambda),
+            bootstrap_samples: unless(self.bootstrap_samples, base.bootstrap_samples),
+            bootstrap_confidence: unless(self.bootstrap_confidence, base.bootstrap_confidence),
+            antithetic: unless(self.antithetic, base.antithetic),
+            control_variate: unless(self.control_variate, base.control_variate),
+            target_relative_error: unless_some(
+                self.target_relative_error,
+                base.target_relative_error,
+            ),
+            max_num_paths: unless(self.max_num_paths, base.max_num_paths),
+            sensitivities: unless(self.sensitivities, base.sensitivities),
+            sensitivity_bump: unless(self.sensitivity_bump, base.sensitivity_bump),
+            analytic_divergence_threshold: unless_some(
+                self.analytic_divergence_threshold,
+                base.analytic_divergence_threshold,
+            ),
+            analytic_divergence_action: unless(
+                self.analytic_divergence_action,
+                base.analytic_divergence_action,
+            ),
+            mrj_max_iterations: unless(self.mrj_max_iterations, base.mrj_max_iterations),
+            mrj_starts: unless(self.mrj_starts, base.mrj_starts),
+            mrj_gradient_tolerance: unless(
+                self.mrj_gradient_tolerance,
+                base.mrj_gradient_tolerance,
+            ),
+            bucket_secs: unless_some(self.bucket_secs, base.bucket_secs),
+            gap_fill: unless(self.gap_fill, base.gap_fill),
+            outlier_filter: unless(self.outlier_filter, base.outlier_filter),
+            outlier_threshold: unless(self.outlier_threshold, base.outlier_threshold),
+            outlier_window: unless(self.outlier_window, base.outlier_window),
+            winsorize_quantile: unless(self.winsorize_quantile, base.winsorize_quantile),
+            base_fee_input: unless(self.base_fee_input, base.base_fee_input),
+            min_header_coverage: unless(self.min_header_coverage, base.min_header_coverage),
+            max_block_gap_secs: unless(self.max_block_gap_secs, base.max_block_gap_secs),
+            max_header_anomalies: unless(self.max_header_anomalies, base.max_header_anomalies),
+            data_quality_action: unless(self.data_quality_action, base.data_quality_action),
+        }
+    }
+}
+
+/// Deployment-wide pricing configuration, read from the JSON file at
+/// `PRICING_CONFIG_FILE`:
+///
+/// ```json
+/// {
+///   "defaults": { "num_paths": 20000 },
+///   "programs": { "0x50495443485f4c414b455f5631": { "lambda_multiplier": 2.0 } }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PricingConfigFile {
+    /// Applied to every program.
+    #[serde(default)]
+    pub defaults: PricingConfigOverrides,
+    /// Applied on top of `defaults`, keyed by hex program ID.
+    #[serde(default)]
+    pub programs: HashMap<String, PricingConfigOverrides>,
+}
+
+impl PricingConfigFile {
+    /// Reads the file at `PRICING_CONFIG_FILE`. Without one, only the built-in
+    /// defaults apply.
+    pub fn from_env() -> Result<Self> {
+        let file: Self = match env::var("PRICING_CONFIG_FILE") {
+            Ok(path) if !path.is_empty() => {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|e| eyre!("Failed to read pricing config {}: {}", path, e))?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| eyre!("Invalid pricing config {}: {}", path, e))?
+            }
+            _ => Self::default(),
+        };
+        file.check_program_ids()?;
+        Ok(file)
+    }
+
+    fn check_program_ids(&self) -> Result<()> {
+        for key in self.programs.keys() {
+            Felt::from_hex(key)
+                .map_err(|e| eyre!("Invalid program ID {} in pricing config: {:?}", key, e))?;
+        }
+        Ok(())
+    }
+
+    /// The config `program_id` runs with when a request overrides nothing:
+    /// defaults, then the program's overrides. Not validated.
+    pub fn program_defaults(&self, program_id: Felt) -> PricingConfig {
+        let mut config = self.defaults.apply(PricingConfig::default());
+        for (key, overrides) in &self.programs {
+            if Felt::from_hex(key).is_ok_and(|key_id| key_id == program_id) {
+                config = overrides.apply(config);
+            }
+        }
+        config
+    }
+
+    /// The validated config a computation for `program_id` runs with:
+    /// defaults, then the program's overrides, then the request's.
+    pub fn resolve(
+        &self,
+        program_id: Felt,
+        request: Option<&PricingConfigOverrides>,
+    ) -> Result<PricingConfig> {
+        self.check_program_ids()?;
+        let mut config = self.program_defaults(program_id);
+        if let Some(overrides) = request {
+            config = overrides.apply(config);
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PROGRAM: &str = "0x50495443485f4c414b455f5631";
+
+    #[test]
+    fn test_defaults_are_valid() {
+        PricingConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn test_request_overrides_win_over_program_and_defaults() {
+        let file: PricingConfigFile = serde_json::from_value(json!({
+            "defaults": { "num_paths": 20000, "twap_window": 360 },
+            "programs": {
+                PROGRAM: { "num_paths": 5000, "lambda_multiplier": 2.0 },
+                "0x1": { "num_paths": 1 }
+            }
+        }))
+        .unwrap();
+        let request = PricingConfigOverrides {
+            lambda_multiplier: Some(1.5),
+            ..Default::default()
+        };
+
+        let config = file
+            .resolve(Felt::from_hex(PROGRAM).unwrap(), Some(&request))
+            .unwrap();
+
+        assert_eq!(config.twap_window, 360);
+        assert_eq!(config.num_paths, 5000);
+        assert_eq!(config.lambda_multiplier, 1.5);
+        assert_eq!(
+            config.risk_free_rate,
+            PricingConfig::default().risk_free_rate
+        );
+    }
+
+    #[test]
+    fn test_overrides_without_defaults() {
+        let file: PricingConfigFile = serde_json::from_value(json!({
+            "defaults": { "num_paths": 20000 },
+            "programs": { PROGRAM: { "bucket_secs": 300 } }
+        }))
+        .unwrap();
+        let base = file.program_defaults(Felt::from_hex(PROGRAM).unwrap());
+        let overrides: PricingConfigOverrides = serde_json::from_value(json!({
+            "num_paths": 20000,
+            "twap_window": base.twap_window,
+            "bucket_secs": 300,
+            "risk_free_rate": 0.01,
+            "target_relative_error": 0.01
+        }))
+        .unwrap();
+
+        let changed = overrides.without_defaults(&base);
+
+        assert_eq!(
+            changed,
+            PricingConfigOverrides {
+                risk_free_rate: Some(0.01),
+                target_relative_error: Some(0.01),
+                ..Default::default()
+            }
+        );
+        assert_eq!(changed.apply(base.clone()), overrides.apply(base));
+    }
+
+    #[test]
+    fn test_invalid_values_are_rejected() {
+        let file = PricingConfigFile::default();
+        let program = Felt::from_hex(PROGRAM).unwrap();
+
+        for overrides in [
+            json!({ "num_paths": 0 }),
+            json!({ "twap_window": 0 }),
+            json!({ "dt": -1.0 }),
+            json!({ "lambda_multiplier": 0.0 }),
+            json!({ "volatility_twap_ratio": 1.5 }),
+            json!({ "volatility_window_multiplier": 0 }),
+            json!({ "ewma_lambda": 1.0 }),
+            json!({ "bootstrap_samples": 1 }),
+            json!({ "bootstrap_confidence": 0.0 }),
+            json!({ "target_relative_error": 0.0 }),
+            json!({ "target_relative_error": 0.01, "max_num_paths": 100 }),
+            json!({ "sensitivity_bump": 0.0 }),
+            json!({ "sensitivity_bump": 1.0 }),
+            json!({ "analytic_divergence_threshold": 0.0 }),
+            json!({ "mrj_starts": 0 }),
+            json!({ "mrj_max_iterations": 0 }),
+            json!({ "mrj_gradient_tolerance": -1.0 }),
+            json!({ "bucket_secs": 0 }),
+            json!({ "bucket_secs": 7 }),
+            json!({ "bucket_secs": 7200 }),
+            json!({ "outlier_threshold": 0.0 }),
+            json!({ "outlier_window": 0 }),
+            json!({ "winsorize_quantile": 0.5 }),
+            json!({ "min_header_coverage": 1.5 }),
+            json!({ "max_block_gap_secs": 0 }),
+        ] {
+            let overrides: PricingConfigOverrides = serde_json::from_value(overrides).unwrap();
+            assert!(file.resolve(program, Some(&overrides)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_stored_without_sampling_options_still_parses() {
+        let stored = json!({
+            "num_paths": 15000,
+            "twap_window": 720,
+            "risk_free_rate": 0.05,
+            "mu": 0.05 / 12.0,
+            "dt": 1.0 / 24.0,
+            "lambda_multiplier": 2.33,
+            "volatility_twap_ratio": 0.2,
+            "volatility_window_multiplier": 3
+        });
+
+        let config: PricingConfig = serde_json::from_value(stored).unwrap();
+
+        assert_eq!(config, PricingConfig::default());
+    }
+
+    #[test]
+    fn test_gap_fill_override() {
+        let overrides: PricingConfigOverrides =
+            serde_json::from_value(json!({ "bucket_secs": 300, "gap_fill": "interpolate" }))
+                .unwrap();
+
+        let config = overrides.apply(PricingConfig::default());
+
+        assert_eq!(config.bucket_secs, Some(300));
+        assert_eq!(config.gap_fill, GapFill::Interpolate);
+    }
+
+    #[test]
+    fn test_unknown_override_is_rejected() {
+        let overrides = serde_json::from_value::<PricingConfigOverrides>(json!({ "paths": 10 }));
+
+        assert!(overrides.is_err());
+    }
+}
@@ -0,0 +1,422 @@
+use db_access::models::BlockHeader;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use super::config::{DataQualityAction, PricingConfig};
+use super::model::PricingHeaders;
+use super::utils::hex_string_to_f64;
+use crate::types::PitchLakeJobRequestParams;
+
+/// Most anomalies of a range listed in its report. All of them are counted.
+const MAX_LISTED_ANOMALIES: usize = 10;
+
+/// Data quality of the headers fetched for each range of a request.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DataQualityReport {
+    pub twap: RangeQuality,
+    pub cap_level: RangeQuality,
+    pub reserve_price: RangeQuality,
+}
+
+impl DataQualityReport {
+    pub fn new(
+        (twap, cap_level, reserve_price): &PricingHeaders,
+        params: &PitchLakeJobRequestParams,
+    ) -> Self {
+        Self {
+            twap: RangeQuality::new(twap, params.twap),
+            cap_level: RangeQuality::new(cap_level, params.cap_level),
+            reserve_price: RangeQuality::new(reserve_price, params.reserve_price),
+        }
+    }
+
+    /// Every threshold of `config` a range violates, prefixed with the range.
+    pub fn violations(&self, config: &PricingConfig) -> Vec<String> {
+        [
+            ("TWAP", &self.twap),
+            ("cap level", &self.cap_level),
+            ("reserve price", &self.reserve_price),
+        ]
+        .into_iter()
+        .flat_map(|(name, range)| {
+            range
+                .violations(config)
+                .into_iter()
+                .map(move |violation| format!("{} range: {}", name, violation))
+        })
+        .collect()
+    }
+
+    /// Fails with every violation when any threshold is violated and
+    /// `data_quality_action` is `fail`. Otherwise they are only logged.
+    pub fn check(&self, config: &PricingConfig) -> Result<()> {
+        let violations = self.violations(config);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let message = format!("Data quality check failed: {}", violations.join("; "));
+        match config.data_quality_action {
+            DataQualityAction::Warn => {
+                tracing::warn!("{}, pricing anyway", message);
+                Ok(())
+            }
+            DataQualityAction::Fail => Err(eyre!(message)),
+        }
+    }
+}
+
+/// Data quality of the headers of one requested time range.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RangeQuality {
+    pub requested: (i64, i64),
+    pub headers: usize,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
+    /// Share of the requested range between the first and last header, 0 to 1.
+    pub coverage: f64,
+    /// Longest time between two consecutive blocks.
+    pub largest_gap: Option<BlockGap>,
+    /// Block numbers skipped between the first and the last header.
+    pub missing_blocks: u64,
+    pub anomaly_counts: AnomalyCounts,
+    /// The first anomalies found.
+    pub anomalies: Vec<Anomaly>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BlockGap {
+    pub from_block: i64,
+    pub to_block: i64,
+    pub seconds: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct AnomalyCounts {
+    pub duplicate_blocks: usize,
+    pub non_monotonic_timestamps: usize,
+    pub zero_base_fees: usize,
+    /// Headers without a readable timestamp or base fee.
+    pub unreadable_headers: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Anomaly {
+    DuplicateBlock {
+        number: i64,
+    },
+    NonMonotonicTimestamp {
+        number: i64,
+        timestamp: i64,
+        previous_timestamp: i64,
+    },
+    /// Breaks the log of base fees the reserve price is fitted on.
+    ZeroBaseFee {
+        number: i64,
+    },
+    UnreadableHeader {
+        number: i64,
+    },
+}
+
+impl RangeQuality {
+    pub fn new(headers: &[BlockHeader], requested: (i64, i64)) -> Self {
+        let mut counts = AnomalyCounts::default();
+        let mut anomalies = Vec::new();
+        let mut record = |anomaly: Anomaly| {
+            if anomalies.len() < MAX_LISTED_ANOMALIES {
+                anomalies.push(anomaly);
+            }
+        };
+
+        let mut blocks: Vec<(i64, i64)> = Vec::with_capacity(headers.len());
+        for header in headers {
+            let timestamp = header
+                .timestamp
+                .as_deref()
+                .and_then(|timestamp| timestamp.parse::<i64>().ok());
+            let base_fee = header
+                .base_fee_per_gas
+                .as_ref()
+                .and_then(|base_fee| hex_string_to_f64(base_fee).ok());
+
+            match (timestamp, base_fee) {
+                (Some(timestamp), Some(base_fee)) => {
+                    if base_fee == 0.0 {
+                        counts.zero_base_fees += 1;
+                        record(Anomaly::ZeroBaseFee {
+                            number: header.number,
+                        });
+                    }
+                    blocks.push((header.number, timestamp));
+                }
+                _ => {
+                    counts.unreadable_headers += 1;
+                    record(Anomaly::UnreadableHeader {
+                        number: header.number,
+                    });
+                }
+            }
+        }
+
+        // Blocks are examined in number order, as the indexer returns them
+        blocks.sort_by_key(|(number, _)| *number);
+
+        let mut largest_gap: Option<BlockGap> = None;
+        let mut missing_blocks = 0;
+        for pair in blocks.windows(2) {
+            let ((previous_number, previous_timestamp), (number, timestamp)) = (pair[0], pair[1]);
+
+            if number == previous_number {
+                counts.duplicate_blocks += 1;
+                record(Anomaly::DuplicateBlock { number });
+                continue;
+            }
+            missing_blocks += (number - previous_number - 1) as u64;
+
+            if timestamp < previous_timestamp {
+                counts.non_monotonic_timestamps += 1;
+                record(Anomaly::NonMonotonicTimestamp {
+                    number,
+                    timestamp,
+                    previous_timestamp,
+                });
+                continue;
+            }
+
+            let seconds = timestamp - previous_timestamp;
+            if largest_gap.as_ref().is_none_or(|gap| seconds > gap.seconds) {
+                largest_gap = Some(BlockGap {
+                    from_block: previous_number,
+                    to_block: number,
+                    seconds,
+                });
+            }
+        }
+
+        let first_timestamp = blocks.iter().map(|(_, timestamp)| *timestamp).min();
+        let last_timestamp = blocks.iter().map(|(_, timestamp)| *timestamp).max();
+
+        Self {
+            requested,
+            headers: headers.len(),
+            first_timestamp,
+            last_timestamp,
+            coverage: coverage(requested, first_timestamp.zip(last_timestamp)),
+            largest_gap,
+            missing_blocks,
+            anomaly_counts: counts,
+            anomalies,
+        }
+    }
+
+    pub fn violations(&self, config: &PricingConfig) -> Vec<String> {
+        let counts = &self.anomaly_counts;
+        let mut violations = Vec::new();
+
+        if self.headers == 0 {
+            violations.push("no block headers".to_string());
+            return violations;
+        }
+        if self.coverage < config.min_header_coverage {
+            violations.push(format!(
+                "headers cover {:.1}% of the range, below the required {:.1}%",
+                self.coverage * 100.0,
+                config.min_header_coverage * 100.0
+            ));
+        }
+        if let Some(gap) = self
+            .largest_gap
+            .as_ref()
+            .filter(|gap| gap.seconds > config.max_block_gap_secs)
+        {
+            violations.push(format!(
+                "{}s gap between blocks {} and {}, above the allowed {}s",
+                gap.seconds, gap.from_block, gap.to_block, config.max_block_gap_secs
+            ));
+        }
+        let out_of_order = counts.duplicate_blocks + counts.non_monotonic_timestamps;
+        if out_of_order > config.max_header_anomalies {
+            violations.push(format!(
+                "{} duplicate blocks and {} out-of-order timestamps, above the allowed {}",
+                counts.duplicate_blocks,
+                counts.non_monotonic_timestamps,
+                config.max_header_anomalies
+            ));
+        }
+        if counts.zero_base_fees > 0 {
+            violations.push(format!(
+                "{} blocks with a zero base fee",
+                counts.zero_base_fees
+            ));
+        }
+        if counts.unreadable_headers > 0 {
+            violations.push(format!(
+                "{} headers without a readable timestamp or base fee",
+                counts.unreadable_headers
+            ));
+        }
+
+        violations
+    }
+}
+
+// Share of `requested` within `span`
+fn coverage((start, end): (i64, i64), span: Option<(i64, i64)>) -> f64 {
+    let Some((first, last)) = span else {
+        return 0.0;
+    };
+    if end <= start {
+        return 0.0;
+    }
+    let covered = last.min(end) - first.max(start);
+    (covered.max(0) as f64 / (end - start) as f64).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(number: i64, timestamp: i64, base_fee: &str) -> BlockHeader {
+        BlockHeader {
+            block_hash: None,
+            number,
+            gas_limit: None,
+            gas_used: None,
+            base_fee_per_gas: Some(base_fee.to_string()),
+            nonce: None,
+            transaction_root: None,
+            receipts_root: None,
+            state_root: None,
+            timestamp: Some(timestamp.to_string()),
+        }
+    }
+
+    // One block every 12 seconds from `start` to `end`
+    fn blocks(start: i64, end: i64) -> Vec<BlockHeader> {
+        (start..=end)
+            .step_by(12)
+            .enumerate()
+            .map(|(i, timestamp)| header(i as i64, timestamp, "0x3b9aca00"))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_range_passes() {
+        let quality = RangeQuality::new(&blocks(0, 1200), (0, 1200));
+
+        assert_eq!(quality.coverage, 1.0);
+        assert_eq!(quality.largest_gap.as_ref().unwrap().seconds, 12);
+        assert_eq!(quality.missing_blocks, 0);
+        assert_eq!(quality.anomaly_counts, AnomalyCounts::default());
+        assert!(quality.violations(&PricingConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_partial_coverage_and_gaps_are_reported() {
+        // Headers stop halfway and blocks 10 to 19 are missing
+        let mut headers = blocks(0, 600);
+        headers.retain(|header| !(10..20).contains(&header.number));
+        let quality = RangeQuality::new(&headers, (0, 1200));
+
+        assert_eq!(quality.coverage, 0.5);
+        assert_eq!(quality.missing_blocks, 10);
+        assert_eq!(
+            quality.largest_gap,
+            Some(BlockGap {
+                from_block: 9,
+                to_block: 20,
+                seconds: 132,
+            })
+        );
+
+        let config = PricingConfig {
+            max_block_gap_secs: 60,
+            ..Default::default()
+        };
+        assert_eq!(
+            quality.violations(&config),
+            vec![
+                "headers cover 50.0% of the range, below the required 90.0%".to_string(),
+                "132s gap between blocks 9 and 20, above the allowed 60s".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anomalies_are_counted_and_listed() {
+        let headers = vec![
+            header(1, 0, "0x1"),
+            header(2, 12, "0x0"),
+            header(2, 12, "0x0"),
+            header(3, 6, "0x1"),
+            header(4, 36, "not hex"),
+        ];
+        let quality = RangeQuality::new(&headers, (0, 36));
+
+        assert_eq!(
+            quality.anomaly_counts,
+            AnomalyCounts {
+                duplicate_blocks: 1,
+                non_monotonic_timestamps: 1,
+                zero_base_fees: 2,
+                unreadable_headers: 1,
+            }
+        );
+        assert_eq!(
+            quality.anomalies[4],
+            Anomaly::NonMonotonicTimestamp {
+                number: 3,
+                timestamp: 6,
+                previous_timestamp: 12,
+            }
+        );
+        assert_eq!(
+            quality.violations(&PricingConfig::default()).len(),
+            4,
+            "{:?}",
+            quality.violations(&PricingConfig::default())
+        );
+    }
+
+    #[test]
+    fn test_report_check_names_the_failing_range() {
+        let params = PitchLakeJobRequestParams {
+            twap: (0, 1200),
+            cap_level: (0, 1200),
+            reserve_price: (0, 1200),
+            ..Default::default()
+        };
+        let headers = (blocks(0, 1200), vec![], blocks(0, 1200));
+        let config = PricingConfig {
+            data_quality_action: DataQualityAction::Fail,
+            ..Default::default()
+        };
+
+        let error = DataQualityReport::new(&headers, &params)
+            .check(&config)
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Data quality check failed: cap level range: no block headers"
+        );
+    }
+
+    #[test]
+    fn test_report_check_only_warns_by_default() {
+        let params = PitchLakeJobRequestParams {
+            twap: (0, 1200),
+            cap_level: (0, 1200),
+            reserve_price: (0, 1200),
+            ..Default::default()
+        };
+        let mut zero_base_fee = blocks(0, 1200);
+        zero_base_fee[3] = header(3, 36, "0x0");
+        let headers = (blocks(0, 1200), zero_base_fee, blocks(0, 1200));
+        let report = DataQualityReport::new(&headers, &params);
+
+        assert_eq!(report.cap_level.anomaly_counts.zero_base_fees, 1);
+        assert!(report.check(&PricingConfig::default()).is_ok());
+    }
+}
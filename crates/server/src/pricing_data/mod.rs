@@ -1,4 +1,13 @@
+pub mod analytic;
+pub mod calibration;
 pub mod cap_level;
+pub mod config;
+pub mod data_quality;
+pub mod model;
+pub mod outliers;
 pub mod reserve_price;
+pub mod rng;
+pub mod simulation;
 pub mod twap;
+pub mod volatility;
 mod utils;
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock};
+
+use db_access::models::BlockHeader;
+use eyre::{eyre, Result};
+use starknet_crypto::Felt;
+use starknet_handler::PITCH_LAKE_V1;
+use tokio::{join, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+use super::cap_level::{calculate_cap_level, CapLevelEstimate};
+use super::config::PricingConfig;
+use super::reserve_price::{
+    calculate_analytic_reserve_price, calculate_reserve_price, ReservePriceEstimate,
+};
+use super::twap::calculate_twap;
+use super::volatility::VolatilityEstimate;
+use crate::types::PitchLakeJobRequestParams;
+
+/// Block headers for the TWAP, cap level and reserve price ranges, in that order.
+pub type PricingHeaders = (Vec<BlockHeader>, Vec<BlockHeader>, Vec<BlockHeader>);
+
+/// Pricing computation in flight. Jobs are priced inside `spawn_blocking`,
+/// so it doesn't have to be `Send`.
+pub type PricingFuture<'a> = Pin<Box<dyn Future<Output = Result<PricingOutput>> + 'a>>;
+
+/// Values a pricing model computes for one round.
+#[derive(Debug, Clone)]
+pub struct PricingOutput {
+    pub twap: f64,
+    pub cap_level: f64,
+    /// Volatility the cap level was computed from, for models that use one.
+    pub volatility: Option<VolatilityEstimate>,
+    pub reserve_price: ReservePriceEstimate,
+}
+
+/// Pricing stopped because its caller gave up on it, e.g. on a timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct PricingCancelled;
+
+impl fmt::Display for PricingCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pricing was cancelled")
+    }
+}
+
+impl std::error::Error for PricingCancelled {}
+
+/// Turns the header series of a request into its pricing values.
+///
+/// Each program ID is priced by one model, looked up in the
+/// [`PricingModelRegistry`]. Once `cancel` fires, models stop with
+/// [`PricingCancelled`] instead of finishing the computation.
+pub trait PricingModel: Send + Sync {
+    fn price<'a>(
+        &'a self,
+        headers: PricingHeaders,
+        params: &'a PitchLakeJobRequestParams,
+        seed: u64,
+        config: &'a PricingConfig,
+        cancel: &'a CancellationToken,
+    ) -> PricingFuture<'a>;
+}
+
+/// The Pitch Lake v1 model: TWAP over the TWAP range, cap level from the
+/// volatility of the cap level range and a reserve price simulated from a
+/// mean-reverting jump diffusion fitted to the reserve price range.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MrjPricingModel;
+
+impl PricingModel for MrjPricingModel {
+    fn price<'a>(
+        &'a self,
+        (twap, cap_level, reserve): PricingHeaders,
+        params: &'a PitchLakeJobRequestParams,
+        seed: u64,
+        config: &'a PricingConfig,
+        cancel: &'a CancellationToken,
+    ) -> PricingFuture<'a> {
+        Box::pin(async move {
+            let now = Instant::now();
+            tracing::info!("Started processing...");
+
+            let twap = calculate_twap(twap, config);
+            let CapLevelEstimate {
+                cap_level,
+                volatility,
+            } = calculate_cap_level(params.alpha, params.k, cap_level, seed, config)
+                .await
+                .inspect_err(|e| tracing::error!("No cap level to pass to reserve price {}.", e))?;
+            let reserve_price =
+                calculate_reserve_price(reserve, cap_level, params.k, seed, config, cancel);
+
+            let (twap, reserve_price) = join!(twap, reserve_price);
+            tracing::info!("Elapsed: {:.2?}", now.elapsed());
+
+            Ok(PricingOutput {
+                twap: twap?,
+                cap_level,
+                volatility: Some(volatility),
+                reserve_price: reserve_price?,
+            })
+        })
+    }
+}
+
+/// Pitch Lake v1 with a closed-form reserve price: the TWAP and cap level of
+/// [`MrjPricingModel`], and the Turnbull–Wakeman/Levy price of the capped
+/// call on the TWAP instead of the Monte Carlo. Nothing is fitted or
+/// simulated for the reserve price, so it runs in milliseconds.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AnalyticPricingModel;
+
+impl PricingModel for AnalyticPricingModel {
+    fn price<'a>(
+        &'a self,
+        (twap, cap_level, reserve): PricingHeaders,
+        params: &'a PitchLakeJobRequestParams,
+        seed: u64,
+        config: &'a PricingConfig,
+        _cancel: &'a CancellationToken,
+    ) -> PricingFuture<'a> {
+        Box::pin(async move {
+            let twap = calculate_twap(twap, config);
+            let CapLevelEstimate {
+                cap_level,
+                volatility,
+            } = calculate_cap_level(params.alpha, params.k, cap_level, seed, config).await?;
+            let reserve_price =
+                calculate_analytic_reserve_price(reserve, cap_level, params.k, config);
+
+            let (twap, reserve_price) = join!(twap, reserve_price);
+            Ok(PricingOutput {
+                twap: twap?,
+                cap_level,
+                volatility: Some(volatility),
+                reserve_price: reserve_price?,
+            })
+        })
+    }
+}
+
+/// Pricing models by the program ID requests are made for.
+pub struct PricingModelRegistry {
+    models: HashMap<Felt, Arc<dyn PricingModel>>,
+}
+
+static REGISTRY: LazyLock<PricingModelRegistry> = LazyLock::new(PricingModelRegistry::default);
+
+impl PricingModelRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self {
+            models: HashMap::new(),
+        }
+    }
+
+    /// The registry requests are priced with.
+    pub fn global() -> &'static Self {
+        &REGISTRY
+    }
+
+    /// Prices `program_id` with `model`, replacing any model it had.
+    pub fn register(&mut self, program_id: Felt, model: impl PricingModel + 'static) {
+        self.models.insert(program_id, Arc::new(model));
+    }
+
+    pub fn get(&self, program_id: Felt) -> Option<Arc<dyn PricingModel>> {
+        self.models.get(&program_id).cloned()
+    }
+
+    /// Like [`Self::get`], failing for program IDs without a model.
+    pub fn model(&self, program_id: Felt) -> Result<Arc<dyn PricingModel>> {
+        self.get(program_id)
+            .ok_or_else(|| eyre!("No pricing model for program ID {:#x}", program_id))
+    }
+
+    pub fn contains(&self, program_id: Felt) -> bool {
+        self.models.contains_key(&program_id)
+    }
+}
+
+impl Default for PricingModelRegistry {
+    /// Every built-in model under its program ID.
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(pitch_lake_v1(), MrjPricingModel);
+        registry.register(pitch_lake_v1_analytic(), AnalyticPricingModel);
+        registry
+    }
+}
+
+/// `PITCH_LAKE_V1_ANALYTIC` as a short string.
+pub const PITCH_LAKE_V1_ANALYTIC: &str = "0x50495443485f4c414b455f56315f414e414c59544943";
+
+/// Program ID of requests that don't name one.
+pub fn pitch_lake_v1() -> Felt {
+    Felt::from_hex(PITCH_LAKE_V1).expect("PITCH_LAKE_V1 is a valid felt")
+}
+
+/// Program ID priced by [`AnalyticPricingModel`].
+pub fn pitch_lake_v1_analytic() -> Felt {
+    Felt::from_hex(PITCH_LAKE_V1_ANALYTIC).expect("PITCH_LAKE_V1_ANALYTIC is a valid felt")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ConstantModel(f64);
+
+    impl PricingModel for ConstantModel {
+        fn price<'a>(
+            &'a self,
+            _headers: PricingHeaders,
+            _params: &'a PitchLakeJobRequestParams,
+            _seed: u64,
+            _config: &'a PricingConfig,
+            _cancel: &'a CancellationToken,
+        ) -> PricingFuture<'a> {
+            Box::pin(async move { Err(eyre!("constant model {} has no reserve price", self.0)) })
+        }
+    }
+
+    #[test]
+    fn test_default_registry_prices_pitch_lake_v1() {
+        let registry = PricingModelRegistry::default();
+
+        assert!(registry.contains(pitch_lake_v1()));
+        assert!(registry.contains(pitch_lake_v1_analytic()));
+        assert!(registry.get(Felt::from(2u8)).is_none());
+        assert_eq!(
+            registry.model(Felt::from(2u8)).err().unwrap().to_string(),
+            "No pricing model for program ID 0x2"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registered_model_is_used_for_its_program_id() {
+        let mut registry = PricingModelRegistry::default();
+        registry.register(Felt::from(2u8), ConstantModel(1.5));
+
+        let error = registry
+            .model(Felt::from(2u8))
+            .unwrap()
+            .price(
+                (vec![], vec![], vec![]),
+                &PitchLakeJobRequestParams::default(),
+                0,
+                &PricingConfig::default(),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "constant model 1.5 has no reserve price");
+        assert!(registry.contains(pitch_lake_v1()));
+    }
+}
@@ -0,0 +1,302 @@
+use db_access::models::BlockHeader;
+use eyre::Result;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::config::{BaseFeeInput, OutlierFilter, PricingConfig};
+use super::model::PricingHeaders;
+use super::utils::hex_string_to_f64;
+
+/// Turns a median absolute deviation into a standard deviation estimate for
+/// normally distributed values.
+const MAD_SCALE: f64 = 1.4826;
+
+/// Base fee outliers found in the headers of each range of a request.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OutlierReport {
+    /// The filter the outliers were found with.
+    pub filter: OutlierFilter,
+    /// Whether the pricing values were computed with them replaced.
+    pub replaced: bool,
+    pub twap: RangeOutliers,
+    pub cap_level: RangeOutliers,
+    pub reserve_price: RangeOutliers,
+}
+
+impl OutlierReport {
+    pub fn new((twap, cap_level, reserve_price): &PricingHeaders, config: &PricingConfig) -> Self {
+        Self {
+            filter: config.outlier_filter,
+            replaced: config.base_fee_input == BaseFeeInput::Filtered,
+            twap: RangeOutliers::new(twap, config),
+            cap_level: RangeOutliers::new(cap_level, config),
+            reserve_price: RangeOutliers::new(reserve_price, config),
+        }
+    }
+}
+
+/// Base fee outliers of one range.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RangeOutliers {
+    /// Headers with a readable base fee.
+    pub points: usize,
+    /// Base fees the filter changes.
+    pub affected: usize,
+}
+
+impl RangeOutliers {
+    pub fn new(headers: &[BlockHeader], config: &PricingConfig) -> Self {
+        let base_fees: Vec<f64> = headers
+            .iter()
+            .filter_map(|header| header.base_fee_per_gas.as_ref())
+            .filter_map(|base_fee| hex_string_to_f64(base_fee).ok())
+            .collect();
+        let (_, affected) = filter_base_fees(&base_fees, config);
+
+        Self {
+            points: base_fees.len(),
+            affected,
+        }
+    }
+}
+
+/// Runs `config.outlier_filter` over base fees in block order.
+///
+/// Returns the filtered base fees and how many of them changed.
+pub fn filter_base_fees(base_fees: &[f64], config: &PricingConfig) -> (Vec<f64>, usize) {
+    let filtered = match config.outlier_filter {
+        OutlierFilter::Mad => mad_clip(base_fees, config.outlier_threshold),
+        OutlierFilter::Hampel => hampel(base_fees, config.outlier_window, config.outlier_threshold),
+        OutlierFilter::Winsorize => winsorize(base_fees, config.winsorize_quantile),
+    };
+    let affected = base_fees
+        .iter()
+        .zip(&filtered)
+        .filter(|(raw, filtered)| raw != filtered)
+        .count();
+
+    (filtered, affected)
+}
+
+/// Replaces the base_fee column of a DataFrame from
+/// [`super::utils::prepare_data_frame`] with its filtered values when
+/// `config.base_fee_input` is `filtered`, and leaves it as is otherwise.
+pub fn select_base_fees(mut df: DataFrame, config: &PricingConfig) -> Result<DataFrame> {
+    if config.base_fee_input == BaseFeeInput::Raw {
+        return Ok(df);
+    }
+
+    let base_fees: Vec<f64> = df.column("base_fee")?.f64()?.into_no_null_iter().collect();
+    let (filtered, affected) = filter_base_fees(&base_fees, config);
+    tracing::info!(
+        "Replaced {} of {} base fees with the {:?} filter",
+        affected,
+        base_fees.len(),
+        config.outlier_filter
+    );
+
+    df.with_column(Series::new("base_fee".into(), filtered))?;
+    Ok(df)
+}
+
+// Clips to `threshold` scaled MADs around the median
+fn mad_clip(values: &[f64], threshold: f64) -> Vec<f64> {
+    let Some((median, scale)) = median_and_scale(values.to_vec()) else {
+        return values.to_vec();
+    };
+    let (low, high) = (median - threshold * scale, median + threshold * scale);
+
+    values.iter().map(|value| value.clamp(low, high)).collect()
+}
+
+// Replaces each value more than `threshold` scaled MADs away from the median
+// of the `window` values on each side of it by that median
+fn hampel(values: &[f64], window: usize, threshold: f64) -> Vec<f64> {
+    (0..values.len())
+        .map(|i| {
+            let neighbours = &values[i.saturating_sub(window)..(i + window + 1).min(values.len())];
+            match median_and_scale(neighbours.to_vec()) {
+                Some((median, scale)) if (values[i] - median).abs() > threshold * scale => median,
+                _ => values[i],
+            }
+        })
+        .collect()
+}
+
+// Clips to the `quantile` and 1 - `quantile` quantiles
+fn winsorize(values: &[f64], quantile: f64) -> Vec<f64> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let last = (sorted.len() - 1) as f64;
+    let low = sorted[(quantile * last).floor() as usize];
+    let high = sorted[((1.0 - quantile) * last).ceil() as usize];
+
+    values.iter().map(|value| value.clamp(low, high)).collect()
+}
+
+// Median and MAD scaled to a standard deviation, None when empty
+fn median_and_scale(mut values: Vec<f64>) -> Option<(f64, f64)> {
+    let median = median_of(&mut values)?;
+    let mut deviations: Vec<f64> = values.iter().map(|value| (value - median).abs()).collect();
+    let mad = median_of(&mut deviations)?;
+
+    Some((median, MAD_SCALE * mad))
+}
+
+fn median_of(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+
+    Some(if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A slowly rising base fee around 20 gwei with a few mint-like spikes
+    fn spiky_series() -> (Vec<f64>, Vec<usize>) {
+        let spikes = vec![40, 41, 120, 170];
+        let base_fees = (0..200)
+            .map(|i| {
+                let base_fee = 20e9 + 1e7 * i as f64 + 2e8 * ((i * 7 % 11) as f64 - 5.0);
+                if spikes.contains(&i) {
+                    base_fee * 15.0
+                } else {
+                    base_fee
+                }
+            })
+            .collect();
+        (base_fees, spikes)
+    }
+
+    fn config(outlier_filter: OutlierFilter) -> PricingConfig {
+        PricingConfig {
+            outlier_filter,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hampel_replaces_only_spikes() {
+        let (base_fees, spikes) = spiky_series();
+
+        let (filtered, affected) = filter_base_fees(&base_fees, &config(OutlierFilter::Hampel));
+
+        assert_eq!(affected, spikes.len());
+        for (i, (raw, filtered)) in base_fees.iter().zip(&filtered).enumerate() {
+            if spikes.contains(&i) {
+                assert!(*filtered < 25e9, "spike {} kept at {}", i, filtered);
+            } else {
+                assert_eq!(raw, filtered);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mad_clips_spikes_to_the_band() {
+        let (base_fees, spikes) = spiky_series();
+
+        let (filtered, affected) = filter_base_fees(&base_fees, &config(OutlierFilter::Mad));
+
+        assert_eq!(affected, spikes.len());
+        let (median, scale) = median_and_scale(base_fees.clone()).unwrap();
+        for i in spikes {
+            assert!((filtered[i] - (median + 3.0 * scale)).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_winsorize_clips_both_tails() {
+        let base_fees: Vec<f64> = (0..100).map(f64::from).collect();
+        let config = PricingConfig {
+            winsorize_quantile: 0.05,
+            ..config(OutlierFilter::Winsorize)
+        };
+
+        let (filtered, affected) = filter_base_fees(&base_fees, &config);
+
+        assert_eq!(affected, 8);
+        assert_eq!(filtered[0], 4.0);
+        assert_eq!(filtered[99], 95.0);
+        assert_eq!(filtered[50], 50.0);
+    }
+
+    #[test]
+    fn test_select_base_fees_keeps_raw_input_by_default() {
+        let (base_fees, _) = spiky_series();
+        let df = DataFrame::new(vec![Series::new("base_fee".into(), base_fees.clone())]).unwrap();
+
+        let raw = select_base_fees(df.clone(), &PricingConfig::default()).unwrap();
+        let filtered = select_base_fees(
+            df,
+            &PricingConfig {
+                base_fee_input: BaseFeeInput::Filtered,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let column = |df: &DataFrame| -> Vec<f64> {
+            df.column("base_fee")
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect()
+        };
+        assert_eq!(column(&raw), base_fees);
+        assert_eq!(
+            column(&filtered),
+            filter_base_fees(&base_fees, &config(OutlierFilter::Hampel)).0
+        );
+        assert_ne!(column(&filtered), base_fees);
+    }
+
+    #[test]
+    fn test_report_counts_outliers_per_range() {
+        let (base_fees, spikes) = spiky_series();
+        let headers: Vec<BlockHeader> = base_fees
+            .iter()
+            .enumerate()
+            .map(|(i, base_fee)| BlockHeader {
+                block_hash: None,
+                number: i as i64,
+                gas_limit: None,
+                gas_used: None,
+                base_fee_per_gas: Some(format!("{:#x}", *base_fee as u128)),
+                nonce: None,
+                transaction_root: None,
+                receipts_root: None,
+                state_root: None,
+                timestamp: Some((12 * i).to_string()),
+            })
+            .collect();
+
+        let report = OutlierReport::new(
+            &(headers[..100].to_vec(), headers.clone(), Vec::new()),
+            &PricingConfig::default(),
+        );
+
+        assert!(!report.replaced);
+        assert_eq!(
+            report.twap,
+            RangeOutliers {
+                points: 100,
+                affected: 2
+            }
+        );
+        assert_eq!(report.cap_level.affected, spikes.len());
+        assert_eq!(report.reserve_price.points, 0);
+    }
+}
@@ -1,9 +1,16 @@
 use db_access::models::BlockHeader;
 use ndarray_linalg::LeastSquaresSvd;
 
+use super::analytic::{average_log_moments, AnalyticCrossCheck};
+use super::calibration::{calibrate_mrj, MrjCalibration};
+use super::config::PricingConfig;
+use super::model::PricingCancelled;
+use super::outliers::select_base_fees;
+use super::rng::{pricing_rng, PricingRng};
+use super::simulation::{PathModel, SimulatedPaths};
 use super::utils::{
-    add_twaps, drop_nulls, group_by_1h_or_1m_intervals, prepare_data_frame,
-    replace_timestamp_with_date,
+    add_twaps, bucket_secs, drop_nulls, prepare_data_frame, replace_timestamp_with_date, resample,
+    twap_window_buckets,
 };
 use chrono::prelude::*;
 use eyre::{anyhow as err, Result};
@@ -12,32 +19,99 @@ use linfa::traits::Fit;
 use linfa_linear::{FittedLinearRegression, LinearRegression};
 use ndarray::prelude::*;
 use ndarray::{stack, Array1, Array2, Axis};
-use ndarray_rand::rand_distr::Normal;
-use optimization::{Func, GradientDescent, Minimizer, NumericalDifferentiation};
 use polars::prelude::*;
-use rand::prelude::*;
-use rand_distr::Distribution;
-use statrs::distribution::Binomial;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ContinuousCDF, Normal};
+use statrs::function::erf::erfc;
 use std::f64::consts::PI;
+use tokio_util::sync::CancellationToken;
 
+/// z-score of a two-sided 95% confidence interval.
+const Z_95: f64 = 1.959_963_984_540_054;
+
+/// Reserve price and how much to trust it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReservePriceEstimate {
+    pub reserve_price: f64,
+    pub diagnostics: ReservePriceDiagnostics,
+    /// Set when `config.sensitivities` is.
+    pub sensitivities: Option<ReservePriceSensitivities>,
+    /// The reserve price against the closed-form one, for models that
+    /// compute it.
+    pub analytic: Option<AnalyticCrossCheck>,
+}
+
+/// Spread of the Monte Carlo estimate. Payoff values are discounted like the
+/// reserve price, so they are directly comparable to it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReservePriceDiagnostics {
+    pub num_paths: usize,
+    /// Standard error of the reserve price.
+    pub std_error: f64,
+    /// Normal-approximation 95% confidence interval of the reserve price.
+    pub confidence_interval_95: (f64, f64),
+    pub payoff_quantiles: PayoffQuantiles,
+    /// Share of paths whose TWAP reached the capped price.
+    pub cap_hit_fraction: f64,
+    /// Share of paths that finished at or below the strike.
+    pub zero_payoff_fraction: f64,
+    /// β of the control variate correction, when one was used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_variate_coefficient: Option<f64>,
+    /// Fitted MRJ parameters the paths were simulated with. Missing from
+    /// results stored before they were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calibration: Option<MrjCalibration>,
+}
+
+/// Central finite difference derivatives of the reserve price. Every bumped
+/// price is simulated on the same random numbers as the reserve price, so
+/// the Monte Carlo noise mostly cancels out of the differences.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReservePriceSensitivities {
+    /// Per basis point of k, which moves both the strike and the capped
+    /// price.
+    pub k: f64,
+    /// Per unit of cap level.
+    pub cap_level: f64,
+    /// Per unit of σ, the volatility of the stochastic trend.
+    pub volatility: f64,
+    /// Per unit of the risk-free rate.
+    pub risk_free_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PayoffQuantiles {
+    pub p05: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p95: f64,
+}
+
+/// Prices the round by Monte Carlo. Every random draw comes from a generator
+/// seeded with `seed`, so the same headers, seed and config always give the
+/// same value. Stops with [`PricingCancelled`] before the next batch of paths
+/// once `cancel` fires.
 pub async fn calculate_reserve_price(
     block_headers: Vec<BlockHeader>,
     cap_level: f64,
     k: i128,
-) -> Result<f64> {
-    // Prepare DataFrame
-    let mut df = prepare_data_frame(block_headers)?;
-
-    df = replace_timestamp_with_date(df)?;
-    df = group_by_1h_or_1m_intervals(df)?;
-
-    let twap_window = 720;
-    df = add_twaps(df, twap_window)?;
-    df = drop_nulls(&df, "TWAP_30d")?;
+    seed: u64,
+    config: &PricingConfig,
+    cancel: &CancellationToken,
+) -> Result<ReservePriceEstimate> {
+    let mut rng = pricing_rng(seed);
 
-    let num_paths = 15000;
-    let n_periods = twap_window;
-    let risk_free_rate = 0.05;
+    let ReservePriceSeries {
+        mut df,
+        inputs,
+        bucket_hours,
+    } = reserve_price_series(block_headers, cap_level, k, config)?;
+    let n_periods = inputs.n_periods;
+    let mu = config.mu;
+    let dt = inputs.dt;
 
     let period_end_date_timestamp = df
         .column("date")?
@@ -68,21 +142,110 @@ pub async fn calculate_reserve_price(
         de_seasonalised_detrended_log_base_fee.to_vec(),
     ))?;
 
-    let (de_seasonalized_detrended_simulated_prices, _params) = simulate_prices(
-        de_seasonalised_detrended_log_base_fee.view(),
-        n_periods,
-        num_paths,
-    )?;
+    let calibration = calibrate_mrj(de_seasonalised_detrended_log_base_fee.view(), config)?;
+    let mrj_start =
+        de_seasonalised_detrended_log_base_fee[de_seasonalised_detrended_log_base_fee.len() - 1];
 
-    let total_hours = (period_end_date_timestamp - period_start_date_timestamp) / 3600 / 1000;
+    let total_hours =
+        (period_end_date_timestamp - period_start_date_timestamp) as f64 / 3_600_000.0;
     let sim_hourly_times: Array1<f64> =
-        Array1::range(0.0, n_periods as f64, 1.0).mapv(|i| total_hours as f64 + i);
+        Array1::range(0.0, n_periods as f64, 1.0).mapv(|i| i.mul_add(bucket_hours, total_hours));
 
     let c = season_matrix(sim_hourly_times);
     let season = c.dot(&season_param);
-    let season_reshaped = season.into_shape((n_periods, 1))?;
 
-    let detrended_simulated_prices = &de_seasonalized_detrended_simulated_prices + &season_reshaped;
+    let coeffs = trend_model.params();
+    let final_trend_value = {
+        let x = (df.height() - 1) as f64;
+        coeffs[0].mul_add(x, coeffs[1])
+    };
+
+    let path_model = |sigma: f64| PathModel {
+        mrj_params: calibration.params.to_vec(),
+        mrj_start,
+        season: season.clone(),
+        trend: final_trend_value,
+        trend_drift: 0.5f64.mul_add(-sigma.powi(2), mu),
+        dt,
+        shock_std: sigma * (f64::sqrt(dt)),
+    };
+
+    let (mut estimate, simulation) = simulate_reserve_price(
+        &path_model(inputs.sigma),
+        &inputs.option(),
+        config,
+        &mut rng,
+        cancel,
+    )?;
+    // A diverged price fails before any bumped price is simulated
+    let analytic = AnalyticCrossCheck::new(
+        estimate.reserve_price,
+        inputs.analytic_price(config),
+        config,
+    );
+    analytic.enforce(config)?;
+    if config.sensitivities {
+        estimate.sensitivities = Some(sensitivities(&inputs, path_model, &simulation, config)?);
+    }
+    estimate.analytic = Some(analytic);
+    estimate.diagnostics.calibration = Some(calibration);
+    Ok(estimate)
+}
+
+/// Prices the round in closed form, from the same TWAP series as
+/// [`calculate_reserve_price`] but without fitting or simulating anything:
+/// the Turnbull–Wakeman/Levy price of the capped call on the TWAP of a GBM
+/// with the drift and volatility of the stochastic trend.
+///
+/// The diagnostics describe the exact payoff distribution of that lognormal
+/// TWAP, so `num_paths` and `std_error` are 0.
+pub async fn calculate_analytic_reserve_price(
+    block_headers: Vec<BlockHeader>,
+    cap_level: f64,
+    k: i128,
+    config: &PricingConfig,
+) -> Result<ReservePriceEstimate> {
+    let ReservePriceSeries { inputs, .. } =
+        reserve_price_series(block_headers, cap_level, k, config)?;
+
+    let (log_mean, log_variance) = inputs.twap_log_moments(config);
+    let mut estimate = inputs.option().lognormal_estimate(log_mean, log_variance);
+    if config.sensitivities {
+        estimate.sensitivities = Some(central_differences(&inputs, config, |bumped| {
+            Ok(bumped.analytic_price(config))
+        })?);
+    }
+    Ok(estimate)
+}
+
+/// Resampled base fees of the reserve price range with their TWAP, and the
+/// inputs both reserve prices are computed from.
+struct ReservePriceSeries {
+    /// From the first row with a full TWAP window.
+    df: DataFrame,
+    inputs: PricingInputs,
+    /// Hours per row of `df`.
+    bucket_hours: f64,
+}
+
+fn reserve_price_series(
+    block_headers: Vec<BlockHeader>,
+    cap_level: f64,
+    k: i128,
+    config: &PricingConfig,
+) -> Result<ReservePriceSeries> {
+    // Prepare DataFrame
+    let mut df = select_base_fees(prepare_data_frame(block_headers)?, config)?;
+
+    df = replace_timestamp_with_date(df)?;
+    let bucket_secs = bucket_secs(&df, config)?;
+    df = resample(df, config)?;
+
+    // Rows are buckets while the window is set in hours when they are configured
+    let twap_window = twap_window_buckets(config, bucket_secs)?;
+    let bucket_hours = bucket_secs as f64 / 3600.0;
+    df = add_twaps(df, twap_window)?;
+    df = drop_nulls(&df, "TWAP_30d")?;
 
     let log_twap_30d: Vec<f64> = df
         .column("TWAP_30d")?
@@ -98,61 +261,463 @@ pub async fn calculate_reserve_price(
         .collect();
     let returns: Vec<f64> = returns.into_iter().filter(|&x| !x.is_nan()).collect();
 
-    let mu = 0.05 / 12.0;
     let sigma = standard_deviation(returns) * f64::sqrt(twap_window as f64);
-    let dt = 1.0 / 24.0;
-
-    let mut stochastic_trend = Array2::<f64>::zeros((n_periods, num_paths));
-    let normal = Normal::new(0.0, sigma * (f64::sqrt(dt)))?;
-    let mut rng = thread_rng();
-    for i in 0..num_paths {
-        let random_shocks: Vec<f64> = (0..n_periods).map(|_| normal.sample(&mut rng)).collect();
-        let mut cumsum = 0.0;
-        for j in 0..n_periods {
-            cumsum += 0.5f64
-                .mul_add(-sigma.powi(2), mu)
-                .mul_add(dt, random_shocks[j]);
-            stochastic_trend[[j, i]] = cumsum;
+
+    let twap_series = df.column("TWAP_30d")?;
+    let last_twap = twap_series.f64()?.last().ok_or_else(|| {
+        tracing::error!("TWAP series is empty.");
+        err!("TWAP series is empty")
+    })?;
+    let strike = ((k as f64 / 10_000.0) + 1.0) * last_twap;
+
+    let inputs = PricingInputs {
+        spot: last_twap,
+        strike,
+        cap_level,
+        sigma,
+        risk_free_rate: config.risk_free_rate,
+        // One simulation step per bucket
+        dt: config.dt * bucket_hours,
+        n_periods: twap_window,
+    };
+    Ok(ReservePriceSeries {
+        df,
+        inputs,
+        bucket_hours,
+    })
+}
+
+/// What the reserve price is computed from besides the fitted paths.
+#[derive(Debug, Clone, Copy)]
+struct PricingInputs {
+    /// Current TWAP.
+    spot: f64,
+    strike: f64,
+    cap_level: f64,
+    /// Volatility of the stochastic trend.
+    sigma: f64,
+    risk_free_rate: f64,
+    /// Step of the stochastic trend, in days.
+    dt: f64,
+    /// Steps in the TWAP.
+    n_periods: usize,
+}
+
+impl PricingInputs {
+    fn option(&self) -> CappedCall {
+        CappedCall {
+            spot: self.spot,
+            strike: self.strike,
+            capped_price: (1.0 + self.cap_level) * self.strike,
+            discount: f64::exp(-self.risk_free_rate),
         }
     }
 
-    let coeffs = trend_model.params();
-    let final_trend_value = {
-        let x = (df.height() - 1) as f64;
-        coeffs[0].mul_add(x, coeffs[1])
-    };
+    /// Closed-form price of the capped call on the TWAP of a GBM started at
+    /// the spot, with the drift and volatility of the stochastic trend. The
+    /// TWAP is approximated by the lognormal with its first two moments.
+    fn analytic_price(&self, config: &PricingConfig) -> f64 {
+        let (log_mean, log_variance) = self.twap_log_moments(config);
+        self.option().lognormal_price(log_mean, log_variance)
+    }
+
+    // Log mean and log variance of the lognormal approximating that TWAP
+    fn twap_log_moments(&self, config: &PricingConfig) -> (f64, f64) {
+        let step_drift = 0.5f64.mul_add(-self.sigma.powi(2), config.mu) * self.dt;
+        let step_variance = self.sigma.powi(2) * self.dt;
+        let (log_mean, log_variance) =
+            average_log_moments(step_drift, step_variance, self.n_periods);
+        (self.spot.ln() + log_mean, log_variance)
+    }
+}
+
+/// Capped call whose discounted expected payoff is the reserve price.
+struct CappedCall {
+    /// Current TWAP.
+    spot: f64,
+    strike: f64,
+    capped_price: f64,
+    discount: f64,
+}
 
-    let mut simulated_log_prices = Array2::<f64>::zeros((n_periods, num_paths));
-    for i in 0..n_periods {
-        let trend = final_trend_value;
-        for j in 0..num_paths {
-            simulated_log_prices[[i, j]] =
-                detrended_simulated_prices[[i, j]] + trend + stochastic_trend[[i, j]];
+impl CappedCall {
+    fn payoff(&self, twap: f64) -> f64 {
+        self.discount * (twap.min(self.capped_price) - self.strike).max(0.0)
+    }
+
+    /// Exact discounted expected payoff on the geometric TWAP of the
+    /// stochastic trend alone, started at the spot.
+    fn control_price(&self, model: &PathModel) -> f64 {
+        let (log_mean, log_variance) = model.control_log_moments();
+        self.lognormal_price(self.spot.ln() + log_mean, log_variance)
+    }
+
+    /// The price on a TWAP with `ln TWAP ~ N(log_mean, log_variance)`, with
+    /// the exact statistics of the payoff instead of simulated ones.
+    fn lognormal_estimate(&self, log_mean: f64, log_variance: f64) -> ReservePriceEstimate {
+        let reserve_price = self.lognormal_price(log_mean, log_variance);
+        let std_dev = log_variance.sqrt();
+        // Share of TWAPs at or below `price`
+        let share_below = |price: f64| {
+            if std_dev > 0.0 {
+                standard_normal_cdf((price.ln() - log_mean) / std_dev)
+            } else if log_mean.exp() <= price {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let quantile =
+            |q: f64| self.payoff(std_dev.mul_add(standard_normal_quantile(q), log_mean).exp());
+
+        ReservePriceEstimate {
+            reserve_price,
+            diagnostics: ReservePriceDiagnostics {
+                num_paths: 0,
+                std_error: 0.0,
+                confidence_interval_95: (reserve_price, reserve_price),
+                payoff_quantiles: PayoffQuantiles {
+                    p05: quantile(0.05),
+                    p25: quantile(0.25),
+                    p50: quantile(0.50),
+                    p75: quantile(0.75),
+                    p95: quantile(0.95),
+                },
+                cap_hit_fraction: 1.0 - share_below(self.capped_price),
+                zero_payoff_fraction: share_below(self.strike),
+                control_variate_coefficient: None,
+                calibration: None,
+            },
+            sensitivities: None,
+            analytic: None,
         }
     }
 
-    let simulated_prices = simulated_log_prices.mapv(f64::exp);
-    let twap_start = n_periods.saturating_sub(twap_window);
-    let final_prices_twap = simulated_prices
-        .slice(s![twap_start.., ..])
-        .mean_axis(Axis(0))
-        .ok_or_else(|| eyre::eyre!("Failed to calculate mean axis"))?;
+    // A capped call is a call spread between the strike and the capped price
+    fn lognormal_price(&self, log_mean: f64, log_variance: f64) -> f64 {
+        if log_variance <= 0.0 {
+            return self.payoff(log_mean.exp());
+        }
+        if self.capped_price <= self.strike {
+            return 0.0;
+        }
+        self.discount
+            * (lognormal_call(log_mean, log_variance, self.strike)
+                - lognormal_call(log_mean, log_variance, self.capped_price))
+    }
+}
 
-    let twap_series = df.column("TWAP_30d")?;
-    let last_twap = twap_series.f64()?.last().ok_or_else(|| {
-        tracing::error!("TWAP series is empty.");
-        err!("TWAP series is empty")
-    })?;
-    let strike = ((k as f64 / 10_000.0) + 1.0) * last_twap;
-    let payoffs = final_prices_twap.mapv(|price| {
-        let capped_price = (1.0 + cap_level) * strike;
-        (price.min(capped_price) - strike).max(0.0)
+// Undiscounted E[(X - strike)+] for ln X ~ N(log_mean, log_variance)
+fn lognormal_call(log_mean: f64, log_variance: f64, strike: f64) -> f64 {
+    let std_dev = log_variance.sqrt();
+    let d1 = (log_mean - strike.ln() + log_variance) / std_dev;
+    let d2 = d1 - std_dev;
+    (log_mean + log_variance / 2.0).exp() * standard_normal_cdf(d1)
+        - strike * standard_normal_cdf(d2)
+}
+
+fn standard_normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+fn standard_normal_quantile(p: f64) -> f64 {
+    Normal::new(0.0, 1.0)
+        .expect("The standard normal is valid")
+        .inverse_cdf(p)
+}
+
+/// Paths an estimate was computed from, with the seed of every batch.
+#[derive(Debug, Clone, Default)]
+struct Simulation {
+    paths: SimulatedPaths,
+    batch_size: usize,
+    batch_seeds: Vec<u64>,
+    /// Stops simulating further batches once it fires.
+    cancel: CancellationToken,
+}
+
+impl Simulation {
+    /// The same number of paths from `model`, drawn from the same seeds.
+    fn resimulate(&self, model: &PathModel, antithetic: bool) -> Result<SimulatedPaths> {
+        let mut paths = SimulatedPaths::default();
+        for &seed in &self.batch_seeds {
+            self.check_cancelled()?;
+            paths.append(model.simulate(self.batch_size, antithetic, seed)?);
+        }
+        Ok(paths)
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(PricingCancelled.into());
+        }
+        Ok(())
+    }
+}
+
+/// Simulates batches of `config.num_paths` paths until the estimate is
+/// precise enough for `config.target_relative_error`, or the next batch
+/// would exceed `config.max_num_paths`. Without a target a single batch is
+/// simulated. Every batch is seeded with the next draw of `rng`.
+fn simulate_reserve_price(
+    model: &PathModel,
+    option: &CappedCall,
+    config: &PricingConfig,
+    rng: &mut PricingRng,
+    cancel: &CancellationToken,
+) -> Result<(ReservePriceEstimate, Simulation)> {
+    // Antithetic pairs never straddle two batches
+    let batch_size = if config.antithetic {
+        config.num_paths.div_ceil(2) * 2
+    } else {
+        config.num_paths
+    };
+
+    let mut simulation = Simulation {
+        batch_size,
+        cancel: cancel.clone(),
+        ..Default::default()
+    };
+    loop {
+        simulation.check_cancelled()?;
+        let seed = rng.next_u64();
+        simulation
+            .paths
+            .append(model.simulate(batch_size, config.antithetic, seed)?);
+        simulation.batch_seeds.push(seed);
+
+        let estimate = price_paths(&simulation.paths, model, option, config);
+        let precise_enough = config.target_relative_error.is_none_or(|target| {
+            estimate.diagnostics.std_error <= target * estimate.reserve_price.abs()
+        });
+        if precise_enough || simulation.paths.len() + batch_size > config.max_num_paths {
+            tracing::debug!(
+                "Simulated {} paths, std error {}",
+                simulation.paths.len(),
+                estimate.diagnostics.std_error
+            );
+            return Ok((estimate, simulation));
+        }
+    }
+}
+
+/// Estimate of `option` on paths simulated from `model`, corrected by the
+/// control variate when `config.control_variate` is set.
+fn price_paths(
+    paths: &SimulatedPaths,
+    model: &PathModel,
+    option: &CappedCall,
+    config: &PricingConfig,
+) -> ReservePriceEstimate {
+    let control = config.control_variate.then(|| {
+        let controls: Vec<f64> = paths
+            .trend_means
+            .iter()
+            .map(|mean| option.payoff(option.spot * mean.exp()))
+            .collect();
+        (controls, option.control_price(model))
     });
 
-    let average_payoff = payoffs.mean().unwrap_or(0.0);
-    let reserve_price = f64::exp(-risk_free_rate) * average_payoff;
+    estimate_reserve_price(
+        &paths.twaps,
+        control
+            .as_ref()
+            .map(|(controls, price)| (controls.as_slice(), *price)),
+        config.antithetic,
+        option,
+    )
+}
 
-    Ok(reserve_price)
+/// Central differences of the reserve price in each input, every input
+/// moved by `config.sensitivity_bump` times its value. The strike, cap level
+/// and rate only change the payoff, so they are priced on the paths of
+/// `simulation` again. σ changes the paths, which are drawn again from the
+/// same batch seeds.
+fn sensitivities(
+    inputs: &PricingInputs,
+    path_model: impl Fn(f64) -> PathModel,
+    simulation: &Simulation,
+    config: &PricingConfig,
+) -> Result<ReservePriceSensitivities> {
+    let model = path_model(inputs.sigma);
+    central_differences(inputs, config, |bumped| {
+        if bumped.sigma == inputs.sigma {
+            return Ok(
+                price_paths(&simulation.paths, &model, &bumped.option(), config).reserve_price,
+            );
+        }
+        let model = path_model(bumped.sigma);
+        let paths = simulation.resimulate(&model, config.antithetic)?;
+        Ok(price_paths(&paths, &model, &bumped.option(), config).reserve_price)
+    })
+}
+
+// Central differences of `price` in each of the inputs, one at a time
+fn central_differences(
+    inputs: &PricingInputs,
+    config: &PricingConfig,
+    price: impl Fn(&PricingInputs) -> Result<f64>,
+) -> Result<ReservePriceSensitivities> {
+    let bump = |value: f64| {
+        if value == 0.0 {
+            config.sensitivity_bump
+        } else {
+            config.sensitivity_bump * value.abs()
+        }
+    };
+    let difference = |bumped: &dyn Fn(f64) -> PricingInputs, h: f64| -> Result<f64> {
+        Ok((price(&bumped(h))? - price(&bumped(-h))?) / (2.0 * h))
+    };
+
+    let h = bump(inputs.strike);
+    let strike = difference(
+        &|h| PricingInputs {
+            strike: inputs.strike + h,
+            ..*inputs
+        },
+        h,
+    )?;
+    let h = bump(inputs.cap_level);
+    let cap_level = difference(
+        &|h| PricingInputs {
+            cap_level: inputs.cap_level + h,
+            ..*inputs
+        },
+        h,
+    )?;
+    let h = bump(inputs.risk_free_rate);
+    let risk_free_rate = difference(
+        &|h| PricingInputs {
+            risk_free_rate: inputs.risk_free_rate + h,
+            ..*inputs
+        },
+        h,
+    )?;
+    let h = bump(inputs.sigma);
+    let volatility = difference(
+        &|h| PricingInputs {
+            sigma: inputs.sigma + h,
+            ..*inputs
+        },
+        h,
+    )?;
+
+    Ok(ReservePriceSensitivities {
+        // strike = (1 + k / 10_000) × spot
+        k: strike * inputs.spot / 10_000.0,
+        cap_level,
+        volatility,
+        risk_free_rate,
+    })
+}
+
+/// Discounted mean payoff of `option` over the simulated TWAPs, with the
+/// statistics of the payoff distribution.
+///
+/// Antithetic pairs are averaged into one sample before the standard error is
+/// taken. With a control, each sample is corrected by `β(Y - E[Y])`, where `Y`
+/// is the control payoff, `E[Y]` its exact expectation and `β` the regression
+/// coefficient of the payoffs on `Y`.
+fn estimate_reserve_price(
+    final_prices_twap: &[f64],
+    control: Option<(&[f64], f64)>,
+    antithetic: bool,
+    option: &CappedCall,
+) -> ReservePriceEstimate {
+    let num_paths = final_prices_twap.len();
+    let mut payoffs: Vec<f64> = final_prices_twap
+        .iter()
+        .map(|&price| option.payoff(price))
+        .collect();
+
+    let independent_samples = |values: &[f64]| -> Vec<f64> {
+        if antithetic {
+            values
+                .chunks(2)
+                .map(|pair| pair.iter().sum::<f64>() / pair.len() as f64)
+                .collect()
+        } else {
+            values.to_vec()
+        }
+    };
+    let mut samples = independent_samples(&payoffs);
+    let mut control_variate_coefficient = None;
+    if let Some((controls, control_price)) = control {
+        let controls = independent_samples(controls);
+        let beta = regression_coefficient(&samples, &controls);
+        for (sample, control) in samples.iter_mut().zip(&controls) {
+            *sample -= beta * (control - control_price);
+        }
+        control_variate_coefficient = Some(beta);
+    }
+
+    let num_samples = samples.len().max(1) as f64;
+    let reserve_price = samples.iter().sum::<f64>() / num_samples;
+    let std_error = standard_deviation(samples) / num_samples.sqrt();
+    let fraction = |count: usize| count as f64 / num_paths.max(1) as f64;
+    let cap_hit_fraction = fraction(
+        final_prices_twap
+            .iter()
+            .filter(|&&price| price >= option.capped_price)
+            .count(),
+    );
+    let zero_payoff_fraction = fraction(payoffs.iter().filter(|&&payoff| payoff <= 0.0).count());
+
+    payoffs.sort_unstable_by(f64::total_cmp);
+    let payoff_quantiles = PayoffQuantiles {
+        p05: quantile(&payoffs, 0.05),
+        p25: quantile(&payoffs, 0.25),
+        p50: quantile(&payoffs, 0.50),
+        p75: quantile(&payoffs, 0.75),
+        p95: quantile(&payoffs, 0.95),
+    };
+
+    ReservePriceEstimate {
+        reserve_price,
+        diagnostics: ReservePriceDiagnostics {
+            num_paths,
+            std_error,
+            confidence_interval_95: (
+                Z_95.mul_add(-std_error, reserve_price),
+                Z_95.mul_add(std_error, reserve_price),
+            ),
+            payoff_quantiles,
+            cap_hit_fraction,
+            zero_payoff_fraction,
+            control_variate_coefficient,
+            calibration: None,
+        },
+        sensitivities: None,
+        analytic: None,
+    }
+}
+
+/// Least-squares slope of `y` on `x`, 0 when `x` is constant.
+fn regression_coefficient(y: &[f64], x: &[f64]) -> f64 {
+    let n = x.len().max(1) as f64;
+    let (mean_x, mean_y) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+    let (covariance, variance) = x.iter().zip(y).fold((0.0, 0.0), |(cov, var), (xi, yi)| {
+        (
+            (xi - mean_x).mul_add(yi - mean_y, cov),
+            (xi - mean_x).mul_add(xi - mean_x, var),
+        )
+    });
+    if variance > 0.0 {
+        covariance / variance
+    } else {
+        0.0
+    }
+}
+
+/// Linearly interpolated `q` quantile of sorted values, 0 when empty.
+pub(super) fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let Some(last) = sorted.len().checked_sub(1) else {
+        return 0.0;
+    };
+    let position = q * last as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    let weight = position - lower as f64;
+    (sorted[upper] - sorted[lower]).mul_add(weight, sorted[lower])
 }
 
 /// Removes seasonality from the detrended log base fee and adds relevant columns to the `DataFrame`.
@@ -208,98 +773,6 @@ fn remove_seasonality(
     Ok((de_seasonalised_detrended_log_base_fee, season_param))
 }
 
-/// Performs Monte Carlo parameter estimation and simulation for the Mean-Reverting Jump (MRJ) model.
-///
-/// This function estimates the parameters of the MRJ model using Monte Carlo methods,
-/// and then uses these parameters to simulate future prices.
-///
-/// # Arguments
-///
-/// * `de_seasonalised_detrended_log_base_fee` - An array of de-seasonalized and de-trended log base fees.
-/// * `n_periods` - The number of periods to simulate.
-/// * `num_paths` - The number of simulation paths.
-///
-/// # Returns
-///
-/// A tuple containing:
-/// * The simulated prices as a 2D array.
-/// * The estimated model parameters.
-///
-/// # Errors
-///
-/// This function will return an error if:
-/// * The parameter estimation fails.
-/// * The Binomial distribution creation fails.
-fn simulate_prices(
-    de_seasonalised_detrended_log_base_fee: ArrayView1<f64>,
-    n_periods: usize,
-    num_paths: usize,
-) -> Result<(Array2<f64>, Vec<f64>)> {
-    let dt = 1.0 / (365.0 * 24.0);
-    let pt = de_seasonalised_detrended_log_base_fee
-        .slice(s![1..])
-        .to_owned();
-    let pt_1 = de_seasonalised_detrended_log_base_fee
-        .slice(s![..-1])
-        .to_owned();
-
-    let function =
-        NumericalDifferentiation::new(Func(|x: &[f64]| neg_log_likelihood(x, &pt, &pt_1)));
-
-    let minimizer = GradientDescent::new().max_iterations(Some(2400));
-
-    let var_pt = pt.var(0.0);
-    let solution = minimizer.minimize(
-        &function,
-        vec![-3.928e-02, 2.873e-04, 4.617e-02, var_pt, var_pt, 0.2],
-    );
-
-    let params = &solution.position;
-    let alpha = params[0] / dt;
-    let kappa = (1.0 - params[1]) / dt;
-    let mu_j = params[2];
-    let sigma = (params[3] / dt).sqrt();
-    let sigma_j = params[4].sqrt();
-    let lambda_ = params[5] / dt;
-
-    let mut rng = thread_rng();
-    let j: Array2<f64> = {
-        let binom = Binomial::new(lambda_ * dt, 1)?;
-        Array2::from_shape_fn((n_periods, num_paths), |_| binom.sample(&mut rng) as f64)
-    };
-
-    let mut simulated_prices = Array2::zeros((n_periods, num_paths));
-    simulated_prices
-        .slice_mut(s![0, ..])
-        .assign(&Array1::from_elem(
-            num_paths,
-            de_seasonalised_detrended_log_base_fee
-                [de_seasonalised_detrended_log_base_fee.len() - 1],
-        ));
-
-    let normal = Normal::new(0.0, 1.0)?;
-    let n1 = Array2::from_shape_fn((n_periods, num_paths), |_| normal.sample(&mut rng));
-    let n2 = Array2::from_shape_fn((n_periods, num_paths), |_| normal.sample(&mut rng));
-
-    for i in 1..n_periods {
-        let prev_prices = simulated_prices.slice(s![i - 1, ..]);
-        let current_n1 = n1.slice(s![i, ..]);
-        let current_n2 = n2.slice(s![i, ..]);
-        let current_j = j.slice(s![i, ..]);
-
-        let new_prices = &(alpha * dt
-            + kappa.mul_add(-dt, 1.0) * &prev_prices
-            + sigma * dt.sqrt() * &current_n1
-            + &current_j * (mu_j + sigma_j * &current_n2));
-
-        simulated_prices
-            .slice_mut(s![i, ..])
-            .assign(&new_prices.clone());
-    }
-
-    Ok((simulated_prices, params.to_vec()))
-}
-
 /// Discovers the trend in the log base fee data using linear regression.
 ///
 /// # Arguments
@@ -433,63 +906,335 @@ fn standard_deviation(returns: Vec<f64>) -> f64 {
     variance.sqrt()
 }
 
-/// Calculates the probability density function (PDF) for the Mean-Reverting Jump (MRJ) model.
-///
-/// This function computes the PDF of the MRJ model given the model parameters and observed prices.
-///
-/// # Arguments
-///
-/// * `params` - A slice of f64 values representing the model parameters:
-///   [a, phi, `mu_j`, `sigma_sq`, `sigma_sq_j`, `lambda`]
-/// * `pt` - An `Array1<f64>` of observed prices at time t
-/// * `pt_1` - An `Array1<f64>` of observed prices at time t-1
-///
-/// # Returns
-///
-/// * `Array1<f64>` - The calculated PDF values
-///
-/// # Notes
-///
-/// The MRJ model combines a mean-reverting process with a jump component. The PDF is a mixture
-/// of two normal distributions, weighted by the jump probability (lambda).
-fn mrjpdf(params: &[f64], pt: &Array1<f64>, pt_1: &Array1<f64>) -> Array1<f64> {
-    let (a, phi, mu_j, sigma_sq, sigma_sq_j, lambda) = (
-        params[0], params[1], params[2], params[3], params[4], params[5],
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing_data::simulation::tests::synthetic_model;
 
-    let term1 = lambda
-        * (-((pt - a - phi * pt_1 - mu_j).mapv(|x| x.powi(2))) / (2.0 * (sigma_sq + sigma_sq_j)))
-            .mapv(f64::exp)
-        / ((2.0 * std::f64::consts::PI * (sigma_sq + sigma_sq_j)).sqrt());
+    fn option(discount: f64) -> CappedCall {
+        CappedCall {
+            spot: 100.0,
+            strike: 100.0,
+            capped_price: 150.0,
+            discount,
+        }
+    }
 
-    let term2 = (1.0 - lambda)
-        * (-((pt - a - phi * pt_1).mapv(|x| x.powi(2))) / (2.0 * sigma_sq)).mapv(f64::exp)
-        / ((2.0 * std::f64::consts::PI * sigma_sq).sqrt());
+    fn simulate(config: &PricingConfig) -> ReservePriceEstimate {
+        simulate_reserve_price(
+            &synthetic_model(240),
+            &option(0.95),
+            config,
+            &mut pricing_rng(42),
+            &CancellationToken::new(),
+        )
+        .unwrap()
+        .0
+    }
 
-    term1 + term2
-}
+    #[test]
+    fn test_variance_reduction_lowers_the_standard_error() {
+        let plain = PricingConfig {
+            num_paths: 2_000,
+            ..Default::default()
+        };
+        let antithetic = PricingConfig {
+            antithetic: true,
+            ..plain.clone()
+        };
+        let control_variate = PricingConfig {
+            control_variate: true,
+            ..plain.clone()
+        };
+        let both = PricingConfig {
+            antithetic: true,
+            control_variate: true,
+            ..plain.clone()
+        };
 
-/// Calculates the negative log-likelihood for the mean-reverting jump diffusion model.
-///
-/// This function computes the negative log-likelihood of the observed data given the model parameters.
-/// It's used in parameter estimation for the mean-reverting jump diffusion model.
-///
-/// # Arguments
-///
-/// * `params` - A slice of f64 values representing the model parameters:
-///   [`a`, `phi`, `mu_j`, `sigma_sq`, `sigma_sq_j`, `lambda`]
-/// * `pt` - An `Array1<f64>` of observed prices at time t
-/// * `pt_1` - An `Array1<f64>` of observed prices at time t-1
-///
-/// # Returns
-///
-/// * `f64` - The negative log-likelihood value
-///
-/// # Notes
-///
-/// The function adds a small constant (1e-10) to each PDF value before taking the logarithm
-/// to avoid potential issues with zero values.
-fn neg_log_likelihood(params: &[f64], pt: &Array1<f64>, pt_1: &Array1<f64>) -> f64 {
-    let pdf_vals = mrjpdf(params, pt, pt_1);
-    -pdf_vals.mapv(|x| (x + 1e-10).ln()).sum()
+        let plain = simulate(&plain);
+        for config in [antithetic, control_variate, both] {
+            let reduced = simulate(&config);
+            let (se_plain, se_reduced) =
+                (plain.diagnostics.std_error, reduced.diagnostics.std_error);
+
+            assert_eq!(reduced.diagnostics.num_paths, 2_000);
+            assert!(
+                se_reduced < se_plain,
+                "{:?}: std error {} not below {}",
+                config,
+                se_reduced,
+                se_plain
+            );
+            // Both estimate the same price
+            let tolerance = 4.0 * se_plain.hypot(se_reduced);
+            assert!((reduced.reserve_price - plain.reserve_price).abs() < tolerance);
+        }
+    }
+
+    #[test]
+    fn test_short_range_keeps_a_twap_window_it_has_rows_for() {
+        // Two days of a block a minute, with a daily cycle in the base fee
+        let headers: Vec<BlockHeader> = (0..2 * 1440)
+            .map(|i| BlockHeader {
+                block_hash: None,
+                number: i as i64,
+                gas_limit: None,
+                gas_used: None,
+                base_fee_per_gas: Some(format!(
+                    "{:#x}",
+                    (1e10 * (1.0 + 0.1 * (i as f64 * 2.0 * PI / 1440.0).sin())) as u128
+                )),
+                nonce: None,
+                transaction_root: None,
+                receipts_root: None,
+                state_root: None,
+                timestamp: Some((60 * i).to_string()),
+            })
+            .collect();
+
+        let series = reserve_price_series(headers, 0.5, 0, &PricingConfig::default()).unwrap();
+
+        // Under 7 days buckets are minutes, and the window is counted in them
+        assert_eq!(series.bucket_hours, 1.0 / 60.0);
+        assert_eq!(series.inputs.n_periods, 720);
+        assert_eq!(series.df.height(), 2 * 1440 - 719);
+    }
+
+    #[test]
+    fn test_lognormal_estimate_describes_the_payoff_distribution() {
+        let option = option(1.0);
+        let (log_mean, log_variance) = (100f64.ln(), 0.04);
+
+        let estimate = option.lognormal_estimate(log_mean, log_variance);
+
+        let diagnostics = &estimate.diagnostics;
+        assert_eq!(
+            estimate.reserve_price,
+            option.lognormal_price(log_mean, log_variance)
+        );
+        assert_eq!((diagnostics.num_paths, diagnostics.std_error), (0, 0.0));
+        // The strike is the median TWAP and the capped price ln 1.5 / 0.2
+        // standard deviations above it
+        assert!((diagnostics.zero_payoff_fraction - 0.5).abs() < 1e-12);
+        let above_cap = 1.0 - standard_normal_cdf(1.5f64.ln() / 0.2);
+        assert!((diagnostics.cap_hit_fraction - above_cap).abs() < 1e-12);
+        let quantiles = &diagnostics.payoff_quantiles;
+        assert_eq!(quantiles.p25, 0.0);
+        assert!(0.0 < quantiles.p75 && quantiles.p75 < quantiles.p95 && quantiles.p95 < 50.0);
+    }
+
+    #[test]
+    fn test_analytic_price_matches_simulated_gbm_twap() {
+        let config = PricingConfig {
+            num_paths: 20_000,
+            twap_window: 240,
+            ..Default::default()
+        };
+        let inputs = PricingInputs {
+            spot: 100.0,
+            strike: 100.0,
+            cap_level: 0.5,
+            sigma: 0.1,
+            risk_free_rate: 0.05,
+            dt: config.dt,
+            n_periods: config.twap_window,
+        };
+        // Without an MRJ component or season the paths are a GBM from the spot
+        let model = PathModel {
+            mrj_params: vec![0.0; 6],
+            mrj_start: 0.0,
+            season: Array1::zeros(config.twap_window),
+            trend: inputs.spot.ln(),
+            trend_drift: 0.5f64.mul_add(-inputs.sigma.powi(2), config.mu),
+            dt: config.dt,
+            shock_std: inputs.sigma * config.dt.sqrt(),
+        };
+
+        let (simulated, _) = simulate_reserve_price(
+            &model,
+            &inputs.option(),
+            &config,
+            &mut pricing_rng(3),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        let analytic = inputs.analytic_price(&config);
+
+        let tolerance = 4.0f64.mul_add(simulated.diagnostics.std_error, 0.01 * analytic);
+        assert!(
+            (simulated.reserve_price - analytic).abs() < tolerance,
+            "simulated {} vs analytic {}",
+            simulated.reserve_price,
+            analytic
+        );
+    }
+
+    #[test]
+    fn test_control_price_matches_simulated_control_payoffs() {
+        let model = synthetic_model(48);
+        let option = option(0.95);
+
+        let paths = model.simulate(20_000, false, 7).unwrap();
+        let controls: Vec<f64> = paths
+            .trend_means
+            .iter()
+            .map(|mean| option.payoff(option.spot * mean.exp()))
+            .collect();
+        let mean = controls.iter().sum::<f64>() / controls.len() as f64;
+        let std_error = standard_deviation(controls) / (20_000f64).sqrt();
+
+        let exact = option.control_price(&model);
+        assert!(
+            (mean - exact).abs() < 4.0 * std_error,
+            "simulated {} vs exact {}",
+            mean,
+            exact
+        );
+    }
+
+    #[test]
+    fn test_adaptive_mode_adds_paths_until_precise() {
+        let batch = PricingConfig {
+            num_paths: 500,
+            ..Default::default()
+        };
+        let single = simulate(&batch);
+        let target = single.diagnostics.std_error / single.reserve_price / 2.0;
+
+        let adaptive = simulate(&PricingConfig {
+            target_relative_error: Some(target),
+            max_num_paths: 10_000,
+            ..batch
+        });
+        let diagnostics = &adaptive.diagnostics;
+
+        assert!(diagnostics.num_paths > 500);
+        assert_eq!(diagnostics.num_paths % 500, 0);
+        assert!(
+            diagnostics.std_error <= target * adaptive.reserve_price
+                || diagnostics.num_paths + 500 > 10_000
+        );
+    }
+
+    #[test]
+    fn test_cancelled_simulation_stops() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let error = simulate_reserve_price(
+            &synthetic_model(240),
+            &option(0.95),
+            &PricingConfig::default(),
+            &mut pricing_rng(42),
+            &cancel,
+        )
+        .unwrap_err();
+
+        assert!(error.is::<PricingCancelled>());
+    }
+
+    #[test]
+    fn test_sensitivities_on_common_random_numbers() {
+        let config = PricingConfig {
+            num_paths: 2_000,
+            sensitivities: true,
+            ..Default::default()
+        };
+        let base = synthetic_model(240);
+        let dt = base.dt;
+        let path_model = |sigma: f64| PathModel {
+            trend_drift: 0.5f64.mul_add(-sigma.powi(2), 0.05 / 12.0),
+            shock_std: sigma * dt.sqrt(),
+            ..base.clone()
+        };
+        let inputs = PricingInputs {
+            spot: 100.0,
+            strike: 100.0,
+            cap_level: 0.5,
+            sigma: 0.1,
+            risk_free_rate: 0.05,
+            dt,
+            n_periods: 240,
+        };
+        let option = inputs.option();
+        let (estimate, simulation) = simulate_reserve_price(
+            &path_model(0.1),
+            &option,
+            &config,
+            &mut pricing_rng(42),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+
+        let greeks = sensitivities(&inputs, path_model, &simulation, &config).unwrap();
+
+        // The payoff is discounted by e^-r
+        assert!((greeks.risk_free_rate + estimate.reserve_price).abs() < 1e-6);
+        assert!(greeks.cap_level >= 0.0);
+        // Per path, a higher strike takes 1 off the payoff below the cap and
+        // adds the cap level to it above
+        let pathwise = simulation
+            .paths
+            .twaps
+            .iter()
+            .map(|&twap| {
+                if twap >= option.capped_price {
+                    0.5
+                } else if twap > option.strike {
+                    -1.0
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f64>()
+            * option.discount
+            / simulation.paths.len() as f64;
+        let expected_k = pathwise * inputs.spot / 10_000.0;
+        assert!(
+            (greeks.k - expected_k).abs() < 0.1 * expected_k.abs(),
+            "k {} vs pathwise {}",
+            greeks.k,
+            expected_k
+        );
+        assert!(greeks.volatility.is_finite());
+        assert_eq!(
+            greeks,
+            sensitivities(&inputs, path_model, &simulation, &config).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_estimate_reserve_price_diagnostics() {
+        // Strike 100, capped at 150: payoffs 0, 0, 10, 30, 50
+        let prices = [80.0, 100.0, 110.0, 130.0, 200.0];
+
+        let estimate = estimate_reserve_price(&prices, None, false, &option(1.0));
+        let diagnostics = &estimate.diagnostics;
+
+        assert_eq!(estimate.reserve_price, 18.0);
+        assert_eq!(diagnostics.num_paths, 5);
+        assert!((diagnostics.std_error - 470f64.sqrt() / 5f64.sqrt()).abs() < 1e-12);
+        let (lower, upper) = diagnostics.confidence_interval_95;
+        assert!(lower < estimate.reserve_price && estimate.reserve_price < upper);
+        assert!((upper - lower - 2.0 * Z_95 * diagnostics.std_error).abs() < 1e-9);
+        assert_eq!(diagnostics.payoff_quantiles.p25, 0.0);
+        assert_eq!(diagnostics.payoff_quantiles.p50, 10.0);
+        assert_eq!(diagnostics.payoff_quantiles.p75, 30.0);
+        assert!((diagnostics.payoff_quantiles.p95 - 46.0).abs() < 1e-9);
+        assert_eq!(diagnostics.cap_hit_fraction, 0.2);
+        assert_eq!(diagnostics.zero_payoff_fraction, 0.4);
+        assert_eq!(diagnostics.control_variate_coefficient, None);
+    }
+
+    #[test]
+    fn test_estimate_reserve_price_is_discounted() {
+        let prices = [120.0, 120.0];
+
+        let estimate = estimate_reserve_price(&prices, None, false, &option(0.5));
+
+        assert_eq!(estimate.reserve_price, 10.0);
+        assert_eq!(estimate.diagnostics.std_error, 0.0);
+        assert_eq!(estimate.diagnostics.payoff_quantiles.p50, 10.0);
+    }
 }
@@ -0,0 +1,70 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+/// Generator behind every Monte Carlo draw.
+///
+/// ChaCha's output stream is fixed by its specification rather than by the
+/// platform or the `rand` version, so a recorded seed reproduces a
+/// computation bit-for-bit.
+pub type PricingRng = ChaCha20Rng;
+
+pub fn pricing_rng(seed: u64) -> PricingRng {
+    PricingRng::seed_from_u64(seed)
+}
+
+/// Stream `stream` of the generator seeded with `seed`. The streams of a seed
+/// do not overlap, so chunks of a simulation can draw from them in parallel.
+pub fn pricing_rng_stream(seed: u64, stream: u64) -> PricingRng {
+    let mut rng = pricing_rng(seed);
+    rng.set_stream(stream);
+    rng
+}
+
+/// Seed used for a job that was not given an explicit one: the first 8 bytes
+/// of the SHA-256 of its job ID, big-endian.
+pub fn seed_from_job_id(job_id: &str) -> u64 {
+    let digest = Sha256::digest(job_id.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_seed_from_job_id_is_stable() {
+        // SHA-256("job") starts with 5e 8c 99 02 20 7a fa eb
+        assert_eq!(seed_from_job_id("job"), 0x5e8c_9902_207a_faeb);
+        assert_ne!(seed_from_job_id("job"), seed_from_job_id("job2"));
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_stream() {
+        let draws = |seed| {
+            let mut rng = pricing_rng(seed);
+            (0..8).map(|_| rng.gen::<u64>()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(draws(7), draws(7));
+        assert_ne!(draws(7), draws(8));
+    }
+
+    #[test]
+    fn test_streams_of_a_seed_differ() {
+        let first = |mut rng: PricingRng| rng.gen::<u64>();
+
+        assert_eq!(
+            first(pricing_rng_stream(7, 1)),
+            first(pricing_rng_stream(7, 1))
+        );
+        assert_ne!(
+            first(pricing_rng_stream(7, 0)),
+            first(pricing_rng_stream(7, 1))
+        );
+        assert_eq!(first(pricing_rng_stream(7, 0)), first(pricing_rng(7)));
+    }
+}
@@ -0,0 +1,499 @@
+use eyre::Result;
+use ndarray::prelude::*;
+use ndarray_rand::rand_distr::Normal;
+use rand_distr::{Bernoulli, Distribution, StandardNormal};
+use rayon::prelude::*;
+use statrs::distribution::Binomial;
+
+use super::rng::{pricing_rng_stream, PricingRng};
+
+/// Paths simulated from one generator stream. Even, so antithetic pairs never
+/// straddle two chunks.
+pub const CHUNK_SIZE: usize = 256;
+
+/// Step the MRJ parameters are fitted with: one hour, in years.
+const MRJ_DT: f64 = 1.0 / (365.0 * 24.0);
+
+/// Fitted model the reserve price paths are drawn from. Log prices are the
+/// MRJ component plus season and trend, plus a stochastic trend that is a
+/// Brownian motion with drift, i.e. a GBM once exponentiated.
+#[derive(Debug, Clone)]
+pub struct PathModel {
+    /// Fitted MRJ parameters: [`a`, `phi`, `mu_j`, `sigma_sq`, `sigma_sq_j`, `lambda`].
+    pub mrj_params: Vec<f64>,
+    /// Last de-seasonalised, detrended log base fee.
+    pub mrj_start: f64,
+    /// Seasonal component of every simulated period. Its length is the
+    /// number of periods simulated.
+    pub season: Array1<f64>,
+    /// Trend value the simulation continues from.
+    pub trend: f64,
+    /// Drift of the stochastic trend, μ - σ²/2.
+    pub trend_drift: f64,
+    pub dt: f64,
+    /// Standard deviation of one stochastic trend step, σ√dt.
+    pub shock_std: f64,
+}
+
+/// What the reserve price needs from every simulated path.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimulatedPaths {
+    /// Arithmetic TWAP of the base fee over all periods.
+    pub twaps: Vec<f64>,
+    /// Mean of the stochastic trend over all periods.
+    pub trend_means: Vec<f64>,
+}
+
+impl SimulatedPaths {
+    fn with_capacity(num_paths: usize) -> Self {
+        Self {
+            twaps: Vec::with_capacity(num_paths),
+            trend_means: Vec::with_capacity(num_paths),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.twaps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.twaps.is_empty()
+    }
+
+    /// Adds the paths of `other` after these.
+    pub fn append(&mut self, other: SimulatedPaths) {
+        self.twaps.extend(other.twaps);
+        self.trend_means.extend(other.trend_means);
+    }
+}
+
+impl PathModel {
+    pub fn n_periods(&self) -> usize {
+        self.season.len()
+    }
+
+    /// Simulates `num_paths` paths in chunks of [`CHUNK_SIZE`] on the rayon
+    /// pool. Chunk `i` draws from stream `i` of the generator seeded with
+    /// `seed`, so the paths do not depend on the number of threads.
+    ///
+    /// Each path is stepped through once keeping only running sums, so memory
+    /// does not grow with the number of periods. Antithetic paths come in
+    /// adjacent pairs that share their jumps and have opposite normal shocks.
+    pub fn simulate(
+        &self,
+        num_paths: usize,
+        antithetic: bool,
+        seed: u64,
+    ) -> Result<SimulatedPaths> {
+        let mrj = MrjStep::new(&self.mrj_params)?;
+        let shock = Normal::new(0.0, self.shock_std)?;
+
+        let chunks: Vec<SimulatedPaths> = (0..num_paths.div_ceil(CHUNK_SIZE))
+            .into_par_iter()
+            .map(|chunk| {
+                let paths = CHUNK_SIZE.min(num_paths - chunk * CHUNK_SIZE);
+                let mut rng = pricing_rng_stream(seed, chunk as u64);
+                self.simulate_chunk(&mrj, &shock, paths, antithetic, &mut rng)
+            })
+            .collect();
+
+        let mut simulated = SimulatedPaths::with_capacity(num_paths);
+        for chunk in chunks {
+            simulated.append(chunk);
+        }
+        Ok(simulated)
+    }
+
+    fn simulate_chunk(
+        &self,
+        mrj: &MrjStep,
+        shock: &Normal<f64>,
+        num_paths: usize,
+        antithetic: bool,
+        rng: &mut PricingRng,
+    ) -> SimulatedPaths {
+        let n_periods = self.n_periods() as f64;
+        let group_size = if antithetic { 2 } else { 1 };
+        let mut simulated = SimulatedPaths::with_capacity(num_paths);
+
+        for first in (0..num_paths).step_by(group_size) {
+            let mut paths = [
+                PathState::new(1.0, self.mrj_start),
+                PathState::new(-1.0, self.mrj_start),
+            ];
+            let paths = &mut paths[..group_size.min(num_paths - first)];
+
+            for (period, &season) in self.season.iter().enumerate() {
+                // The MRJ component starts at `mrj_start` and steps from the
+                // second period on
+                let mrj_draw = (period > 0).then(|| mrj.draw(&mut *rng));
+                let shock = shock.sample(&mut *rng);
+
+                for path in paths.iter_mut() {
+                    if let Some(draw) = &mrj_draw {
+                        path.mrj_price = mrj.step(path.mrj_price, draw, path.sign);
+                    }
+                    path.trend += self.trend_drift.mul_add(self.dt, path.sign * shock);
+                    path.price_sum += (path.mrj_price + season + self.trend + path.trend).exp();
+                    path.trend_sum += path.trend;
+                }
+            }
+
+            for path in paths.iter() {
+                simulated.twaps.push(path.price_sum / n_periods);
+                simulated.trend_means.push(path.trend_sum / n_periods);
+            }
+        }
+
+        simulated
+    }
+
+    /// The same paths built from full `(periods, paths)` matrices on the
+    /// calling thread, drawing everything from `rng`. Kept as the reference
+    /// the streamed simulator is checked and benchmarked against.
+    pub fn simulate_materialized(
+        &self,
+        num_paths: usize,
+        antithetic: bool,
+        rng: &mut PricingRng,
+    ) -> Result<SimulatedPaths> {
+        let n_periods = self.n_periods();
+        let mrj_prices = simulate_mrj_paths(
+            &self.mrj_params,
+            self.mrj_start,
+            n_periods,
+            num_paths,
+            antithetic,
+            rng,
+        )?;
+        let season = self.season.view().into_shape((n_periods, 1))?;
+        let detrended_simulated_prices = &mrj_prices + &season;
+
+        let mut stochastic_trend = Array2::<f64>::zeros((n_periods, num_paths));
+        let normal = Normal::new(0.0, self.shock_std)?;
+        let mut random_shocks: Vec<f64> = Vec::new();
+        for i in 0..num_paths {
+            if antithetic && i % 2 == 1 {
+                random_shocks.iter_mut().for_each(|shock| *shock = -*shock);
+            } else {
+                random_shocks = (0..n_periods).map(|_| normal.sample(&mut *rng)).collect();
+            }
+            let mut cumsum = 0.0;
+            for (j, shock) in random_shocks.iter().enumerate() {
+                cumsum += self.trend_drift.mul_add(self.dt, *shock);
+                stochastic_trend[[j, i]] = cumsum;
+            }
+        }
+
+        let simulated_log_prices = detrended_simulated_prices + self.trend + &stochastic_trend;
+        let final_prices_twap = simulated_log_prices
+            .mapv(f64::exp)
+            .mean_axis(Axis(0))
+            .ok_or_else(|| eyre::eyre!("Failed to calculate mean axis"))?;
+        let trend_means = stochastic_trend
+            .mean_axis(Axis(0))
+            .ok_or_else(|| eyre::eyre!("Failed to calculate mean axis"))?;
+
+        Ok(SimulatedPaths {
+            twaps: final_prices_twap.to_vec(),
+            trend_means: trend_means.to_vec(),
+        })
+    }
+
+    /// Log mean and log variance of the geometric TWAP of `exp(stochastic
+    /// trend)`, i.e. of the GBM component alone started at 1.
+    ///
+    /// The mean of `n` Brownian steps is normal, with mean `a(n+1)/2` and
+    /// variance `s²(n+1)(2n+1)/(6n)`, where `a` and `s` are the drift and
+    /// standard deviation of one step.
+    pub fn control_log_moments(&self) -> (f64, f64) {
+        let n = self.n_periods() as f64;
+        let log_mean = self.trend_drift * self.dt * (n + 1.0) / 2.0;
+        let log_variance = self.shock_std.powi(2) * (n + 1.0) * 2.0f64.mul_add(n, 1.0) / (6.0 * n);
+        (log_mean, log_variance)
+    }
+}
+
+// Running state of one streamed path
+struct PathState {
+    /// +1, or -1 for the mirrored path of an antithetic pair.
+    sign: f64,
+    mrj_price: f64,
+    trend: f64,
+    price_sum: f64,
+    trend_sum: f64,
+}
+
+impl PathState {
+    const fn new(sign: f64, mrj_start: f64) -> Self {
+        Self {
+            sign,
+            mrj_price: mrj_start,
+            trend: 0.0,
+            price_sum: 0.0,
+            trend_sum: 0.0,
+        }
+    }
+}
+
+// One hourly step of the MRJ model, with its coefficients worked out once
+struct MrjStep {
+    drift: f64,
+    decay: f64,
+    diffusion: f64,
+    mu_j: f64,
+    sigma_j: f64,
+    jump: Bernoulli,
+}
+
+struct MrjDraw {
+    jump: bool,
+    n1: f64,
+    n2: f64,
+}
+
+impl MrjStep {
+    fn new(params: &[f64]) -> Result<Self> {
+        let dt = MRJ_DT;
+        let alpha = params[0] / dt;
+        let kappa = (1.0 - params[1]) / dt;
+        let sigma = (params[3] / dt).sqrt();
+        let lambda_ = params[5] / dt;
+
+        Ok(Self {
+            drift: alpha * dt,
+            decay: kappa.mul_add(-dt, 1.0),
+            diffusion: sigma * dt.sqrt(),
+            mu_j: params[2],
+            sigma_j: params[4].sqrt(),
+            jump: Bernoulli::new(lambda_ * dt)?,
+        })
+    }
+
+    fn draw(&self, rng: &mut PricingRng) -> MrjDraw {
+        MrjDraw {
+            jump: self.jump.sample(&mut *rng),
+            n1: StandardNormal.sample(&mut *rng),
+            n2: StandardNormal.sample(&mut *rng),
+        }
+    }
+
+    // `sign` mirrors the normal shocks of an antithetic path
+    fn step(&self, price: f64, draw: &MrjDraw, sign: f64) -> f64 {
+        let jump = if draw.jump {
+            self.sigma_j.mul_add(sign * draw.n2, self.mu_j)
+        } else {
+            0.0
+        };
+        self.decay.mul_add(price, self.drift) + self.diffusion * sign * draw.n1 + jump
+    }
+}
+
+/// Simulates `num_paths` paths of the MRJ model with fitted `params`
+/// ([`a`, `phi`, `mu_j`, `sigma_sq`, `sigma_sq_j`, `lambda`]), all starting at `start`.
+///
+/// Draws are taken from `rng` in a fixed order (jumps, then both normal
+/// shocks), so the paths depend on nothing but the inputs. With `antithetic`,
+/// paths `2i` and `2i + 1` share their jumps and have opposite normal shocks.
+fn simulate_mrj_paths(
+    params: &[f64],
+    start: f64,
+    n_periods: usize,
+    num_paths: usize,
+    antithetic: bool,
+    rng: &mut PricingRng,
+) -> Result<Array2<f64>> {
+    let dt = MRJ_DT;
+    let alpha = params[0] / dt;
+    let kappa = (1.0 - params[1]) / dt;
+    let mu_j = params[2];
+    let sigma = (params[3] / dt).sqrt();
+    let sigma_j = params[4].sqrt();
+    let lambda_ = params[5] / dt;
+
+    let j: Array2<f64> = {
+        let binom = Binomial::new(lambda_ * dt, 1)?;
+        draw_paths(
+            (n_periods, num_paths),
+            antithetic,
+            |jump| jump,
+            || binom.sample(&mut *rng) as f64,
+        )
+    };
+
+    let mut simulated_prices = Array2::zeros((n_periods, num_paths));
+    simulated_prices
+        .slice_mut(s![0, ..])
+        .assign(&Array1::from_elem(num_paths, start));
+
+    let normal = Normal::new(0.0, 1.0)?;
+    let n1 = draw_paths(
+        (n_periods, num_paths),
+        antithetic,
+        |z| -z,
+        || normal.sample(&mut *rng),
+    );
+    let n2 = draw_paths(
+        (n_periods, num_paths),
+        antithetic,
+        |z| -z,
+        || normal.sample(&mut *rng),
+    );
+
+    for i in 1..n_periods {
+        let prev_prices = simulated_prices.slice(s![i - 1, ..]);
+        let current_n1 = n1.slice(s![i, ..]);
+        let current_n2 = n2.slice(s![i, ..]);
+        let current_j = j.slice(s![i, ..]);
+
+        let new_prices = &(alpha * dt
+            + kappa.mul_add(-dt, 1.0) * &prev_prices
+            + sigma * dt.sqrt() * &current_n1
+            + &current_j * (mu_j + sigma_j * &current_n2));
+
+        simulated_prices
+            .slice_mut(s![i, ..])
+            .assign(&new_prices.clone());
+    }
+
+    Ok(simulated_prices)
+}
+
+// A `(periods, paths)` matrix of draws. Antithetic matrices only draw every
+// other path and fill the one after it with the `mirror` of its draws.
+fn draw_paths(
+    (n_periods, num_paths): (usize, usize),
+    antithetic: bool,
+    mirror: impl Fn(f64) -> f64,
+    mut draw: impl FnMut() -> f64,
+) -> Array2<f64> {
+    if !antithetic {
+        return Array2::from_shape_fn((n_periods, num_paths), |_| draw());
+    }
+
+    let drawn = Array2::from_shape_fn((n_periods, num_paths.div_ceil(2)), |_| draw());
+    Array2::from_shape_fn((n_periods, num_paths), |(period, path)| {
+        let value = drawn[[period, path / 2]];
+        if path % 2 == 0 {
+            value
+        } else {
+            mirror(value)
+        }
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::pricing_data::rng::pricing_rng;
+
+    // Roughly what the fit yields on mainnet base fees
+    pub(crate) const PARAMS: [f64; 6] = [-3.928e-02, 0.98, 4.617e-02, 1e-3, 4e-2, 0.05];
+
+    // Base fees mean-reverting around 100 with a stochastic trend of comparable
+    // size, as fitted on a synthetic fee series
+    pub(crate) fn synthetic_model(n_periods: usize) -> PathModel {
+        let sigma: f64 = 0.1;
+        let dt = 1.0 / 24.0;
+        // Long-run level of the MRJ component with PARAMS
+        let mrj_level = PARAMS[2].mul_add(PARAMS[5], PARAMS[0]) / (1.0 - PARAMS[1]);
+
+        PathModel {
+            mrj_params: PARAMS.to_vec(),
+            mrj_start: mrj_level,
+            season: Array1::zeros(n_periods),
+            trend: 100f64.ln() - mrj_level,
+            trend_drift: 0.5f64.mul_add(-sigma.powi(2), 0.05 / 12.0),
+            dt,
+            shock_std: sigma * dt.sqrt(),
+        }
+    }
+
+    fn mean_and_std_error(values: &[f64]) -> (f64, f64) {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        (mean, (variance / n).sqrt())
+    }
+
+    #[test]
+    fn test_simulated_paths_are_reproducible_from_the_seed() {
+        let simulate =
+            |seed| simulate_mrj_paths(&PARAMS, 0.5, 48, 64, false, &mut pricing_rng(seed)).unwrap();
+
+        assert_eq!(simulate(42), simulate(42));
+        assert_ne!(simulate(42), simulate(43));
+    }
+
+    #[test]
+    fn test_antithetic_paths_mirror_each_other() {
+        let paths = simulate_mrj_paths(&PARAMS, 0.5, 48, 64, true, &mut pricing_rng(42)).unwrap();
+        let first_steps = paths.row(1);
+
+        // One step from the same start: the normal shocks cancel out, leaving
+        // only the shared jump
+        let expected = PARAMS[1].mul_add(0.5, PARAMS[0]);
+        for pair in first_steps.to_vec().chunks(2) {
+            let shocks = (pair[0] - expected) + (pair[1] - expected);
+            assert!(shocks.abs() < 1e-9 || (shocks - 2.0 * PARAMS[2]).abs() < 1e-9);
+        }
+        assert_ne!(paths.column(0), paths.column(1));
+    }
+
+    #[test]
+    fn test_streamed_paths_do_not_depend_on_thread_count() {
+        let model = synthetic_model(48);
+        let simulate = |threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| model.simulate(3 * CHUNK_SIZE + 10, true, 42).unwrap())
+        };
+
+        let single = simulate(1);
+        assert_eq!(single.len(), 3 * CHUNK_SIZE + 10);
+        assert_eq!(single, simulate(4));
+        assert_ne!(
+            single,
+            model.simulate(3 * CHUNK_SIZE + 10, true, 43).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_streamed_antithetic_pairs_mirror_the_stochastic_trend() {
+        let model = synthetic_model(48);
+        let paths = model.simulate(CHUNK_SIZE + 2, true, 7).unwrap();
+
+        // The shocks of a pair cancel out, leaving twice the drift
+        let drift_mean = model.trend_drift * model.dt * 49.0 / 2.0;
+        for pair in paths.trend_means.chunks(2) {
+            assert!((pair[0] + pair[1] - 2.0 * drift_mean).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_streamed_and_materialized_paths_agree() {
+        let model = synthetic_model(120);
+
+        let streamed = model.simulate(4_000, false, 42).unwrap();
+        let materialized = model
+            .simulate_materialized(4_000, false, &mut pricing_rng(42))
+            .unwrap();
+
+        for (streamed, materialized) in [
+            (&streamed.twaps, &materialized.twaps),
+            (&streamed.trend_means, &materialized.trend_means),
+        ] {
+            let (streamed_mean, streamed_error) = mean_and_std_error(streamed);
+            let (materialized_mean, materialized_error) = mean_and_std_error(materialized);
+            assert!(
+                (streamed_mean - materialized_mean).abs()
+                    < 4.0 * streamed_error.hypot(materialized_error),
+                "streamed {} vs materialized {}",
+                streamed_mean,
+                materialized_mean
+            );
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use super::config::PricingConfig;
+use super::outliers::select_base_fees;
 use super::utils::prepare_data_frame;
 use db_access::models::BlockHeader;
 use eyre::{eyre, Result};
 use polars::prelude::*;
 
-pub async fn calculate_twap(block_headers: Vec<BlockHeader>) -> Result<f64> {
-    let df = prepare_data_frame(block_headers)?;
+pub async fn calculate_twap(
+    block_headers: Vec<BlockHeader>,
+    config: &PricingConfig,
+) -> Result<f64> {
+    let df = select_base_fees(prepare_data_frame(block_headers)?, config)?;
 
     let mean = df
         .column("base_fee")?
@@ -2,6 +2,8 @@ use db_access::models::BlockHeader;
 use eyre::{anyhow as err, Result};
 use polars::prelude::*;
 
+use super::config::{GapFill, PricingConfig};
+
 /// Converts a hex string to a f64 value.
 ///
 /// # Arguments
@@ -225,26 +227,17 @@ pub fn drop_nulls(df: &DataFrame, column_name: &str) -> Result<DataFrame> {
     Ok(df)
 }
 
-/// Groups a DataFrame into 1-hour or 1-minute basefee averages.
-///
-/// For data spanning over 7 days, the data is grouped into 1-hour averages.
-/// For shorter (testnet) data ranges, the data is grouped by 1-minute averages.
+/// Picks the bucket size used when none is configured: 1 minute for data
+/// spanning less than 7 days, 1 hour otherwise.
 ///
 /// # Arguments
 ///
-/// * `df` - The input DataFrame to be grouped and aggregated
+/// * `df` - A DataFrame with a date column
 ///
 /// # Returns
 ///
-/// A `Result` containing the grouped and aggregated DataFrame, or an `Error` if the operation fails.
-///
-/// # Errors
-///
-/// Returns an error if:
-/// * The grouping or aggregation operations fail
-/// * The final collection of the lazy DataFrame fails
-pub fn group_by_1h_or_1m_intervals(df: DataFrame) -> Result<DataFrame> {
-    tracing::debug!("DataFrame shape before grouping: {:?}", df.shape());
+/// A `Result` containing the bucket size in seconds, or an `Error` if the date column is missing or empty.
+pub fn default_bucket_secs(df: &DataFrame) -> Result<u64> {
     // Calculate the total span in days
     let min_ts = df
         .column("date")?
@@ -263,17 +256,36 @@ pub fn group_by_1h_or_1m_intervals(df: DataFrame) -> Result<DataFrame> {
     tracing::debug!("DataFrame length in days: {:?}", span_days);
 
     // Decide grouping interval (Tolerance to account for block gaps)
-    let group_by = if span_days < 7.0 {
+    if span_days < 7.0 {
         tracing::info!(
             "Using 1-minute grouping (data span = {:.2} days)",
             span_days
         );
-        "1m"
+        Ok(60)
     } else {
         tracing::info!("Using 1-hour grouping (data span = {:.2} days)", span_days);
-        "1h"
-    };
-    let (every, period) = (Duration::parse(group_by), Duration::parse(group_by));
+        Ok(3600)
+    }
+}
+
+/// Groups a DataFrame into basefee averages over buckets of `bucket_secs`
+/// seconds, labelled by their start.
+///
+/// Buckets without any block are left out.
+///
+/// # Arguments
+///
+/// * `df` - The input DataFrame to be grouped and aggregated
+/// * `bucket_secs` - The bucket size in seconds
+///
+/// # Returns
+///
+/// A `Result` containing the grouped and aggregated DataFrame, or an `Error` if the operation fails.
+pub fn group_by_interval(df: DataFrame, bucket_secs: u64) -> Result<DataFrame> {
+    tracing::debug!("DataFrame shape before grouping: {:?}", df.shape());
+
+    let group_by = format!("{}s", bucket_secs);
+    let (every, period) = (Duration::parse(&group_by), Duration::parse(&group_by));
 
     let df = match df
         .lazy()
@@ -303,6 +315,141 @@ pub fn group_by_1h_or_1m_intervals(df: DataFrame) -> Result<DataFrame> {
     Ok(df)
 }
 
+/// Inserts the buckets missing between the first and the last bucket of a
+/// grouped DataFrame, so each row stands for the same span of time.
+///
+/// # Arguments
+///
+/// * `df` - A DataFrame grouped by [`group_by_interval`], sorted by date
+/// * `bucket_secs` - The bucket size it was grouped with
+/// * `gap_fill` - How the base fee of an inserted bucket is set
+///
+/// # Returns
+///
+/// A `Result` containing the DataFrame with one row per bucket, or an `Error` if the operation fails.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// * A bucket is missing and `gap_fill` is [`GapFill::Error`]
+/// * A date or base fee is null
+pub fn fill_gaps(df: DataFrame, bucket_secs: u64, gap_fill: GapFill) -> Result<DataFrame> {
+    let bucket_millis = bucket_secs as i64 * 1000;
+    let rows = df
+        .column("date")?
+        .datetime()?
+        .into_iter()
+        .zip(df.column("base_fee")?.f64()?)
+        .map(|(date, base_fee)| match (date, base_fee) {
+            (Some(date), Some(base_fee)) => Ok((date, base_fee)),
+            _ => Err(err!("Null date or base fee in grouped data")),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut dates = Vec::with_capacity(rows.len());
+    let mut base_fees = Vec::with_capacity(rows.len());
+    let mut filled = 0;
+    for (date, base_fee) in rows {
+        if let (Some(&previous_date), Some(&previous_fee)) = (dates.last(), base_fees.last()) {
+            let mut missing = previous_date + bucket_millis;
+            if missing < date && gap_fill == GapFill::Error {
+                return Err(err!(
+                    "No blocks between {} and {}",
+                    missing / 1000,
+                    date / 1000
+                ));
+            }
+            while missing < date {
+                let fee = match gap_fill {
+                    GapFill::Interpolate => {
+                        let weight =
+                            (missing - previous_date) as f64 / (date - previous_date) as f64;
+                        previous_fee + weight * (base_fee - previous_fee)
+                    }
+                    _ => previous_fee,
+                };
+                dates.push(missing);
+                base_fees.push(fee);
+                filled += 1;
+                missing += bucket_millis;
+            }
+        }
+        dates.push(date);
+        base_fees.push(base_fee);
+    }
+
+    if filled > 0 {
+        tracing::warn!(
+            "Filled {} empty {}s buckets with {:?}",
+            filled,
+            bucket_secs,
+            gap_fill
+        );
+    }
+
+    let df = DataFrame::new(vec![
+        Series::new("date".into(), dates)
+            .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?,
+        Series::new("base_fee".into(), base_fees),
+    ])?;
+
+    Ok(df)
+}
+
+/// The bucket size [`resample`] groups a DataFrame with: `config.bucket_secs`,
+/// or [`default_bucket_secs`] when unset.
+pub fn bucket_secs(df: &DataFrame, config: &PricingConfig) -> Result<u64> {
+    match config.bucket_secs {
+        Some(bucket_secs) => Ok(bucket_secs),
+        None => default_bucket_secs(df),
+    }
+}
+
+/// Number of `bucket_secs` buckets in `hours` hours.
+///
+/// # Returns
+///
+/// A `Result` containing the bucket count, or an `Error` if the buckets do not divide an hour.
+pub fn hours_to_buckets(hours: usize, bucket_secs: u64) -> Result<usize> {
+    if bucket_secs == 0 || 3600 % bucket_secs != 0 {
+        return Err(err!("Bucket size {}s does not divide an hour", bucket_secs));
+    }
+    Ok(hours * (3600 / bucket_secs) as usize)
+}
+
+/// Buckets in the `config.twap_window` hour TWAP window at `bucket_secs` per
+/// bucket.
+///
+/// Without a configured bucket size the window is `config.twap_window` buckets
+/// of the automatic size, so ranges under 7 days, bucketed by the minute, are
+/// not asked for more rows than they span.
+pub fn twap_window_buckets(config: &PricingConfig, bucket_secs: u64) -> Result<usize> {
+    match config.bucket_secs {
+        Some(_) => hours_to_buckets(config.twap_window, bucket_secs),
+        None => Ok(config.twap_window),
+    }
+}
+
+/// Groups a DataFrame into basefee averages on a fixed time grid.
+///
+/// The bucket size is [`bucket_secs`]. Buckets without any block are filled
+/// as set by `config.gap_fill`, so rolling windows over the result span
+/// wall-clock time.
+///
+/// # Arguments
+///
+/// * `df` - The input DataFrame with date and base_fee columns
+/// * `config` - The pricing config with the bucket size and fill strategy
+///
+/// # Returns
+///
+/// A `Result` containing the resampled DataFrame, or an `Error` if the operation fails.
+pub fn resample(df: DataFrame, config: &PricingConfig) -> Result<DataFrame> {
+    let bucket_secs = bucket_secs(&df, config)?;
+    let df = group_by_interval(df, bucket_secs)?;
+    fill_gaps(df, bucket_secs, config.gap_fill)
+}
+
 #[cfg(test)]
 mod tests {
     use rand::Rng;
@@ -418,7 +565,8 @@ mod tests {
         ])
         .expect("Failed to create DataFrame");
         let df = replace_timestamp_with_date(df).expect("Failed to convert timestamps");
-        let df = group_by_1h_or_1m_intervals(df).expect("Failed to group");
+        let bucket_secs = default_bucket_secs(&df).expect("Failed to pick bucket size");
+        let df = group_by_interval(df, bucket_secs).expect("Failed to group");
 
         // Check shape
         let in_minutes = (amount * step as usize) / 60;
@@ -441,10 +589,111 @@ mod tests {
         .expect("Failed to create DataFrame");
 
         let df = replace_timestamp_with_date(df).expect("Failed to convert timestamps");
-        let df = group_by_1h_or_1m_intervals(df).expect("Failed to group");
+        let bucket_secs = default_bucket_secs(&df).expect("Failed to pick bucket size");
+        let df = group_by_interval(df, bucket_secs).expect("Failed to group");
 
         // Check shape
         let in_hours = 24 * 30 * 5;
         assert_eq!(df.shape(), (in_hours, 2));
     }
+
+    // A frame with a date column of the given unix timestamps
+    fn dated_frame(timestamps: &[i64], base_fees: &[f64]) -> DataFrame {
+        let df = DataFrame::new(vec![
+            Series::new("timestamp".into(), timestamps),
+            Series::new("base_fee".into(), base_fees),
+        ])
+        .expect("Failed to create DataFrame");
+        replace_timestamp_with_date(df).expect("Failed to convert timestamps")
+    }
+
+    fn base_fees(df: &DataFrame) -> Vec<f64> {
+        df.column("base_fee")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    fn config(bucket_secs: u64, gap_fill: GapFill) -> PricingConfig {
+        PricingConfig {
+            bucket_secs: Some(bucket_secs),
+            gap_fill,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resample_forward_fills_empty_buckets() {
+        // Nothing in the second and third minute
+        let df = dated_frame(&[0, 30, 180, 240], &[10.0, 20.0, 40.0, 50.0]);
+
+        let df = resample(df, &config(60, GapFill::ForwardFill)).unwrap();
+
+        assert_eq!(base_fees(&df), vec![15.0, 15.0, 15.0, 40.0, 50.0]);
+        let dates: Vec<i64> = df
+            .column("date")
+            .unwrap()
+            .datetime()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(dates, vec![0, 60_000, 120_000, 180_000, 240_000]);
+    }
+
+    #[test]
+    fn test_resample_interpolates_empty_buckets() {
+        let df = dated_frame(&[0, 180], &[10.0, 40.0]);
+
+        let df = resample(df, &config(60, GapFill::Interpolate)).unwrap();
+
+        assert_eq!(base_fees(&df), vec![10.0, 20.0, 30.0, 40.0]);
+    }
+
+    #[test]
+    fn test_resample_errors_on_gap() {
+        let df = dated_frame(&[0, 180], &[10.0, 40.0]);
+
+        let result = resample(df, &config(60, GapFill::Error));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "No blocks between 60 and 180"
+        );
+    }
+
+    #[test]
+    fn test_resample_with_explicit_bucket_size() {
+        // Under 7 days of data, so the heuristic would pick 1 minute
+        let timestamps = create_timestamps(0, 3600 / 12, 12);
+        let timestamps: Vec<i64> = timestamps.iter().map(|t| t.parse().unwrap()).collect();
+        let df = dated_frame(&timestamps, &vec![1.0; timestamps.len()]);
+
+        let df = resample(df, &config(300, GapFill::Error)).unwrap();
+
+        assert_eq!(df.shape(), (12, 2));
+    }
+
+    #[test]
+    fn test_hours_to_buckets() {
+        assert_eq!(hours_to_buckets(720, 3600).unwrap(), 720);
+        assert_eq!(hours_to_buckets(720, 60).unwrap(), 720 * 60);
+        assert_eq!(hours_to_buckets(2, 300).unwrap(), 24);
+        assert!(hours_to_buckets(1, 7).is_err());
+        assert!(hours_to_buckets(1, 7200).is_err());
+    }
+
+    #[test]
+    fn test_twap_window_buckets() {
+        let automatic = PricingConfig::default();
+        let by_minute = PricingConfig {
+            bucket_secs: Some(60),
+            ..Default::default()
+        };
+
+        assert_eq!(twap_window_buckets(&automatic, 3600).unwrap(), 720);
+        assert_eq!(twap_window_buckets(&automatic, 60).unwrap(), 720);
+        assert_eq!(twap_window_buckets(&by_minute, 60).unwrap(), 720 * 60);
+    }
 }
@@ -0,0 +1,444 @@
+use optimization::{Func, GradientDescent, Minimizer, NumericalDifferentiation};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::calibration::nelder_mead;
+use super::config::VolatilityEstimator;
+use super::reserve_price::quantile;
+
+/// Largest gradient norm per observation at which a GARCH fit by gradient
+/// descent counts as converged. Nelder–Mead takes over above it.
+const GARCH_GRADIENT_TOLERANCE: f64 = 1e-3;
+
+/// Most GARCH optimizer iterations, for each of the optimizers.
+const GARCH_MAX_ITERATIONS: usize = 1000;
+
+/// Volatility behind a cap level, with what the estimator reports about it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct VolatilityEstimate {
+    pub volatility: f64,
+    #[serde(flatten)]
+    pub diagnostics: VolatilityDiagnostics,
+}
+
+/// Per estimator details of a volatility estimate, tagged with the estimator.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "estimator", rename_all = "snake_case")]
+pub enum VolatilityDiagnostics {
+    SampleStd {
+        /// Returns the volatility was estimated from.
+        observations: usize,
+    },
+    Ewma {
+        observations: usize,
+        lambda: f64,
+    },
+    Garch {
+        /// Non-overlapping returns the model was fitted to.
+        observations: usize,
+        omega: f64,
+        alpha: f64,
+        beta: f64,
+        /// Gaussian log-likelihood of the returns at the fitted parameters.
+        log_likelihood: f64,
+        /// `alpha + beta`, below 1 as only stationary models are fitted.
+        persistence: f64,
+        /// Whether the likelihood was maximized. When the fit did not
+        /// converge, the volatility is the sample standard deviation of the
+        /// returns instead of the forecast.
+        converged: bool,
+    },
+    Realized {
+        /// Buckets with a base fee change in the volatility window.
+        buckets: usize,
+        /// Buckets in the TWAP and in the return the volatility is of.
+        horizon: usize,
+    },
+    Bootstrap {
+        observations: usize,
+        samples: usize,
+        /// Consecutive returns drawn together, so their overlap is kept.
+        block_length: usize,
+        /// Standard deviation of the resampled volatilities.
+        std_error: f64,
+        confidence: f64,
+        /// Bounds of the `confidence` interval of the resampled volatilities.
+        lower: f64,
+        upper: f64,
+    },
+}
+
+impl VolatilityDiagnostics {
+    pub fn estimator(&self) -> VolatilityEstimator {
+        match self {
+            Self::SampleStd { .. } => VolatilityEstimator::SampleStd,
+            Self::Ewma { .. } => VolatilityEstimator::Ewma,
+            Self::Garch { .. } => VolatilityEstimator::Garch,
+            Self::Realized { .. } => VolatilityEstimator::Realized,
+            Self::Bootstrap { .. } => VolatilityEstimator::Bootstrap,
+        }
+    }
+}
+
+/// Sample standard deviation, None for fewer than two values.
+pub fn sample_std(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let sum_of_squares: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+    Some((sum_of_squares / (n - 1.0)).sqrt())
+}
+
+/// Exponentially weighted standard deviation: the last value has weight 1
+/// and each earlier one `lambda` times the weight of the next. None when
+/// empty.
+pub fn ewma_std(values: &[f64], lambda: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let weights: Vec<f64> = (0..values.len())
+        .map(|i| lambda.powi((values.len() - 1 - i) as i32))
+        .collect();
+    let total: f64 = weights.iter().sum();
+    let mean = values.iter().zip(&weights).map(|(v, w)| v * w).sum::<f64>() / total;
+    let variance = values
+        .iter()
+        .zip(&weights)
+        .map(|(v, w)| w * (v - mean).powi(2))
+        .sum::<f64>()
+        / total;
+    Some(variance.sqrt())
+}
+
+/// GARCH(1,1) parameters of demeaned returns `e`:
+/// `σ²(t) = ω + α e²(t-1) + β σ²(t-1)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GarchFit {
+    pub omega: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub log_likelihood: f64,
+    /// Standard deviation forecast for the step after the last return.
+    pub forecast: f64,
+    /// Whether gradient descent ended with a gradient norm per observation of
+    /// at most [`GARCH_GRADIENT_TOLERANCE`], or Nelder–Mead converged after
+    /// it did not.
+    pub converged: bool,
+}
+
+impl GarchFit {
+    /// `alpha + beta`, how long a shock to the variance lasts. Always below
+    /// 1, so the variance reverts to `omega / (1 - alpha - beta)`.
+    pub fn persistence(&self) -> f64 {
+        self.alpha + self.beta
+    }
+}
+
+/// Fits a GARCH(1,1) to `returns` by maximum Gaussian likelihood, starting
+/// the variance recursion at the sample variance. Gradient descent is tried
+/// first, and Nelder–Mead when it does not converge. None for fewer than
+/// three returns or constant ones.
+pub fn fit_garch(returns: &[f64]) -> Option<GarchFit> {
+    if returns.len() < 3 {
+        return None;
+    }
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let residuals: Vec<f64> = returns.iter().map(|r| r - mean).collect();
+    let variance = residuals.iter().map(|e| e * e).sum::<f64>() / n;
+    if variance <= 0.0 || !variance.is_finite() {
+        return None;
+    }
+
+    // Searched unconstrained: ω = variance × e^x0, persistence α + β =
+    // 0.999 × sigmoid(x1) and α = sigmoid(x2) of it, so ω > 0, α, β >= 0
+    // and α + β < 1 everywhere
+    let sigmoid = |x: f64| 1.0 / (1.0 + (-x).exp());
+    let params = |x: &[f64]| {
+        let persistence = 0.999 * sigmoid(x[1]);
+        let share = sigmoid(x[2]);
+        (
+            variance * x[0].exp(),
+            share * persistence,
+            (1.0 - share) * persistence,
+        )
+    };
+    let logit = |p: f64| (p / (1.0 - p)).ln();
+
+    let objective = |x: &[f64]| {
+        let (omega, alpha, beta) = params(x);
+        garch_neg_log_likelihood(&residuals, variance, omega, alpha, beta).0
+    };
+    let function = NumericalDifferentiation::new(Func(objective));
+    let minimizer = GradientDescent::new().max_iterations(Some(GARCH_MAX_ITERATIONS));
+    // α = 0.05 and β = 0.9, with ω matching the sample variance
+    let start = vec![(0.05f64).ln(), logit(0.95 / 0.999), logit(0.05 / 0.95)];
+    let solution = minimizer.minimize(&function, start.clone());
+    let descended = solution.value.is_finite().then_some(solution.position);
+
+    let (position, converged) = match descended {
+        Some(position) if gradient_norm(objective, &position) / n <= GARCH_GRADIENT_TOLERANCE => {
+            (position, true)
+        }
+        descended => {
+            let simplex = nelder_mead(objective, &start, GARCH_MAX_ITERATIONS);
+            match descended {
+                Some(position) if !simplex.converged && objective(&position) < simplex.value => {
+                    (position, false)
+                }
+                _ if simplex.value.is_finite() => (simplex.position, simplex.converged),
+                _ => (start, false),
+            }
+        }
+    };
+
+    let (omega, alpha, beta) = params(&position);
+    let (neg_log_likelihood, last_variance) =
+        garch_neg_log_likelihood(&residuals, variance, omega, alpha, beta);
+    let last = residuals[residuals.len() - 1];
+    let forecast = (omega + alpha * last * last + beta * last_variance).sqrt();
+
+    Some(GarchFit {
+        omega,
+        alpha,
+        beta,
+        log_likelihood: -neg_log_likelihood,
+        forecast,
+        converged,
+    })
+}
+
+// Norm of the central difference gradient of `f` at `x`
+fn gradient_norm(f: impl Fn(&[f64]) -> f64, x: &[f64]) -> f64 {
+    let mut point = x.to_vec();
+    let mut sum_of_squares = 0.0;
+    for i in 0..x.len() {
+        let h = 1e-6 * x[i].abs().max(1.0);
+        point[i] = x[i] + h;
+        let above = f(&point);
+        point[i] = x[i] - h;
+        let below = f(&point);
+        point[i] = x[i];
+        sum_of_squares += ((above - below) / (2.0 * h)).powi(2);
+    }
+    sum_of_squares.sqrt()
+}
+
+// Negative Gaussian log-likelihood of the residuals and the conditional
+// variance of the last one
+fn garch_neg_log_likelihood(
+    residuals: &[f64],
+    initial_variance: f64,
+    omega: f64,
+    alpha: f64,
+    beta: f64,
+) -> (f64, f64) {
+    let mut variance = initial_variance;
+    let mut neg_log_likelihood = 0.0;
+    for (t, e) in residuals.iter().enumerate() {
+        if t > 0 {
+            let previous = residuals[t - 1];
+            variance = omega + alpha * previous * previous + beta * variance;
+        }
+        neg_log_likelihood +=
+            0.5 * ((2.0 * std::f64::consts::PI).ln() + variance.ln() + e * e / variance);
+    }
+    (neg_log_likelihood, variance)
+}
+
+/// Standard deviation of the change of a TWAP over `horizon` buckets across
+/// `horizon` buckets, the return the other estimators work on, from the
+/// realized variance of each bucket and assuming independent buckets. None
+/// when empty.
+///
+/// Averaging keeps `(2 horizon² + 1) / (3 horizon)` buckets of variance out
+/// of the `horizon` the price itself moves by.
+pub fn realized_volatility(bucket_variances: &[f64], horizon: usize) -> Option<f64> {
+    if bucket_variances.is_empty() {
+        return None;
+    }
+    let mean = bucket_variances.iter().sum::<f64>() / bucket_variances.len() as f64;
+    let horizon = horizon.max(1) as f64;
+    let buckets = 2.0f64.mul_add(horizon * horizon, 1.0) / (3.0 * horizon);
+    Some((mean * buckets).sqrt())
+}
+
+/// Every `horizon`-th value, ending with the last one, so returns over
+/// `horizon` rows do not overlap.
+pub fn non_overlapping(values: &[f64], horizon: usize) -> Vec<f64> {
+    let mut picked: Vec<f64> = values
+        .iter()
+        .rev()
+        .step_by(horizon.max(1))
+        .copied()
+        .collect();
+    picked.reverse();
+    picked
+}
+
+/// Sample standard deviations of moving block bootstrap resamples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootstrapVolatility {
+    pub mean: f64,
+    pub std_error: f64,
+    pub lower: f64,
+    pub upper: f64,
+    pub block_length: usize,
+}
+
+/// Resamples `values` `samples` times by concatenating blocks of
+/// `block_length` consecutive values drawn with replacement, and summarises
+/// the sample standard deviation of each resample. None for fewer than two
+/// values.
+pub fn bootstrap_volatility(
+    values: &[f64],
+    block_length: usize,
+    samples: usize,
+    confidence: f64,
+    rng: &mut impl Rng,
+) -> Option<BootstrapVolatility> {
+    if values.len() < 2 {
+        return None;
+    }
+    // At least two blocks, so resamples differ
+    let block_length = block_length.clamp(1, (values.len() / 2).max(1));
+    let starts = values.len() - block_length + 1;
+
+    let mut volatilities: Vec<f64> = (0..samples)
+        .filter_map(|_| {
+            let mut resample = Vec::with_capacity(values.len());
+            while resample.len() < values.len() {
+                let start = rng.gen_range(0..starts);
+                resample.extend_from_slice(&values[start..start + block_length]);
+            }
+            resample.truncate(values.len());
+            sample_std(&resample)
+        })
+        .collect();
+    volatilities.sort_unstable_by(f64::total_cmp);
+
+    let tail = (1.0 - confidence) / 2.0;
+    Some(BootstrapVolatility {
+        mean: volatilities.iter().sum::<f64>() / volatilities.len() as f64,
+        std_error: sample_std(&volatilities).unwrap_or(0.0),
+        lower: quantile(&volatilities, tail),
+        upper: quantile(&volatilities, 1.0 - tail),
+        block_length,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing_data::rng::pricing_rng;
+    use rand_distr::{Distribution, StandardNormal};
+
+    fn normal_returns(n: usize, std: f64, seed: u64) -> Vec<f64> {
+        let mut rng = pricing_rng(seed);
+        (0..n)
+            .map(|_| {
+                let z: f64 = StandardNormal.sample(&mut rng);
+                std * z
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_estimate_is_tagged_with_its_estimator() {
+        let estimate = VolatilityEstimate {
+            volatility: 0.3,
+            diagnostics: VolatilityDiagnostics::Ewma {
+                observations: 10,
+                lambda: 0.94,
+            },
+        };
+
+        let json = serde_json::to_value(&estimate).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "volatility": 0.3,
+                "estimator": "ewma",
+                "observations": 10,
+                "lambda": 0.94
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<VolatilityEstimate>(json).unwrap(),
+            estimate
+        );
+        assert_eq!(estimate.diagnostics.estimator(), VolatilityEstimator::Ewma);
+    }
+
+    #[test]
+    fn test_ewma_weights_recent_values() {
+        let calm_then_wild: Vec<f64> = (0..100)
+            .map(|i| {
+                let size = if i < 80 { 0.01 } else { 0.2 };
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                size * sign
+            })
+            .collect();
+
+        let ewma = ewma_std(&calm_then_wild, 0.9).unwrap();
+        let sample = sample_std(&calm_then_wild).unwrap();
+
+        assert!(ewma > sample, "{} <= {}", ewma, sample);
+        // With every weight equal it is the population standard deviation
+        let flat = ewma_std(&[1.0, -1.0, 1.0, -1.0], 1.0).unwrap();
+        assert!((flat - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_garch_recovers_constant_volatility() {
+        let returns = normal_returns(1000, 0.05, 7);
+
+        let fit = fit_garch(&returns).unwrap();
+
+        assert!(fit.alpha >= 0.0 && fit.beta >= 0.0);
+        assert!(fit.converged && fit.persistence() < 1.0);
+        assert!(
+            (fit.forecast - 0.05).abs() < 0.01,
+            "forecast {}",
+            fit.forecast
+        );
+        assert!(fit_garch(&[0.1, 0.1, 0.1]).is_none());
+    }
+
+    #[test]
+    fn test_realized_volatility_scales_with_the_horizon() {
+        // 33 / 12 of the 4 buckets of variance are left after averaging
+        let volatility = realized_volatility(&[0.03, 0.05], 4).unwrap();
+
+        assert!((volatility - 0.11f64.sqrt()).abs() < 1e-12);
+        assert!((realized_volatility(&[0.04], 1).unwrap() - 0.2).abs() < 1e-12);
+        assert_eq!(realized_volatility(&[], 4), None);
+    }
+
+    #[test]
+    fn test_non_overlapping_ends_with_the_last_value() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+
+        assert_eq!(non_overlapping(&values, 3), vec![1.0, 4.0, 7.0]);
+        assert_eq!(non_overlapping(&values, 1), values.to_vec());
+    }
+
+    #[test]
+    fn test_bootstrap_bounds_contain_the_sample_volatility() {
+        let returns = normal_returns(500, 0.1, 3);
+        let sample = sample_std(&returns).unwrap();
+
+        let bootstrap =
+            bootstrap_volatility(&returns, 10, 500, 0.95, &mut pricing_rng(11)).unwrap();
+
+        assert!(bootstrap.lower < sample && sample < bootstrap.upper);
+        assert!((bootstrap.mean - sample).abs() < 3.0 * bootstrap.std_error);
+        assert_eq!(
+            bootstrap,
+            bootstrap_volatility(&returns, 10, 500, 0.95, &mut pricing_rng(11)).unwrap()
+        );
+    }
+}
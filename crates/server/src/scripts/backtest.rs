@@ -0,0 +1,108 @@
+use db_access::IndexerDbConnection;
+use eyre::{eyre, Result};
+use server::backtest::{run_backtest, BacktestConfig};
+use server::cli::CliArgs;
+use server::header_dump::read_headers;
+use server::header_ranges::HeaderRanges;
+use server::pricing_data::{
+    config::PricingConfigFile,
+    model::{pitch_lake_v1, PricingModelRegistry},
+};
+use starknet_crypto::Felt;
+use std::{env, fs::File, io, path::PathBuf, sync::Arc};
+use tracing::info;
+
+const USAGE: &str =
+    "Usage: backtest --vault-length <secs> [--headers <dump.json|dump.csv|dump.parquet>] \
+[--start <ts>] [--end <ts>] [--alpha <bps>] [--k <bps>] [--twap-lookback <secs>] \
+[--cap-level-lookback <secs>] [--reserve-price-lookback <secs>] [--seed <n>] \
+[--program-id <hex>] [--output <report.json|report.csv>]";
+
+const OPTIONS: &[&str] = &[
+    "headers",
+    "vault-length",
+    "start",
+    "end",
+    "alpha",
+    "k",
+    "twap-lookback",
+    "cap-level-lookback",
+    "reserve-price-lookback",
+    "seed",
+    "program-id",
+    "output",
+];
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let args = CliArgs::parse(env::args().skip(1), OPTIONS, &[], USAGE)?;
+    let vault_length: i64 = args.required("vault-length")?;
+    let program_id = match args.get("program-id") {
+        Some(program_id) => Felt::from_hex(program_id)
+            .map_err(|e| eyre!("Invalid --program-id {}: {:?}", program_id, e))?,
+        None => pitch_lake_v1(),
+    };
+
+    // Lookbacks default to the ranges a vault requests: the last round for
+    // the TWAP, five rounds for the cap level and three for the reserve price
+    let mut config = BacktestConfig {
+        vault_length,
+        alpha: args.optional("alpha")?.unwrap_or(5_000),
+        k: args.optional("k")?.unwrap_or(0),
+        start: 0,
+        end: 0,
+        twap_lookback: args.optional("twap-lookback")?.unwrap_or(vault_length),
+        cap_level_lookback: args
+            .optional("cap-level-lookback")?
+            .unwrap_or(5 * vault_length),
+        reserve_price_lookback: args
+            .optional("reserve-price-lookback")?
+            .unwrap_or(3 * vault_length),
+        seed: args.optional("seed")?.unwrap_or(0),
+        program_id,
+    };
+
+    let headers = match args.get("headers") {
+        Some(path) => {
+            let headers = HeaderRanges::from_headers(read_headers(&PathBuf::from(path))?);
+            let (first, last) = headers
+                .span()
+                .ok_or_else(|| eyre!("No block headers with a timestamp in {}", path))?;
+            config.start = args
+                .optional("start")?
+                .unwrap_or(first + config.max_lookback());
+            config.end = args.optional("end")?.unwrap_or(last);
+            headers
+        }
+        None => {
+            config.start = args.required("start")?;
+            config.end = args.required("end")?;
+            let db = IndexerDbConnection::from_env().await?;
+            HeaderRanges::fetch(Arc::new(db), [config.header_range()]).await?
+        }
+    };
+
+    let pricing_config = PricingConfigFile::from_env()?.resolve(program_id, None)?;
+    let model = PricingModelRegistry::global().model(program_id)?;
+
+    info!(
+        "Backtesting {} rounds from {} to {}",
+        config.round_starts().len(),
+        config.start,
+        config.end
+    );
+    let report = run_backtest(&headers, &config, &pricing_config, model.as_ref()).await;
+    info!("Backtest summary: {:?}", report.summary);
+
+    match args.get("output") {
+        Some(path) if path.ends_with(".csv") => report.write_csv(File::create(path)?)?,
+        Some(path) => report.write_json(File::create(path)?)?,
+        None => report.write_json(io::stdout())?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use eyre::{eyre, Result};
+use polars::prelude::DataFrame;
+use serde::Serialize;
+use server::cli::CliArgs;
+use server::header_dump::read_headers;
+use server::header_ranges::HeaderRanges;
+use server::pricing_data::{
+    cap_level::{cap_level_from_volatility, volatility_series, VolatilitySeries},
+    config::{PricingConfig, PricingConfigFile, VolatilityEstimator},
+    data_quality::DataQualityReport,
+    model::{pitch_lake_v1, PricingModelRegistry},
+    outliers::OutlierReport,
+    volatility::VolatilityEstimate,
+};
+use server::types::{PitchLakeJobRequestParams, PricingResult};
+use starknet_crypto::Felt;
+use std::{env, io, path::PathBuf};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+const USAGE: &str = "Usage: fossil-price --headers <dump.json|dump.csv|dump.parquet> \
+--alpha <bps> --k <bps> [--seed <n>] [--twap <start,end>] [--cap-level <start,end>] \
+[--reserve-price <start,end>] [--program-id <hex>] [--series] [--compare-estimators]";
+
+const OPTIONS: &[&str] = &[
+    "headers",
+    "alpha",
+    "k",
+    "seed",
+    "twap",
+    "cap-level",
+    "reserve-price",
+    "program-id",
+];
+
+/// What is printed: the pricing result as the server stores it, with
+/// `--series` the series the volatility was computed from and with
+/// `--compare-estimators` the cap level each volatility estimator gives.
+#[derive(Serialize)]
+struct Output {
+    #[serde(flatten)]
+    result: PricingResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series: Option<IntermediateSeries>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimators: Option<Vec<EstimatorCapLevel>>,
+}
+
+#[derive(Serialize)]
+struct EstimatorCapLevel {
+    #[serde(flatten)]
+    volatility: VolatilityEstimate,
+    cap_level: f64,
+}
+
+#[derive(Serialize)]
+struct IntermediateSeries {
+    twap_window: usize,
+    vol_window: usize,
+    /// Milliseconds since the epoch.
+    date: Vec<Option<i64>>,
+    base_fee: Vec<Option<f64>>,
+    twap: Vec<Option<f64>>,
+    returns: Vec<Option<f64>>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Logs go to stderr so the results can be piped
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_writer(io::stderr)
+        .init();
+
+    let args = CliArgs::parse(
+        env::args().skip(1),
+        OPTIONS,
+        &["series", "compare-estimators"],
+        USAGE,
+    )?;
+    let path: String = args.required("headers")?;
+    let headers = HeaderRanges::from_headers(read_headers(&PathBuf::from(&path))?);
+    let span = headers
+        .span()
+        .ok_or_else(|| eyre!("No block headers with a timestamp in {}", path))?;
+
+    let seed = args.optional("seed")?.unwrap_or_else(rand::random);
+
+    // Every range covers the whole file unless narrowed
+    let range = |name: &str| -> Result<(i64, i64)> {
+        args.get(name)
+            .map_or(Ok(span), |range| parse_range(name, range))
+    };
+    let params = PitchLakeJobRequestParams {
+        twap: range("twap")?,
+        cap_level: range("cap-level")?,
+        reserve_price: range("reserve-price")?,
+        alpha: args.required("alpha")?,
+        k: args.required("k")?,
+        seed: Some(seed),
+        pricing_config: None,
+        program_id: match args.get("program-id") {
+            Some(program_id) => Some(
+                Felt::from_hex(program_id)
+                    .map_err(|e| eyre!("Invalid --program-id {}: {:?}", program_id, e))?,
+            ),
+            None => None,
+        },
+    };
+    let program_id = params.program_id.unwrap_or_else(pitch_lake_v1);
+
+    let slice = |range: (i64, i64)| {
+        headers
+            .get(range)
+            .filter(|headers| !headers.is_empty())
+            .ok_or_else(|| eyre!("No block headers between {} and {}", range.0, range.1))
+    };
+    let pricing_headers = (
+        slice(params.twap)?,
+        slice(params.cap_level)?,
+        slice(params.reserve_price)?,
+    );
+
+    let config = PricingConfigFile::from_env()?.resolve(program_id, None)?;
+    let data_quality = DataQualityReport::new(&pricing_headers, &params);
+    data_quality.check(&config)?;
+
+    let outliers = OutlierReport::new(&pricing_headers, &config);
+    let series = volatility_series(pricing_headers.1.clone(), &config)?;
+
+    info!("Pricing {} with seed {}", path, seed);
+    let output = PricingModelRegistry::global()
+        .model(program_id)?
+        .price(
+            pricing_headers,
+            &params,
+            seed,
+            &config,
+            &CancellationToken::new(),
+        )
+        .await?;
+
+    let output = Output {
+        result: PricingResult {
+            twap: output.twap,
+            cap_level: output.cap_level,
+            reserve_price: output.reserve_price.reserve_price,
+            volatility: output.volatility,
+            reserve_price_diagnostics: Some(output.reserve_price.diagnostics),
+            reserve_price_sensitivities: output.reserve_price.sensitivities,
+            analytic_cross_check: output.reserve_price.analytic,
+            data_quality: Some(data_quality),
+            outliers: Some(outliers),
+            seed: Some(seed),
+            config: Some(config),
+        },
+        series: if args.switch("series") {
+            Some(IntermediateSeries::new(&series)?)
+        } else {
+            None
+        },
+        estimators: if args.switch("compare-estimators") {
+            Some(compare_estimators(&series, &params, seed, &config))
+        } else {
+            None
+        },
+    };
+    serde_json::to_writer_pretty(io::stdout(), &output)?;
+    println!();
+
+    Ok(())
+}
+
+impl IntermediateSeries {
+    fn new(series: &VolatilitySeries) -> Result<Self> {
+        let frame: &DataFrame = &series.frame;
+        let dates = frame.column("date")?.datetime()?;
+        let column = |name: &str| -> Result<Vec<Option<f64>>> {
+            Ok(frame.column(name)?.f64()?.into_iter().collect())
+        };
+
+        Ok(Self {
+            twap_window: series.twap_window,
+            vol_window: series.vol_window,
+            date: (0..frame.height()).map(|row| dates.get(row)).collect(),
+            base_fee: column("base_fee")?,
+            twap: column("TWAP_30d")?,
+            returns: column("30d_returns")?,
+        })
+    }
+}
+
+// The cap level with each volatility estimator, leaving out the ones the
+// headers are too few for
+fn compare_estimators(
+    series: &VolatilitySeries,
+    params: &PitchLakeJobRequestParams,
+    seed: u64,
+    config: &PricingConfig,
+) -> Vec<EstimatorCapLevel> {
+    VolatilityEstimator::ALL
+        .into_iter()
+        .filter_map(|estimator| {
+            let estimate = series
+                .estimate(estimator, seed, config)
+                .and_then(|volatility| {
+                    let cap_level = cap_level_from_volatility(
+                        params.alpha,
+                        params.k,
+                        volatility.volatility,
+                        config,
+                    )?;
+                    Ok(EstimatorCapLevel {
+                        volatility,
+                        cap_level,
+                    })
+                });
+            estimate
+                .inspect_err(|e| warn!("Skipping the {:?} estimator: {}", estimator, e))
+                .ok()
+        })
+        .collect()
+}
+
+// `start,end` in unix seconds
+fn parse_range(name: &str, range: &str) -> Result<(i64, i64)> {
+    let invalid = || eyre!("Invalid --{} {}: expected <start,end>", name, range);
+    let (start, end) = range.split_once(',').ok_or_else(invalid)?;
+    let start = start.trim().parse().map_err(|_| invalid())?;
+    let end = end.trim().parse().map_err(|_| invalid())?;
+    if start >= end {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
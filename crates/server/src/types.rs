@@ -1,36 +1,147 @@
-use db_access::models::JobStatus;
+use crate::pricing_data::{
+    analytic::AnalyticCrossCheck,
+    config::{PricingConfig, PricingConfigOverrides},
+    data_quality::DataQualityReport,
+    outliers::OutlierReport,
+    reserve_price::{ReservePriceDiagnostics, ReservePriceSensitivities},
+    volatility::VolatilityEstimate,
+};
+use chrono::NaiveDateTime;
+use db_access::models::{JobStatus, JobStatusChange};
 use serde::{Deserialize, Serialize};
 use starknet_crypto::Felt;
 
 // timestamp ranges for each sub-job calculation
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct PitchLakeJobRequestParams {
     pub twap: (i64, i64),
     pub cap_level: (i64, i64),
     pub reserve_price: (i64, i64),
     pub alpha: u128,
     pub k: i128,
+    /// Seeds the reserve price simulation. Derived from the job ID when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Replaces pricing model parameters for this request only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing_config: Option<PricingConfigOverrides>,
+    /// Program whose pricing model prices the request. Pitch Lake v1 when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program_id: Option<Felt>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PitchLakeJobRequest {
     pub identifiers: Vec<String>,
     pub params: PitchLakeJobRequestParams,
     pub client_info: ClientInfo, // New field
+    /// Receives a signed webhook once the job finishes. Not part of the job ID:
+    /// every submitter of the same job is notified, and the URL is neither
+    /// stored with the request nor echoed back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ClientInfo {
     pub client_address: Felt,
     pub vault_address: Felt,
     pub timestamp: i64,
 }
 
+/// Pricing values computed for a job, as stored in `job_requests.result`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PricingResult {
+    pub twap: f64,
+    pub cap_level: f64,
+    pub reserve_price: f64,
+    /// Volatility the cap level was computed from, with the estimator and its
+    /// diagnostics. Missing from mock results, from models without one and
+    /// from results stored before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volatility: Option<VolatilityEstimate>,
+    /// How noisy the simulated reserve price is. Missing from mock results and
+    /// from results stored before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserve_price_diagnostics: Option<ReservePriceDiagnostics>,
+    /// How the reserve price moves with k, the cap level, the volatility and
+    /// the risk-free rate. Only computed when `config.sensitivities` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserve_price_sensitivities: Option<ReservePriceSensitivities>,
+    /// Gap between the simulated reserve price and the closed-form one, and
+    /// whether it is beyond `config.analytic_divergence_threshold`. Missing
+    /// from mock results and from results stored before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analytic_cross_check: Option<AnalyticCrossCheck>,
+    /// Coverage, gaps and anomalies of the headers the values were computed
+    /// from. Missing from mock results and from results stored before it was
+    /// recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_quality: Option<DataQualityReport>,
+    /// Base fee outliers found in the headers, and whether they were replaced.
+    /// Missing from mock results and from results stored before it was
+    /// recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outliers: Option<OutlierReport>,
+    /// Seed the reserve price was simulated with. Recomputing with it gives
+    /// the same value. Missing from results stored before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Pricing model parameters the values were computed with. Missing from
+    /// results stored before they were configurable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<PricingConfig>,
+}
+
+/// Why a job failed.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct JobError {
+    pub message: String,
+}
+
+// The two shapes `job_requests.result` is written in
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredJobResult {
+    Error { error: String },
+    Pricing(PricingResult),
+}
+
+/// Splits a stored `job_requests.result` into its pricing values or failure
+/// reason. Values in any other shape yield neither.
+pub fn parse_job_result(result: serde_json::Value) -> (Option<PricingResult>, Option<JobError>) {
+    match serde_json::from_value(result) {
+        Ok(StoredJobResult::Pricing(result)) => (Some(result), None),
+        Ok(StoredJobResult::Error { error }) => (None, Some(JobError { message: error })),
+        Err(_) => (None, None),
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct JobResponse {
     pub job_id: String,
     pub message: Option<String>,
     pub status: Option<JobStatus>,
+    /// Computed pricing values, once the job got that far.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<PricingResult>,
+    /// Failure reason of a failed job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+    /// Hash of the Starknet callback transaction, once submitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<NaiveDateTime>,
+    /// The request the job was created from, echoed back by `/job_status`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<PitchLakeJobRequest>,
+    /// Every status the job went through, oldest first. Only filled in by
+    /// `/job_status`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<JobStatusChange>,
 }
 
 impl JobResponse {
@@ -39,10 +150,73 @@ impl JobResponse {
             job_id,
             message,
             status,
+            result: None,
+            error: None,
+            tx_hash: None,
+            created_at: None,
+            updated_at: None,
+            request: None,
+            history: Vec::new(),
         }
     }
 }
 
+/// Body of `/pricing_data/compute`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ComputePricingRequest {
+    pub params: PitchLakeJobRequestParams,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ComputePricingResponse {
+    pub result: PricingResult,
+    /// How long the computation took.
+    pub elapsed_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ComputePricingResponseEnum {
+    Success(ComputePricingResponse),
+    Error(ErrorResponse),
+}
+
+/// Response of `/pricing_data/batch`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchJobResponse {
+    /// Set when the batch as a whole was rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// One entry per submitted request, in request order.
+    pub jobs: Vec<BatchJobItem>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchJobItem {
+    /// HTTP status the request would have been answered with on its own.
+    pub code: u16,
+    #[serde(flatten)]
+    pub job: JobResponse,
+}
+
+/// Payload of a `status` event on `/job_status/{job_id}/events`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JobEventResponse {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub previous_status: Option<JobStatus>,
+    pub changed_at: NaiveDateTime,
+    /// Only set on the event that finishes the job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<PricingResult>,
+    /// Only set on the event that finishes the job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+    /// Only set on the event that finishes the job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
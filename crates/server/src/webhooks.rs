@@ -0,0 +1,753 @@
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use db_access::{
+    models::{JobStatus, WebhookDelivery, WebhookDeliveryStatus},
+    queries::{
+        create_webhook_delivery, get_job_request, get_job_webhooks, get_pending_webhook_deliveries,
+        record_webhook_attempt,
+    },
+    OffchainProcessorDbConnection,
+};
+use eyre::{eyre, Result};
+use hmac::{Hmac, Mac};
+use reqwest::{redirect, Url};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::{net::lookup_host, time::sleep};
+
+use crate::env_config::{env_millis, env_value_where};
+use crate::types::{parse_job_result, JobError, PricingResult};
+
+/// Header carrying `sha256=<hex HMAC of "{timestamp}.{body}">`.
+pub const SIGNATURE_HEADER: &str = "X-Fossil-Signature";
+/// Header carrying the unix timestamp the signature was made at.
+pub const TIMESTAMP_HEADER: &str = "X-Fossil-Timestamp";
+
+/// Longest wait between two attempts of a delivery.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// How webhooks are signed and retried.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Shared secret for the HMAC signature. Webhooks are not sent without one.
+    pub secret: Option<String>,
+    /// Attempts per delivery before it is marked `Failed`.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every further attempt.
+    pub base_delay: Duration,
+    /// Per-request timeout.
+    pub timeout: Duration,
+    /// Hosts webhooks may be sent to. When empty, any host that is not a
+    /// loopback, private or link-local address.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+            allowed_hosts: Vec::new(),
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Reads `WEBHOOK_SECRET`, `WEBHOOK_MAX_ATTEMPTS`, `WEBHOOK_RETRY_BASE_MS`
+    /// and the comma separated `WEBHOOK_ALLOWED_HOSTS`, falling back to the
+    /// defaults when unset and warning about unparsable values or zero
+    /// attempts.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        Self {
+            secret: env::var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty()),
+            max_attempts: env_value_where("WEBHOOK_MAX_ATTEMPTS", default.max_attempts, |&n| n > 0),
+            base_delay: env_millis("WEBHOOK_RETRY_BASE_MS", default.base_delay),
+            allowed_hosts: env::var("WEBHOOK_ALLOWED_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|host| host.trim().to_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+            ..default
+        }
+    }
+}
+
+/// Body POSTed to every `callback_url` subscribed to a job once it finishes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub job_id: String,
+    pub status: JobStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<PricingResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+}
+
+/// Signature of `body` sent at `timestamp`, as put in [`SIGNATURE_HEADER`].
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// `true` if webhooks may be sent to `url`: plain HTTP(S) to one of
+/// `allowed_hosts` or, without an allow-list, to a host that is not a
+/// loopback, private or link-local address.
+pub fn is_webhook_url(url: &str, allowed_hosts: &[String]) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    if !allowed_hosts.is_empty() {
+        return allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host));
+    }
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => host != "localhost" && !host.ends_with(".localhost"),
+    }
+}
+
+// `false` for addresses that reach the server itself or its private network
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !is_internal_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => !is_internal_ipv4(ip),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+fn is_internal_ipv4(ip: Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (first == 100 && (second & 0xc0) == 64)
+}
+
+// Refuses to send to a host that is not allowed, including a public name that
+// resolves to an internal address. Allow-listed hosts are trusted as they are.
+//
+// Returns a client that connects to the addresses checked here only, so the
+// name cannot be resolved again to somewhere else before the request is sent.
+async fn destination_client(url: &str, allowed_hosts: &[String]) -> Result<reqwest::Client> {
+    if !is_webhook_url(url, allowed_hosts) {
+        return Err(eyre!("{} is not an allowed webhook destination", url));
+    }
+
+    // Redirects could lead past the destination check
+    let client = reqwest::Client::builder().redirect(redirect::Policy::none());
+    let url = Url::parse(url)?;
+    let client = match url.domain() {
+        Some(domain) if allowed_hosts.is_empty() => {
+            let port = url.port_or_known_default().unwrap_or(80);
+            let addresses: Vec<SocketAddr> = lookup_host((domain, port)).await?.collect();
+            if let Some(address) = addresses.iter().find(|address| !is_public_ip(address.ip())) {
+                return Err(eyre!("{} resolves to internal address {}", domain, address));
+            }
+            if addresses.is_empty() {
+                return Err(eyre!("{} does not resolve to any address", domain));
+            }
+            client.resolve_to_addrs(domain, &addresses)
+        }
+        _ => client,
+    };
+    Ok(client.build()?)
+}
+
+/// Logs a webhook with the job's current state for every URL subscribed to
+/// the job. Returns nothing when the job is gone or has no subscribers.
+pub(crate) async fn create_job_webhooks(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+) -> Result<Vec<WebhookDelivery>> {
+    let urls = get_job_webhooks(db.clone(), job_id).await?;
+    create_webhooks(db, job_id, &urls).await
+}
+
+// Logs a webhook with the job's current state for each of `urls`
+async fn create_webhooks(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: &str,
+    urls: &[String],
+) -> Result<Vec<WebhookDelivery>> {
+    if urls.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(job) = get_job_request(db.clone(), job_id).await? else {
+        return Ok(Vec::new());
+    };
+
+    let (result, error) = job.result.map(parse_job_result).unwrap_or_default();
+    let payload = serde_json::to_value(WebhookPayload {
+        job_id: job.job_id,
+        status: job.status,
+        result,
+        error,
+        tx_hash: job.callback_tx_hash,
+    })?;
+
+    let mut deliveries = Vec::with_capacity(urls.len());
+    for url in urls {
+        deliveries.push(create_webhook_delivery(db.clone(), job_id, url, &payload).await?);
+    }
+    Ok(deliveries)
+}
+
+/// Sends a logged webhook, retrying with exponential backoff until the
+/// receiver answers with a 2xx or the attempts run out. A delivery interrupted
+/// by a restart continues from its last attempt.
+///
+/// Without `config.secret` the delivery fails right away, as unsigned webhooks
+/// are never sent.
+pub(crate) async fn deliver_webhook(
+    db: Arc<OffchainProcessorDbConnection>,
+    delivery: &WebhookDelivery,
+    config: &WebhookConfig,
+) -> Result<WebhookDeliveryStatus> {
+    let Some(secret) = config.secret.as_deref() else {
+        let error = "WEBHOOK_SECRET is not set, refusing to send unsigned webhooks";
+        record_webhook_attempt(
+            db,
+            delivery.id,
+            WebhookDeliveryStatus::Failed,
+            None,
+            Some(error),
+        )
+        .await?;
+        tracing::error!(
+            "Webhook {} for job {} failed: {}",
+            delivery.id,
+            delivery.job_id,
+            error
+        );
+        return Ok(WebhookDeliveryStatus::Failed);
+    };
+    let body = serde_json::to_vec(&delivery.payload)?;
+
+    let first_attempt = u32::try_from(delivery.attempts).unwrap_or_default() + 1;
+    let last_attempt = config.max_attempts.max(first_attempt);
+
+    for attempt in first_attempt..=last_attempt {
+        let timestamp = Utc::now().timestamp();
+        let response = match destination_client(&delivery.url, &config.allowed_hosts).await {
+            Ok(client) => client
+                .post(&delivery.url)
+                .timeout(config.timeout)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(SIGNATURE_HEADER, sign(secret, timestamp, &body))
+                .body(body.clone())
+                .send()
+                .await
+                .map_err(|e| format!("Request failed: {}", e)),
+            Err(e) => Err(format!("Refused to send: {}", e)),
+        };
+
+        let (status_code, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                record_webhook_attempt(
+                    db.clone(),
+                    delivery.id,
+                    WebhookDeliveryStatus::Delivered,
+                    Some(i32::from(response.status().as_u16())),
+                    None,
+                )
+                .await?;
+                tracing::info!(
+                    "Webhook {} for job {} delivered on attempt {}",
+                    delivery.id,
+                    delivery.job_id,
+                    attempt
+                );
+                return Ok(WebhookDeliveryStatus::Delivered);
+            }
+            Ok(response) => (
+                Some(i32::from(response.status().as_u16())),
+                format!("Receiver answered {}", response.status()),
+            ),
+            Err(error) => (None, error),
+        };
+
+        let exhausted = attempt == last_attempt;
+        let status = if exhausted {
+            WebhookDeliveryStatus::Failed
+        } else {
+            WebhookDeliveryStatus::Pending
+        };
+        record_webhook_attempt(db.clone(), delivery.id, status, status_code, Some(&error)).await?;
+        tracing::warn!(
+            "Webhook {} for job {} attempt {}/{} failed: {}",
+            delivery.id,
+            delivery.job_id,
+            attempt,
+            last_attempt,
+            error
+        );
+
+        if !exhausted {
+            sleep(retry_delay(config.base_delay, attempt)).await;
+        }
+    }
+
+    Ok(WebhookDeliveryStatus::Failed)
+}
+
+// Wait after the `attempt`th failed attempt: `base_delay` doubled per earlier
+// attempt, up to `MAX_RETRY_DELAY`
+fn retry_delay(base_delay: Duration, attempt: u32) -> Duration {
+    base_delay
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Logs and sends the job's webhook to every subscriber in the background.
+pub(crate) fn spawn_job_webhooks(db: Arc<OffchainProcessorDbConnection>, job_id: String) {
+    tokio::spawn(async move {
+        match create_job_webhooks(db.clone(), &job_id).await {
+            Ok(deliveries) => {
+                for delivery in deliveries {
+                    spawn_webhook_delivery(db.clone(), delivery);
+                }
+            }
+            Err(e) => tracing::error!("Failed to log webhooks for job {}: {:?}", job_id, e),
+        }
+    });
+}
+
+/// Logs and sends the job's webhook to a single subscriber in the background,
+/// e.g. one that subscribed after the job finished.
+pub(crate) fn spawn_subscriber_webhook(
+    db: Arc<OffchainProcessorDbConnection>,
+    job_id: String,
+    url: String,
+) {
+    tokio::spawn(async move {
+        match create_webhooks(db.clone(), &job_id, &[url]).await {
+            Ok(deliveries) => {
+                for delivery in deliveries {
+                    send_in_background(db.clone(), delivery).await;
+                }
+            }
+            Err(e) => tracing::error!("Failed to log webhook for job {}: {:?}", job_id, e),
+        }
+    });
+}
+
+/// Sends the deliveries a previous run left `Pending` in the background.
+/// Returns how many were resumed.
+pub(crate) async fn resume_pending_webhooks(
+    db: Arc<OffchainProcessorDbConnection>,
+) -> Result<usize> {
+    let pending = get_pending_webhook_deliveries(db.clone()).await?;
+    let resumed = pending.len();
+    for delivery in pending {
+        spawn_webhook_delivery(db.clone(), delivery);
+    }
+    Ok(resumed)
+}
+
+/// Sends an already logged webhook in the background.
+pub(crate) fn spawn_webhook_delivery(
+    db: Arc<OffchainProcessorDbConnection>,
+    delivery: WebhookDelivery,
+) {
+    tokio::spawn(send_in_background(db, delivery));
+}
+
+async fn send_in_background(db: Arc<OffchainProcessorDbConnection>, delivery: WebhookDelivery) {
+    let config = WebhookConfig::from_env();
+    if let Err(e) = deliver_webhook(db, &delivery, &config).await {
+        tracing::error!(
+            "Failed to send webhook {} for job {}: {:?}",
+            delivery.id,
+            delivery.job_id,
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::fixtures::TestContext;
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use db_access::queries::{create_queued_job_request, get_webhook_deliveries};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::mpsc;
+
+    const SECRET: &str = "test-secret";
+
+    #[derive(Clone)]
+    struct Receiver {
+        failures_left: Arc<AtomicUsize>,
+        received: mpsc::UnboundedSender<(HeaderMap, String)>,
+    }
+
+    async fn receive(
+        State(receiver): State<Receiver>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        let failing = receiver
+            .failures_left
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        receiver.received.send((headers, body)).unwrap();
+        if failing {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    // Local stand-in for a keeper's webhook endpoint that fails `failures` times
+    async fn start_receiver(
+        failures: usize,
+    ) -> (String, mpsc::UnboundedReceiver<(HeaderMap, String)>) {
+        let (received, rx) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(Receiver {
+                failures_left: Arc::new(AtomicUsize::new(failures)),
+                received,
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, rx)
+    }
+
+    fn config() -> WebhookConfig {
+        WebhookConfig {
+            secret: Some(SECRET.to_string()),
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+            ..WebhookConfig::default()
+        }
+    }
+
+    async fn create_finished_job(ctx: &TestContext, callback_url: Option<String>) {
+        let db = ctx.offchain_processor_db.clone();
+        create_queued_job_request(
+            db.clone(),
+            "webhook_job",
+            JobStatus::Queued,
+            &json!({
+                "identifiers": ["test-id"],
+                "params": {
+                    "twap": [0, 100],
+                    "cap_level": [0, 100],
+                    "reserve_price": [0, 100],
+                    "alpha": 2500,
+                    "k": 0
+                },
+                "client_info": {
+                    "client_address": "0x123",
+                    "vault_address": "0x456",
+                    "timestamp": 0
+                }
+            }),
+            callback_url.as_deref(),
+        )
+        .await
+        .unwrap();
+        db_access::queries::update_job_status(
+            db,
+            "webhook_job",
+            JobStatus::Failed,
+            Some(json!({ "error": "No block headers in range" })),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_signature_covers_timestamp_and_body() {
+        let signature = sign(SECRET, 1_741_243_059, b"{}");
+
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature, sign(SECRET, 1_741_243_059, b"{}"));
+        assert_ne!(signature, sign(SECRET, 1_741_243_060, b"{}"));
+        assert_ne!(signature, sign(SECRET, 1_741_243_059, b"[]"));
+        assert_ne!(signature, sign("other-secret", 1_741_243_059, b"{}"));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_is_signed_and_logged() {
+        let ctx = TestContext::new().await;
+        let (url, mut received) = start_receiver(0).await;
+        create_finished_job(&ctx, Some(url)).await;
+
+        let delivery = create_job_webhooks(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap()
+            .pop()
+            .expect("job has a callback_url");
+        let status = deliver_webhook(ctx.offchain_processor_db.clone(), &delivery, &config())
+            .await
+            .unwrap();
+        assert_eq!(status, WebhookDeliveryStatus::Delivered);
+
+        let (headers, body) = received.recv().await.unwrap();
+        let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign(SECRET, timestamp, body.as_bytes())
+        );
+
+        let payload: WebhookPayload = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload.job_id, "webhook_job");
+        assert_eq!(payload.status, JobStatus::Failed);
+        assert_eq!(payload.error.unwrap().message, "No block headers in range");
+
+        let deliveries = get_webhook_deliveries(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].attempts, 1);
+        assert!(deliveries[0].delivered_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retries_until_delivered() {
+        let ctx = TestContext::new().await;
+        let (url, _received) = start_receiver(2).await;
+        create_finished_job(&ctx, Some(url)).await;
+
+        let delivery = create_job_webhooks(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let status = deliver_webhook(ctx.offchain_processor_db.clone(), &delivery, &config())
+            .await
+            .unwrap();
+        assert_eq!(status, WebhookDeliveryStatus::Delivered);
+
+        let deliveries = get_webhook_deliveries(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap();
+        assert_eq!(deliveries[0].status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(deliveries[0].attempts, 3);
+        assert_eq!(deliveries[0].last_status_code, Some(200));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_gives_up_after_max_attempts() {
+        let ctx = TestContext::new().await;
+        let (url, _received) = start_receiver(usize::MAX).await;
+        create_finished_job(&ctx, Some(url)).await;
+
+        let delivery = create_job_webhooks(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let status = deliver_webhook(ctx.offchain_processor_db.clone(), &delivery, &config())
+            .await
+            .unwrap();
+        assert_eq!(status, WebhookDeliveryStatus::Failed);
+
+        let deliveries = get_webhook_deliveries(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap();
+        assert_eq!(deliveries[0].attempts, 3);
+        assert_eq!(deliveries[0].last_status_code, Some(500));
+    }
+
+    #[tokio::test]
+    async fn test_no_webhook_without_callback_url() {
+        let ctx = TestContext::new().await;
+        create_finished_job(&ctx, None).await;
+
+        let deliveries = create_job_webhooks(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap();
+
+        assert!(deliveries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_delivery_resumes_from_its_last_attempt() {
+        let ctx = TestContext::new().await;
+        let (url, _received) = start_receiver(usize::MAX).await;
+        create_finished_job(&ctx, Some(url)).await;
+        let db = ctx.offchain_processor_db.clone();
+
+        let delivery = create_job_webhooks(db.clone(), "webhook_job")
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        record_webhook_attempt(
+            db.clone(),
+            delivery.id,
+            WebhookDeliveryStatus::Pending,
+            Some(500),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let pending = get_pending_webhook_deliveries(db.clone()).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        let status = deliver_webhook(db.clone(), &pending[0], &config())
+            .await
+            .unwrap();
+        assert_eq!(status, WebhookDeliveryStatus::Failed);
+
+        let deliveries = get_webhook_deliveries(db.clone(), "webhook_job")
+            .await
+            .unwrap();
+        assert_eq!(deliveries[0].attempts, 3);
+        assert!(get_pending_webhook_deliveries(db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_to_internal_host_is_refused() {
+        let ctx = TestContext::new().await;
+        let (url, mut received) = start_receiver(0).await;
+        create_finished_job(&ctx, Some(url)).await;
+
+        let delivery = create_job_webhooks(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let status = deliver_webhook(
+            ctx.offchain_processor_db.clone(),
+            &delivery,
+            &WebhookConfig {
+                allowed_hosts: Vec::new(),
+                ..config()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, WebhookDeliveryStatus::Failed);
+        assert!(received.try_recv().is_err());
+        let deliveries = get_webhook_deliveries(ctx.offchain_processor_db.clone(), "webhook_job")
+            .await
+            .unwrap();
+        assert!(deliveries[0]
+            .last_error
+            .as_deref()
+            .unwrap()
+            .starts_with("Refused to send"));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_without_secret_fails() {
+        let ctx = TestContext::new().await;
+        let (url, mut received) = start_receiver(0).await;
+        create_finished_job(&ctx, Some(url)).await;
+        let db = ctx.offchain_processor_db.clone();
+
+        let delivery = create_job_webhooks(db.clone(), "webhook_job")
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let status = deliver_webhook(
+            db.clone(),
+            &delivery,
+            &WebhookConfig {
+                secret: None,
+                ..config()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(status, WebhookDeliveryStatus::Failed);
+        assert!(received.try_recv().is_err());
+        let deliveries = get_webhook_deliveries(db.clone(), "webhook_job")
+            .await
+            .unwrap();
+        assert!(deliveries[0]
+            .last_error
+            .as_deref()
+            .unwrap()
+            .contains("WEBHOOK_SECRET"));
+        // Nothing is left for the next start to resume
+        assert!(get_pending_webhook_deliveries(db).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_only_public_or_allowed_hosts_are_webhook_urls() {
+        let none: &[String] = &[];
+        assert!(is_webhook_url("https://keeper.example/hook", none));
+        assert!(is_webhook_url("http://8.8.8.8/hook", none));
+        for url in [
+            "ftp://keeper.example/hook",
+            "not a url",
+            "http://localhost:8080/hook",
+            "http://api.localhost/hook",
+            "http://127.0.0.1/hook",
+            "http://10.0.0.5/hook",
+            "http://192.168.1.1/hook",
+            "http://172.16.0.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+        ] {
+            assert!(!is_webhook_url(url, none), "{}", url);
+        }
+
+        let allowed = ["127.0.0.1".to_string()];
+        assert!(is_webhook_url("http://127.0.0.1:9000/hook", &allowed));
+        assert!(!is_webhook_url("https://keeper.example/hook", &allowed));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_the_cap() {
+        let base = Duration::from_secs(1);
+
+        assert_eq!(retry_delay(base, 1), base);
+        assert_eq!(retry_delay(base, 4), Duration::from_secs(8));
+        assert_eq!(retry_delay(base, 20), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(base, 40), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(base, u32::MAX), MAX_RETRY_DELAY);
+    }
+}
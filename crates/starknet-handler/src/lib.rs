@@ -5,9 +5,16 @@ use dotenv::dotenv;
 use eyre::{eyre, Result};
 use resilience::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig};
 use starknet::{
-    accounts::{Account, ExecutionEncoding, SingleOwnerAccount},
-    core::{chain_id, types::Call, types::U256, utils::get_selector_from_name},
-    providers::{jsonrpc::HttpTransport, JsonRpcClient, Url},
+    accounts::{Account, ConnectedAccount, ExecutionEncoding, SingleOwnerAccount},
+    core::{
+        chain_id,
+        types::{
+            Call, ExecutionResult, StarknetError, TransactionExecutionStatus, TransactionStatus,
+            U256,
+        },
+        utils::get_selector_from_name,
+    },
+    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider, ProviderError, Url},
     signers::{LocalWallet, SigningKey},
 };
 use starknet_crypto::Felt;
@@ -32,6 +39,22 @@ pub struct PitchLakeResult {
     pub reserve_price: U256,
 }
 
+/// Where a submitted transaction stands on the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxStatus {
+    /// Received but not yet accepted on L2.
+    Pending,
+    /// Unknown to the node: not propagated yet, or dropped before it was
+    /// included in a block.
+    NotFound,
+    /// Accepted on L2 (or already on L1) and executed successfully.
+    Accepted,
+    /// Accepted but execution reverted.
+    Reverted { reason: String },
+    /// Rejected by the sequencer and never included in a block.
+    Rejected,
+}
+
 #[derive(Debug)]
 pub struct FossilStarknetAccount {
     pub account: SingleOwnerAccount<JsonRpcClient<HttpTransport>, LocalWallet>,
@@ -197,6 +220,44 @@ impl FossilStarknetAccount {
         }))
     }
 
+    /// Looks up the status of a transaction sent by [`Self::callback_to_contract`].
+    pub async fn tx_status(&self, tx_hash: Felt) -> Result<TxStatus> {
+        let provider = self.account.provider();
+
+        let status = match provider.get_transaction_status(tx_hash).await {
+            Ok(status) => status,
+            // The node may not have seen a freshly sent transaction yet, so
+            // it's up to the caller how long to wait for it to show up
+            Err(ProviderError::StarknetError(StarknetError::TransactionHashNotFound)) => {
+                return Ok(TxStatus::NotFound)
+            }
+            Err(e) => return Err(eyre!("Failed to get transaction status: {}", e)),
+        };
+
+        match status {
+            TransactionStatus::Received => Ok(TxStatus::Pending),
+            TransactionStatus::Rejected => Ok(TxStatus::Rejected),
+            TransactionStatus::AcceptedOnL2(TransactionExecutionStatus::Succeeded)
+            | TransactionStatus::AcceptedOnL1(TransactionExecutionStatus::Succeeded) => {
+                Ok(TxStatus::Accepted)
+            }
+            TransactionStatus::AcceptedOnL2(TransactionExecutionStatus::Reverted)
+            | TransactionStatus::AcceptedOnL1(TransactionExecutionStatus::Reverted) => {
+                // Only the receipt carries the revert reason
+                let receipt = provider
+                    .get_transaction_receipt(tx_hash)
+                    .await
+                    .map_err(|e| eyre!("Failed to get transaction receipt: {}", e))?;
+
+                let reason = match receipt.receipt.execution_result() {
+                    ExecutionResult::Reverted { reason } => reason.clone(),
+                    ExecutionResult::Succeeded => "unknown revert reason".to_string(),
+                };
+                Ok(TxStatus::Reverted { reason })
+            }
+        }
+    }
+
     // Add a method to manually reset the circuit breaker
     pub async fn reset_circuit_breaker(&self) {
         self.circuit_breaker.reset().await;